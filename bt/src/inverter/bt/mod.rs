@@ -1,14 +1,118 @@
-use crate::inverter::InverterData;
+use crate::inverter::parameters::{InverterParameter, ParameterRequest, ParameterWriter};
+use crate::inverter::transport::{
+    AdapterStatus, ConnectionMonitor, ConnectionState, GattTransport,
+};
+use crate::inverter::{
+    transport::short_id, CharacteristicGroup, CharacteristicRead, InverterData, InverterSnapshot,
+    CHAR_UUID_0X2A03, CHAR_UUID_0X2A04, CHAR_UUID_0X2A0C, CHAR_UUID_0X2A0D, CHAR_UUID_0X2A11,
+    SERVICE_UUID_0X1810,
+};
+use crate::schedule::PollingSchedule;
 use bluer::agent::{AgentHandle, ReqResult, RequestPasskey};
-use bluer::{agent::Agent, Adapter, AdapterEvent, Address};
-use chrono::{Local, NaiveTime};
+use bluer::gatt::remote::Characteristic;
+use bluer::{agent::Agent, Adapter, AdapterEvent, Address, Device};
+use chrono::{DateTime, Local, Utc};
+use futures::future::BoxFuture;
 use futures::prelude::*;
 use futures::{pin_mut, StreamExt};
 use influxdb2::models::DataPoint;
 use influxdb2::Client;
+use rand::Rng;
 use std::cell::RefCell;
-use std::time::Duration;
-use tokio::time::sleep;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Notify};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
+
+/// Maximum number of parameter changes waiting to be written.
+const PARAMETER_QUEUE_SIZE: usize = 8;
+
+/// Time the adapter is kept powered off when power cycling it.
+const ADAPTER_RESET_DELAY: Duration = Duration::from_secs(2);
+
+/// Exponential backoff with jitter applied between failed polls.
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    min: Duration,
+    max: Duration,
+}
+
+impl Backoff {
+    pub fn new(min: Duration, max: Duration) -> Self {
+        Backoff {
+            min,
+            max: max.max(min),
+        }
+    }
+
+    /// Delay before the next attempt after `failures` consecutive failures.
+    /// The exponential delay is randomized within its upper half to spread retries.
+    pub fn delay(&self, failures: u32) -> Duration {
+        let exponent = failures.saturating_sub(1).min(16);
+        let delay = self.min.saturating_mul(1 << exponent).min(self.max);
+        let half = delay / 2;
+        half + delay.mul_f64(rand::thread_rng().gen_range(0.0..=0.5))
+    }
+}
+
+/// How often the characteristic groups that rarely change are read again.
+/// Live readings are read on every poll.
+#[derive(Debug, Clone, Copy)]
+pub struct RefreshIntervals {
+    pub product_info: Duration,
+    pub ratings: Duration,
+    pub parameters: Duration,
+}
+
+impl RefreshIntervals {
+    fn interval(&self, group: CharacteristicGroup) -> Duration {
+        match group {
+            CharacteristicGroup::ProductInfo => self.product_info,
+            CharacteristicGroup::Ratings => self.ratings,
+            CharacteristicGroup::Parameters => self.parameters,
+            CharacteristicGroup::Live => Duration::ZERO,
+        }
+    }
+}
+
+impl Default for RefreshIntervals {
+    fn default() -> Self {
+        RefreshIntervals {
+            product_info: Duration::from_secs(24 * 3600),
+            ratings: Duration::from_secs(24 * 3600),
+            parameters: Duration::from_secs(3600),
+        }
+    }
+}
+
+/// Cloneable handle used to request a full refresh of a running `BTInterface`,
+/// polled right away instead of waiting for the next period.
+#[derive(Clone)]
+pub struct RefreshTrigger {
+    requested: Arc<AtomicBool>,
+    notify: Arc<Notify>,
+}
+
+impl RefreshTrigger {
+    fn new() -> Self {
+        RefreshTrigger {
+            requested: Arc::new(AtomicBool::new(false)),
+            notify: Arc::new(Notify::new()),
+        }
+    }
+
+    pub fn request(&self) {
+        self.requested.store(true, Ordering::Relaxed);
+        self.notify.notify_one();
+    }
+
+    fn take(&self) -> bool {
+        self.requested.swap(false, Ordering::Relaxed)
+    }
+}
 
 #[derive(Clone)]
 pub struct InfluxData {
@@ -38,122 +142,258 @@ impl InfluxData {
 }
 
 pub struct BTInterface {
-    target_device: Address,
+    device: String,
+    transport: Box<dyn GattTransport>,
+    monitor: ConnectionMonitor,
     data: RefCell<InverterData>,
+    reads: RefCell<BTreeMap<String, CharacteristicRead>>,
+    refresh_intervals: RefreshIntervals,
+    last_refresh: RefCell<HashMap<CharacteristicGroup, Instant>>,
+    refresh: RefreshTrigger,
     influx_data: InfluxData,
-    listener: Option<Box<dyn Fn(InverterData) + Send + Sync>>,
+    listener: Option<Box<dyn Fn(InverterSnapshot) + Send + Sync>>,
+    parameter_sender: mpsc::Sender<ParameterRequest>,
+    parameter_receiver: RefCell<Option<mpsc::Receiver<ParameterRequest>>>,
 }
 
 unsafe impl Sync for BTInterface {}
 
 impl BTInterface {
     #[allow(dead_code)]
-    pub fn new(target_device: Address, influx_data: InfluxData) -> Self {
+    pub fn new(device: String, transport: Box<dyn GattTransport>, influx_data: InfluxData) -> Self {
+        let (parameter_sender, parameter_receiver) = mpsc::channel(PARAMETER_QUEUE_SIZE);
         BTInterface {
-            target_device,
+            device,
+            transport,
+            monitor: ConnectionMonitor::new(),
             data: RefCell::new(InverterData::new()),
+            reads: RefCell::new(BTreeMap::new()),
+            refresh_intervals: RefreshIntervals::default(),
+            last_refresh: RefCell::new(HashMap::new()),
+            refresh: RefreshTrigger::new(),
             influx_data,
             listener: None,
+            parameter_sender,
+            parameter_receiver: RefCell::new(Some(parameter_receiver)),
         }
     }
 
+    pub fn with_refresh_intervals(mut self, refresh_intervals: RefreshIntervals) -> Self {
+        self.refresh_intervals = refresh_intervals;
+        self
+    }
+
+    /// Handle to request a full refresh while the service is running.
+    pub fn refresh_trigger(&self) -> RefreshTrigger {
+        self.refresh.clone()
+    }
+
+    /// Handle to follow the connection state while the service is running.
+    pub fn connection_monitor(&self) -> ConnectionMonitor {
+        self.monitor.clone()
+    }
+
+    /// Handle to queue inverter parameter changes while the service is running.
+    pub fn parameter_writer(&self) -> ParameterWriter {
+        ParameterWriter::new(self.parameter_sender.clone())
+    }
+
     /// Start the main loop to scan for the target device, query its data, and save it to InfluxDB.
-    /// The loop will run indefinitely, with a configurable period for querying the device.
-    pub async fn serve(&self, period: u64, night_period: u64) {
-        loop {
-            let _ = self.scan_and_query_once().await.map_err(|e| {
-                println!("Error: {} {}", e.kind.to_string(), e.message);
-            });
-            self.save_to_db().await;
+    /// The loop will run indefinitely, querying the device with the period given by the schedule
+    /// and an exponential backoff between failed attempts.
+    pub async fn serve(&self, schedule: PollingSchedule, backoff: Backoff) {
+        let mut parameter_requests = self
+            .parameter_receiver
+            .borrow_mut()
+            .take()
+            .expect("BTInterface is already being served");
 
-            if let Some(data) = self.get_data() {
-                self.emit(data);
+        loop {
+            // Changes queued while polling are written before connecting again
+            while let Ok(request) = parameter_requests.try_recv() {
+                self.handle_parameter_request(request).await;
             }
 
-            let low = NaiveTime::from_hms_opt(7, 15, 0).unwrap();
-            let high = NaiveTime::from_hms_opt(23, 15, 0).unwrap();
-            let time_of_day = Local::now().time();
-            let mut current_period: u64 = night_period;
-            if (time_of_day > low) && (time_of_day < high) {
-                current_period = period;
+            let poll_started = Utc::now();
+            let delay = match self.scan_and_query_once().await {
+                Ok(()) => {
+                    self.monitor.set_success();
+
+                    // Only values read during this poll reach the sinks
+                    let snapshot = self.get_snapshot(poll_started);
+                    self.save_to_db(&snapshot).await;
+                    self.emit(snapshot);
+
+                    schedule.period_at(Local::now())
+                }
+                Err(e) => {
+                    let failures = self.monitor.status().consecutive_failures + 1;
+                    let delay = backoff.delay(failures);
+                    let next_attempt = Utc::now()
+                        + chrono::Duration::from_std(delay).unwrap_or(chrono::Duration::zero());
+                    self.monitor.set_failure(e.to_string(), next_attempt);
+                    println!(
+                        "Error [{}]: {} {} (attempt {}, retrying in {}s)",
+                        self.device,
+                        e.kind,
+                        e.message,
+                        failures,
+                        delay.as_secs()
+                    );
+
+                    delay
+                }
+            };
+
+            if delay.is_zero() {
+                continue;
             }
 
-            if current_period > 0 {
-                sleep(Duration::from_secs(current_period)).await;
+            // Parameter changes are written as soon as they arrive, then the loop polls again
+            tokio::select! {
+                _ = sleep(delay) => {}
+                _ = self.refresh.notify.notified() => {}
+                Some(request) = parameter_requests.recv() => {
+                    self.handle_parameter_request(request).await;
+                }
             }
         }
     }
 
-    /// Connect a listener callback to receive emitted inverter data.
+    /// Write a queued parameter change and reply with the result.
+    async fn handle_parameter_request(&self, request: ParameterRequest) {
+        let result = self.write_parameter(request.parameter).await;
+        if let Err(e) = &result {
+            println!(
+                "Error writing parameter {:?} [{}]: {}",
+                request.parameter, self.device, e
+            );
+        }
+        let _ = request.reply.send(result);
+    }
+
+    /// Connect a listener callback to receive emitted inverter snapshots.
     pub fn connect<F>(&mut self, listener: F)
     where
-        F: Fn(InverterData) + Send + Sync + 'static,
+        F: Fn(InverterSnapshot) + Send + Sync + 'static,
     {
         self.listener = Some(Box::new(listener));
     }
 
-    /// Emit the inverter data to the registered listener, if any.
-    fn emit(&self, data: InverterData) {
+    /// Emit the inverter snapshot to the registered listener, if any.
+    fn emit(&self, snapshot: InverterSnapshot) {
         if let Some(listener) = &self.listener {
-            listener(data);
+            listener(snapshot);
         }
     }
 
-    /// Save the inverter data to InfluxDB.
-    async fn save_to_db(&self) {
-        let points = self.get_data_points();
+    /// Save the fresh values of the inverter snapshot to InfluxDB.
+    async fn save_to_db(&self, snapshot: &InverterSnapshot) {
+        let mut points = BTInterface::get_data_points(&self.device, snapshot);
+        points.extend(self.get_link_point(snapshot));
+        if points.is_empty() {
+            return;
+        }
+
         let client = &self.influx_data.create_client();
         let result = client
             .write(&self.influx_data.bucket, stream::iter(points))
             .await;
         if let Err(e) = result {
-            println!("Influxdb client error: {}", e.to_string());
+            println!("Influxdb client error: {}", e);
         }
     }
 
-    /// Get the data points for InfluxDB from the inverter data.
-    fn get_data_points(&self) -> Vec<DataPoint> {
-        let point_battery = DataPoint::builder("battery")
+    /// Get the link quality measured during the poll of this snapshot as an InfluxDB point.
+    fn get_link_point(&self, snapshot: &InverterSnapshot) -> Option<DataPoint> {
+        let link = self.monitor.status().link.last_poll;
+        let mut point = DataPoint::builder("link")
             .tag("host", "inverter")
-            .field(
-                "capacity",
-                self.data.borrow().clone().battery_capacity as f64,
-            )
-            .field("voltage", self.data.borrow().clone().battery_voltage as f64)
-            .field(
-                "charge",
-                self.data.borrow().clone().battery_charge_current as f64,
-            )
-            .field(
-                "discharge",
-                self.data.borrow().clone().battery_current_discharge as f64,
-            )
-            .build()
-            .unwrap();
-
-        let point_inverter = DataPoint::builder("inverter")
+            .tag("device", self.device.as_str())
+            .timestamp(snapshot.read_at.timestamp_nanos_opt().unwrap_or_default());
+        let mut has_fields = false;
+        for (field, value) in [
+            ("rssi", link.rssi.map(|rssi| rssi as f64)),
+            ("discovery_ms", link.discovery_ms),
+            ("connect_ms", link.connect_ms),
+            ("read_ms", link.read_ms),
+        ] {
+            if let Some(value) = value {
+                point = point.field(field, value);
+                has_fields = true;
+            }
+        }
+        has_fields.then(|| point.build().ok()).flatten()
+    }
+
+    /// Get the data points for InfluxDB from the characteristics read in this snapshot,
+    /// tagged with the inverter they come from.
+    fn get_data_points(device: &str, snapshot: &InverterSnapshot) -> Vec<DataPoint> {
+        let data = &snapshot.data;
+        let timestamp = snapshot.read_at.timestamp_nanos_opt().unwrap_or_default();
+        let fresh_0x2a03 = snapshot.is_fresh(&CHAR_UUID_0X2A03);
+        let fresh_0x2a04 = snapshot.is_fresh(&CHAR_UUID_0X2A04);
+        // Settings are refreshed less often than the live readings, tag with the last known ones
+        let known_0x2a0c = snapshot.has_value(&CHAR_UUID_0X2A0C);
+        let known_0x2a0d = snapshot.has_value(&CHAR_UUID_0X2A0D);
+        let fresh_0x2a11 = snapshot.is_fresh(&CHAR_UUID_0X2A11);
+
+        let mut point_battery = DataPoint::builder("battery")
             .tag("host", "inverter")
-            .field(
-                "pv1_power",
-                self.data.borrow().clone().pv_input_power_stage1 as f64,
-            )
-            .field(
-                "pv1_voltage",
-                self.data.borrow().clone().pv_input_voltage_stage1 as f64,
-            )
-            .field(
-                "output_voltage",
-                self.data.borrow().clone().output_voltage as f64,
-            )
-            .field(
-                "output_power",
-                self.data.borrow().clone().output_active_power as f64,
-            )
-            .field("load", self.data.borrow().clone().load_percentage as f64)
-            .build()
-            .unwrap();
-
-        vec![point_battery, point_inverter]
+            .tag("device", device)
+            .timestamp(timestamp);
+        if fresh_0x2a04 {
+            point_battery = point_battery
+                .field("capacity", data.live.battery_capacity as f64)
+                .field("discharge", data.live.battery_current_discharge as f64);
+        }
+        if fresh_0x2a03 {
+            point_battery = point_battery
+                .field("voltage", data.live.battery_voltage as f64)
+                .field("charge", data.live.battery_charge_current as f64);
+        }
+
+        let mut point_inverter = DataPoint::builder("inverter")
+            .tag("host", "inverter")
+            .tag("device", device)
+            .timestamp(timestamp);
+        if fresh_0x2a04 {
+            point_inverter = point_inverter.tag("workmode", data.live.workmode.name());
+        }
+        if known_0x2a0c {
+            point_inverter = point_inverter
+                .tag(
+                    "output_source_priority",
+                    data.parameters.p_output_source_priotrity.name(),
+                )
+                .tag(
+                    "charger_source_priority",
+                    data.parameters.p_charger_source_priority.name(),
+                )
+                .tag("battery_type", data.parameters.p_battery_type.name());
+        }
+        if known_0x2a0d {
+            point_inverter = point_inverter
+                .tag("output_mode", data.parameters.output_mode.name())
+                .tag("charge_mode", data.parameters.charge_mode.name());
+        }
+        if fresh_0x2a11 {
+            point_inverter = point_inverter
+                .field("pv1_power", data.live.pv_input_power_stage1 as f64)
+                .field("pv1_voltage", data.live.pv_input_voltage_stage1 as f64);
+        }
+        if fresh_0x2a03 {
+            point_inverter = point_inverter
+                .field("output_voltage", data.live.output_voltage as f64)
+                .field("output_power", data.live.output_active_power as f64)
+                .field("load", data.live.load_percentage as f64);
+        }
+
+        // Points without fresh fields are skipped
+        [point_battery.build(), point_inverter.build()]
+            .into_iter()
+            .filter_map(|point| point.ok())
+            .collect()
     }
 
     #[allow(dead_code)]
@@ -164,6 +404,271 @@ impl BTInterface {
         }
     }
 
+    /// Snapshot of the current data and the read status of every characteristic.
+    pub fn get_snapshot(&self, read_at: DateTime<Utc>) -> InverterSnapshot {
+        InverterSnapshot {
+            data: self.data.borrow().clone(),
+            read_at,
+            characteristics: self.reads.borrow().clone(),
+        }
+    }
+
+    /// Connect through the transport and query the inverter data once.
+    async fn scan_and_query_once(&self) -> bluer::Result<()> {
+        self.monitor.start_poll();
+        self.transport.connect(&self.monitor).await?;
+        self.monitor.set_state(ConnectionState::Reading);
+        if let Some(rssi) = self.transport.rssi().await {
+            self.monitor.record_rssi(rssi);
+        }
+        let force: &[CharacteristicGroup] = if self.refresh.take() {
+            &CharacteristicGroup::ALL
+        } else {
+            &[]
+        };
+        self.query_device(force).await
+    }
+
+    /// Validate, write and confirm a single inverter parameter change.
+    pub async fn write_parameter(&self, parameter: InverterParameter) -> Result<(), String> {
+        self.data.borrow().validate_parameter(&parameter)?;
+
+        let uuid = parameter.characteristic();
+        self.transport
+            .connect(&self.monitor)
+            .await
+            .map_err(|e| e.to_string())?;
+        self.monitor.set_state(ConnectionState::Reading);
+        let mut value = self
+            .transport
+            .read(uuid)
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Characteristic {} not found", uuid))?;
+        parameter.encode(&mut value)?;
+        self.transport
+            .write(uuid, &value)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        // Read back to confirm the inverter accepted the change
+        self.query_device(&[CharacteristicGroup::Parameters])
+            .await
+            .map_err(|e| e.to_string())?;
+
+        if !self.data.borrow().has_parameter(&parameter) {
+            return Err(format!("Inverter did not apply {:?}", parameter));
+        }
+
+        println!("Parameter written [{}]: {:?}", self.device, parameter);
+        Ok(())
+    }
+
+    /// Query the connected inverter for the groups due for a refresh, and the `force`d ones.
+    /// A group is only considered refreshed once all its characteristics were read.
+    async fn query_device(&self, force: &[CharacteristicGroup]) -> bluer::Result<()> {
+        let groups: Vec<CharacteristicGroup> =
+            CharacteristicGroup::ALL
+                .into_iter()
+                .filter(|group| {
+                    force.contains(group)
+                        || self.last_refresh.borrow().get(group).is_none_or(|at| {
+                            at.elapsed() >= self.refresh_intervals.interval(*group)
+                        })
+                })
+                .collect();
+
+        let mut data = self.data.borrow().clone();
+        let mut reads = self.reads.borrow().clone();
+        let started = Utc::now();
+        let result = data
+            .read_characteristics(self.transport.as_ref(), &groups, &mut reads)
+            .await;
+
+        for uuid in groups.iter().flat_map(|group| group.characteristics()) {
+            if let Some(read) = reads.get(&short_id(&uuid)) {
+                if read.read_at >= started {
+                    self.monitor
+                        .record_read(&uuid, Duration::from_secs_f64(read.latency_ms / 1000.0));
+                }
+            }
+        }
+
+        let now = Instant::now();
+        for group in &groups {
+            if group
+                .characteristics()
+                .all(|uuid| reads.get(&short_id(&uuid)).is_none_or(|read| read.success))
+            {
+                self.last_refresh.borrow_mut().insert(*group, now);
+            }
+        }
+
+        *self.data.borrow_mut() = data;
+        *self.reads.borrow_mut() = reads;
+        result?;
+
+        // self.data.borrow().print_inverter_info();
+        // self.data.borrow().print_battery_info();
+        // self.data.borrow().print_basic_info();
+        // self.data.borrow().print_parameters();
+        // self.data.borrow().print_json().unwrap_or_default();
+
+        Ok(())
+    }
+}
+
+/// Live BlueZ connection to the inverter with its resolved characteristics.
+struct BluerConnection {
+    _session: bluer::Session,
+    _agent: Option<AgentHandle>,
+    device: Device,
+    characteristics: HashMap<uuid::Uuid, Characteristic>,
+    parameter_characteristics: HashMap<uuid::Uuid, Characteristic>,
+    notifications: Vec<JoinHandle<()>>,
+}
+
+impl Drop for BluerConnection {
+    fn drop(&mut self) {
+        for notification in &self.notifications {
+            notification.abort();
+        }
+    }
+}
+
+/// Find the adapter with the given name (e.g. `hci1`) or address, or the default one.
+pub async fn select_adapter(
+    session: &bluer::Session,
+    selector: Option<&str>,
+) -> bluer::Result<Adapter> {
+    let Some(selector) = selector else {
+        return session.default_adapter().await;
+    };
+
+    let not_found = || bluer::Error {
+        kind: bluer::ErrorKind::NotFound,
+        message: format!("Bluetooth adapter {} not found", selector),
+    };
+    let names = session.adapter_names().await?;
+    match selector.parse::<Address>() {
+        Ok(address) => {
+            for name in names {
+                let adapter = session.adapter(&name)?;
+                if adapter.address().await? == address {
+                    return Ok(adapter);
+                }
+            }
+            Err(not_found())
+        }
+        Err(_) if names.iter().any(|name| name == selector) => session.adapter(selector),
+        Err(_) => Err(not_found()),
+    }
+}
+
+/// GATT transport backed by BlueZ through `bluer`.
+///
+/// In persistent mode the device stays connected between polls, characteristics are resolved
+/// once and notify-capable characteristics are subscribed, so discovery only runs again after
+/// the inverter disconnects.
+pub struct BluerTransport {
+    target_device: Address,
+    persistent: bool,
+    discovery_timeout: Duration,
+    connect_timeout: Duration,
+    connection: Mutex<Option<BluerConnection>>,
+    notified_values: Arc<Mutex<HashMap<uuid::Uuid, Vec<u8>>>>,
+    adapter: Option<String>,
+    reset_after_failures: u32,
+    adapter_status: Mutex<Option<AdapterStatus>>,
+    /// Signal strength seen while discovering, until reported by the next poll.
+    discovery_rssi: Mutex<Option<i16>>,
+}
+
+impl BluerTransport {
+    pub fn new(
+        target_device: Address,
+        persistent: bool,
+        discovery_timeout: Duration,
+        connect_timeout: Duration,
+    ) -> Self {
+        BluerTransport {
+            target_device,
+            persistent,
+            discovery_timeout,
+            connect_timeout,
+            connection: Mutex::new(None),
+            notified_values: Arc::new(Mutex::new(HashMap::new())),
+            adapter: None,
+            reset_after_failures: 0,
+            adapter_status: Mutex::new(None),
+            discovery_rssi: Mutex::new(None),
+        }
+    }
+
+    /// Use the adapter with the given name (e.g. `hci1`) or address instead of the default one.
+    pub fn with_adapter(mut self, adapter: Option<String>) -> Self {
+        self.adapter = adapter;
+        self
+    }
+
+    /// Power cycle the adapter every `failures` consecutive failed polls, 0 to never do it.
+    pub fn with_adapter_reset(mut self, failures: u32) -> Self {
+        self.reset_after_failures = failures;
+        self
+    }
+
+    /// Find the configured adapter, or the default one.
+    async fn select_adapter(&self, session: &bluer::Session) -> bluer::Result<Adapter> {
+        select_adapter(session, self.adapter.as_deref()).await
+    }
+
+    /// Update the reported adapter state from the adapter itself.
+    async fn update_adapter_status(&self, adapter: &Adapter, monitor: &ConnectionMonitor) {
+        let mut adapter_status = self.adapter_status.lock().unwrap().clone();
+        let status = adapter_status.get_or_insert_with(|| AdapterStatus {
+            name: adapter.name().to_owned(),
+            address: None,
+            powered: false,
+            resets: 0,
+            last_reset: None,
+        });
+        status.name = adapter.name().to_owned();
+        status.address = adapter
+            .address()
+            .await
+            .ok()
+            .map(|address| address.to_string());
+        status.powered = adapter.is_powered().await.unwrap_or(false);
+
+        monitor.set_adapter(status.clone());
+        *self.adapter_status.lock().unwrap() = adapter_status;
+    }
+
+    /// Power the adapter off and on again, a wedged adapter often recovers this way.
+    async fn reset_adapter(&self, monitor: &ConnectionMonitor) {
+        let result = async {
+            let session = bluer::Session::new().await?;
+            let adapter = self.select_adapter(&session).await?;
+            println!("Power cycling Bluetooth adapter {}", adapter.name());
+            adapter.set_powered(false).await?;
+            sleep(ADAPTER_RESET_DELAY).await;
+            adapter.set_powered(true).await?;
+            bluer::Result::Ok(adapter)
+        }
+        .await;
+
+        match result {
+            Ok(adapter) => {
+                if let Some(status) = self.adapter_status.lock().unwrap().as_mut() {
+                    status.resets += 1;
+                    status.last_reset = Some(Utc::now());
+                }
+                self.update_adapter_status(&adapter, monitor).await;
+            }
+            Err(e) => println!("Unable to power cycle the Bluetooth adapter: {}", e),
+        }
+    }
+
     /// Request the passkey for pairing with the inverter device.
     pub async fn request_passkey(_req: RequestPasskey) -> ReqResult<u32> {
         println!("Requesting passkey...");
@@ -173,67 +678,97 @@ impl BTInterface {
         Ok(device_pin_code)
     }
 
-    /// Scan for the target device and query its data once.
-    async fn scan_and_query_once(&self) -> bluer::Result<()> {
+    /// Whether the cached connection is still usable.
+    async fn is_connected(&self) -> bool {
+        let device = match self.connection.lock().unwrap().as_ref() {
+            Some(connection) => connection.device.clone(),
+            None => return false,
+        };
+
+        device.is_connected().await.unwrap_or(false)
+    }
+
+    /// Scan for the target device, pair if required and connect to it.
+    async fn discover_and_connect(
+        &self,
+        monitor: &ConnectionMonitor,
+    ) -> bluer::Result<BluerConnection> {
+        monitor.set_state(ConnectionState::Scanning);
         let session = bluer::Session::new().await?;
 
         // Register custom agent to handle the authentication
         let agent = Agent {
             request_default: true,
-            request_passkey: Some(Box::new(|req| Box::pin(BTInterface::request_passkey(req)))),
+            request_passkey: Some(Box::new(|req| {
+                Box::pin(BluerTransport::request_passkey(req))
+            })),
             ..Default::default()
         };
 
-        #[allow(unused_variables)]
-        let agent_handle: AgentHandle;
-        if let Ok(handle) = session.register_agent(agent).await {
-            agent_handle = handle;
-            println!("Custom agent registered: {:?}", agent_handle);
-        } else {
-            println!("Error: Custom agent could not be registered.");
-        }
+        let agent_handle = match session.register_agent(agent).await {
+            Ok(handle) => {
+                println!("Custom agent registered: {:?}", handle);
+                Some(handle)
+            }
+            Err(_) => {
+                println!("Error: Custom agent could not be registered.");
+                None
+            }
+        };
 
-        let adapter = session.default_adapter().await?;
+        let adapter = self.select_adapter(&session).await?;
         println!(
             "Discovering devices using Bluetooth adapter {}",
             adapter.name()
         );
         adapter.set_powered(true).await?;
+        self.update_adapter_status(&adapter, monitor).await;
 
+        let discovery_started = Instant::now();
         let device_events = adapter.discover_devices().await?;
         pin_mut!(device_events);
 
-        loop {
-            tokio::select! {
-                Some(device_event) = device_events.next() => {
-                    match device_event {
-                        AdapterEvent::DeviceAdded(addr) => {
-                            if addr == self.target_device {
-                                // println!("Inverter device found: {}", addr);
-                                let res = self.query_device(&adapter, addr).await;
-                                if let Err(err) = res {
-                                    println!("    Error: {}", &err);
-                                }
-                                break;
-                            }
-                        }
-                        AdapterEvent::DeviceRemoved(addr) => {
-                            println!("Device removed: {}", addr);
-                        }
-                        _ => (),
+        let discovery = async {
+            while let Some(device_event) = device_events.next().await {
+                match device_event {
+                    AdapterEvent::DeviceAdded(addr) if addr == self.target_device => {
+                        // println!("Inverter device found: {}", addr);
+                        return true;
+                    }
+                    AdapterEvent::DeviceRemoved(addr) => {
+                        println!("Device removed: {}", addr);
                     }
-                    println!();
+                    _ => (),
                 }
-                else => break
+            }
+            false
+        };
+
+        match timeout(self.discovery_timeout, discovery).await {
+            Ok(true) => {}
+            Ok(false) => {
+                return Err(bluer::Error {
+                    kind: bluer::ErrorKind::NotFound,
+                    message: format!("Discovery ended without finding {}", self.target_device),
+                });
+            }
+            Err(_) => {
+                return Err(bluer::Error {
+                    kind: bluer::ErrorKind::Failed,
+                    message: format!(
+                        "Device {} not found after {}s",
+                        self.target_device,
+                        self.discovery_timeout.as_secs()
+                    ),
+                });
             }
         }
 
-        Ok(())
-    }
+        monitor.record_discovery(discovery_started.elapsed());
 
-    /// Query the target device for its data.
-    async fn query_device(&self, adapter: &Adapter, addr: Address) -> bluer::Result<()> {
-        let device = adapter.device(addr)?;
+        monitor.set_state(ConnectionState::Connecting);
+        let device = adapter.device(self.target_device)?;
+        *self.discovery_rssi.lock().unwrap() = device.rssi().await.ok().flatten();
         device.set_trusted(true).await?;
 
         // println!("    Address type:       {}", device.address_type().await?);
@@ -242,33 +777,234 @@ impl BTInterface {
         // println!("    Connected:          {:?}", device.is_connected().await?);
         // println!("    Trusted:            {:?}", device.is_trusted().await?);
 
-        match device.is_paired().await {
-            Ok(is_paired) => {
-                if !is_paired {
-                    // println!("Trying to pair device...");
-                    device.pair().await?;
-                    device.connect().await?;
-                } else {
-                    // println!("Connecting...");
-                    device.connect().await?;
+        let pair_and_connect = async {
+            match device.is_paired().await {
+                Ok(is_paired) => {
+                    if !is_paired {
+                        // println!("Trying to pair device...");
+                        device.pair().await?;
+                        device.connect().await?;
+                    } else {
+                        // println!("Connecting...");
+                        device.connect().await?;
+                    }
                 }
-            }
-            Err(err) => {
-                println!("    Error: {}", &err);
-            }
+                Err(err) => {
+                    println!("    Error: {}", &err);
+                }
+            };
+            bluer::Result::Ok(())
         };
 
-        for service in device.services().await? {
-            self.data.borrow_mut().read_characteristics(service).await?;
-        }
+        let connect_started = Instant::now();
+        timeout(self.connect_timeout, pair_and_connect)
+            .await
+            .map_err(|_| bluer::Error {
+                kind: bluer::ErrorKind::ConnectionAttemptFailed,
+                message: format!(
+                    "Connection to {} timed out after {}s",
+                    self.target_device,
+                    self.connect_timeout.as_secs()
+                ),
+            })??;
+        monitor.record_connect(connect_started.elapsed());
+
+        let mut connection = BluerConnection {
+            _session: session,
+            _agent: agent_handle,
+            device,
+            characteristics: HashMap::new(),
+            parameter_characteristics: HashMap::new(),
+            notifications: Vec::new(),
+        };
+        self.resolve_characteristics(&mut connection).await?;
 
-        // self.data.print_inverter_info();
-        // self.data.print_battery_info();
-        // self.data.print_basic_info();
-        // self.data.print_parameters();
-        // println!("{:?}", self.data);
-        // self.data.borrow().print_json().unwrap_or_default();
+        Ok(connection)
+    }
+
+    /// Cache the device characteristics and subscribe to the notify-capable ones.
+    async fn resolve_characteristics(&self, connection: &mut BluerConnection) -> bluer::Result<()> {
+        self.notified_values.lock().unwrap().clear();
+
+        for service in connection.device.services().await? {
+            let service_uuid = service.uuid().await?;
+
+            for char in service.characteristics().await? {
+                let uuid = char.uuid().await?;
+                let flags = char.flags().await?;
+
+                if service_uuid == SERVICE_UUID_0X1810
+                    && (flags.write || flags.write_without_response)
+                {
+                    connection
+                        .parameter_characteristics
+                        .insert(uuid, char.clone());
+                }
+
+                if !flags.read || connection.characteristics.contains_key(&uuid) {
+                    continue;
+                }
+
+                if self.persistent && (flags.notify || flags.indicate) {
+                    match char.notify().await {
+                        Ok(notify) => {
+                            let notified_values = self.notified_values.clone();
+                            connection.notifications.push(tokio::spawn(async move {
+                                pin_mut!(notify);
+                                while let Some(value) = notify.next().await {
+                                    notified_values.lock().unwrap().insert(uuid, value);
+                                }
+                                // Subscription ended, the cached value is no longer current
+                                notified_values.lock().unwrap().remove(&uuid);
+                            }));
+                        }
+                        Err(err) => println!("Unable to subscribe to {}: {}", uuid, err),
+                    }
+                }
+
+                connection.characteristics.insert(uuid, char);
+            }
+        }
 
         Ok(())
     }
+
+    fn not_connected_error(&self) -> bluer::Error {
+        bluer::Error {
+            kind: bluer::ErrorKind::NotReady,
+            message: format!("Device {} is not connected", self.target_device),
+        }
+    }
+}
+
+impl GattTransport for BluerTransport {
+    fn connect<'a>(&'a self, monitor: &'a ConnectionMonitor) -> BoxFuture<'a, bluer::Result<()>> {
+        Box::pin(async move {
+            if self.persistent && self.is_connected().await {
+                return Ok(());
+            }
+
+            // Drop the previous connection (and its subscriptions) before discovering again
+            self.connection.lock().unwrap().take();
+            monitor.set_state(ConnectionState::Disconnected);
+
+            let failures = monitor.status().consecutive_failures;
+            if self.reset_after_failures > 0
+                && failures > 0
+                && failures.is_multiple_of(self.reset_after_failures)
+            {
+                self.reset_adapter(monitor).await;
+            }
+
+            let connection = self.discover_and_connect(monitor).await?;
+            *self.connection.lock().unwrap() = Some(connection);
+            Ok(())
+        })
+    }
+
+    fn read(&self, uuid: uuid::Uuid) -> BoxFuture<'_, bluer::Result<Option<Vec<u8>>>> {
+        Box::pin(async move {
+            if let Some(value) = self.notified_values.lock().unwrap().get(&uuid) {
+                return Ok(Some(value.clone()));
+            }
+
+            let char = match self.connection.lock().unwrap().as_ref() {
+                Some(connection) => connection.characteristics.get(&uuid).cloned(),
+                None => return Err(self.not_connected_error()),
+            };
+
+            match char {
+                Some(char) => Ok(Some(char.read().await?)),
+                None => Ok(None),
+            }
+        })
+    }
+
+    fn write<'a>(&'a self, uuid: uuid::Uuid, value: &'a [u8]) -> BoxFuture<'a, bluer::Result<()>> {
+        Box::pin(async move {
+            let char = match self.connection.lock().unwrap().as_ref() {
+                Some(connection) => connection.parameter_characteristics.get(&uuid).cloned(),
+                None => return Err(self.not_connected_error()),
+            };
+
+            let Some(char) = char else {
+                return Err(bluer::Error {
+                    kind: bluer::ErrorKind::NotFound,
+                    message: format!(
+                        "Writable characteristic {} not found on service {}",
+                        uuid, SERVICE_UUID_0X1810
+                    ),
+                });
+            };
+
+            char.write(value).await?;
+
+            // Force the next read to query the inverter instead of a notified value
+            self.notified_values.lock().unwrap().remove(&uuid);
+            Ok(())
+        })
+    }
+
+    fn rssi(&self) -> BoxFuture<'_, Option<i16>> {
+        Box::pin(async move {
+            let device = self
+                .connection
+                .lock()
+                .unwrap()
+                .as_ref()
+                .map(|connection| connection.device.clone());
+            // BlueZ only reports the RSSI while discovering, fall back to the discovery value
+            let rssi = match device {
+                Some(device) => device.rssi().await.ok().flatten(),
+                None => None,
+            };
+            let discovery_rssi = self.discovery_rssi.lock().unwrap().take();
+            rssi.or(discovery_rssi)
+        })
+    }
+
+    fn name(&self) -> String {
+        let mode = if self.persistent {
+            "persistent"
+        } else {
+            "rescan per poll"
+        };
+        format!("bluetooth {} ({})", self.target_device, mode)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inverter::transport::FixtureTransport;
+
+    fn fixture_interface() -> BTInterface {
+        let transport = FixtureTransport::from_file("fixtures/inverter.json").unwrap();
+        let influx_data = InfluxData::new(
+            "http://127.0.0.1:1".to_owned(),
+            "test".to_owned(),
+            "test".to_owned(),
+            "test".to_owned(),
+        );
+        BTInterface::new("fixture".to_owned(), Box::new(transport), influx_data)
+    }
+
+    #[tokio::test]
+    async fn fixture_poll_is_decoded_into_the_snapshot() {
+        let interface = fixture_interface();
+        interface.scan_and_query_once().await.unwrap();
+
+        let snapshot = interface.get_snapshot(Utc::now());
+        assert_eq!(snapshot.characteristics.len(), 14);
+        assert!(snapshot.characteristics.values().all(|read| read.success));
+
+        let json = serde_json::to_value(&snapshot).unwrap();
+        assert_eq!(json["blt_version"], "00001.02");
+        assert_eq!(json["battery_capacity"], 85);
+        assert_eq!(json["battery_charge_current"], 12);
+        assert_eq!(json["charge_mode"], "AUTO");
+        assert!((json["battery_voltage"].as_f64().unwrap() - 52.4).abs() < 0.01);
+        assert!((json["ac_voltage"].as_f64().unwrap() - 230.1).abs() < 0.01);
+        assert_eq!(json["ac_frequency"], 50.0);
+    }
 }
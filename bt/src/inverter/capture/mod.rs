@@ -0,0 +1,374 @@
+use crate::inverter::transport::{short_id, uuid_from_short, ConnectionMonitor, GattTransport};
+use crate::inverter::{CharacteristicGroup, InverterData};
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// A single raw characteristic read, stored as one JSON line of a capture file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureRecord {
+    pub timestamp: DateTime<Utc>,
+    pub device: String,
+    /// Poll the read belongs to, increased on every connection request.
+    pub poll: u64,
+    /// Short characteristic id (e.g. `2a03`).
+    pub characteristic: String,
+    /// Raw value in hex, `None` if the inverter does not expose the characteristic.
+    pub value: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Transport wrapper that records every characteristic read into a JSON lines file.
+pub struct CaptureTransport {
+    inner: Box<dyn GattTransport>,
+    device: String,
+    path: String,
+    file: Mutex<File>,
+    poll: AtomicU64,
+}
+
+impl CaptureTransport {
+    /// Append the reads of `device` to the capture at `path`, numbering the polls after the
+    /// last one already recorded for it so a capture can span several runs.
+    pub fn new(inner: Box<dyn GattTransport>, device: &str, path: &str) -> Result<Self, String> {
+        let last_poll = CaptureTransport::last_poll(path, device);
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("Unable to open capture file {}: {}", path, e))?;
+
+        Ok(CaptureTransport {
+            inner,
+            device: device.to_owned(),
+            path: path.to_owned(),
+            file: Mutex::new(file),
+            poll: AtomicU64::new(last_poll),
+        })
+    }
+
+    /// Highest poll number recorded for `device`, 0 for a new capture.
+    fn last_poll(path: &str, device: &str) -> u64 {
+        let Ok(file) = File::open(path) else {
+            return 0;
+        };
+        BufReader::new(file)
+            .lines()
+            .map_while(|line| line.ok())
+            .filter_map(|line| serde_json::from_str::<CaptureRecord>(&line).ok())
+            .filter(|record| record.device == device)
+            .map(|record| record.poll)
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn record(&self, uuid: Uuid, result: &bluer::Result<Option<Vec<u8>>>) {
+        let (value, error) = match result {
+            Ok(value) => (value.as_ref().map(hex::encode), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        let record = CaptureRecord {
+            timestamp: Utc::now(),
+            device: self.device.clone(),
+            poll: self.poll.load(Ordering::Relaxed),
+            characteristic: short_id(&uuid),
+            value,
+            error,
+        };
+
+        let line = match serde_json::to_string(&record) {
+            Ok(line) => line + "\n",
+            Err(e) => {
+                println!("Unable to serialize capture record: {}", e);
+                return;
+            }
+        };
+        // A single write per record, the other inverters append to the same file
+        if let Err(e) = self.file.lock().unwrap().write_all(line.as_bytes()) {
+            println!("Unable to write capture file {}: {}", self.path, e);
+        }
+    }
+}
+
+impl GattTransport for CaptureTransport {
+    fn connect<'a>(&'a self, monitor: &'a ConnectionMonitor) -> BoxFuture<'a, bluer::Result<()>> {
+        self.poll.fetch_add(1, Ordering::Relaxed);
+        self.inner.connect(monitor)
+    }
+
+    fn read(&self, uuid: Uuid) -> BoxFuture<'_, bluer::Result<Option<Vec<u8>>>> {
+        Box::pin(async move {
+            let result = self.inner.read(uuid).await;
+            self.record(uuid, &result);
+            result
+        })
+    }
+
+    fn write<'a>(&'a self, uuid: Uuid, value: &'a [u8]) -> BoxFuture<'a, bluer::Result<()>> {
+        self.inner.write(uuid, value)
+    }
+
+    fn rssi(&self) -> BoxFuture<'_, Option<i16>> {
+        self.inner.rssi()
+    }
+
+    fn name(&self) -> String {
+        format!("{} (capturing to {})", self.inner.name(), self.path)
+    }
+}
+
+/// Values read during a single recorded poll.
+struct CapturedPoll {
+    poll: u64,
+    timestamp: DateTime<Utc>,
+    values: HashMap<Uuid, Result<Vec<u8>, String>>,
+}
+
+/// Transport that plays a capture back, one recorded poll per connection request.
+pub struct ReplayTransport {
+    name: String,
+    polls: Vec<CapturedPoll>,
+    current: Mutex<Option<usize>>,
+    repeat: bool,
+}
+
+impl ReplayTransport {
+    /// Load the polls of `device` from a capture file, or those of the first recorded device
+    /// when none is given. With `repeat` the capture starts over when it ends.
+    pub fn from_file(path: &str, device: Option<&str>, repeat: bool) -> Result<Self, String> {
+        let file =
+            File::open(path).map_err(|e| format!("Unable to open capture {}: {}", path, e))?;
+
+        let mut records = Vec::new();
+        for (number, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| format!("Unable to read capture {}: {}", path, e))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: CaptureRecord = serde_json::from_str(&line)
+                .map_err(|e| format!("Invalid record at {}:{}: {}", path, number + 1, e))?;
+            records.push(record);
+        }
+
+        let device = match device {
+            Some(device) if records.iter().any(|record| record.device == device) => {
+                device.to_owned()
+            }
+            Some(device) => {
+                return Err(format!("Capture {} has no reads of {}", path, device));
+            }
+            None => records
+                .first()
+                .map(|record| record.device.clone())
+                .ok_or_else(|| format!("Capture {} is empty", path))?,
+        };
+
+        let mut polls: Vec<CapturedPoll> = Vec::new();
+        for record in records.into_iter().filter(|record| record.device == device) {
+            let uuid = u16::from_str_radix(&record.characteristic, 16)
+                .map(uuid_from_short)
+                .map_err(|_| {
+                    format!(
+                        "Invalid characteristic {} in {}",
+                        record.characteristic, path
+                    )
+                })?;
+            let value = match (record.value, record.error) {
+                (Some(value), _) => Ok(hex::decode(&value)
+                    .map_err(|e| format!("Invalid value for {} in {}: {}", uuid, path, e))?),
+                (None, Some(error)) => Err(error),
+                (None, None) => continue,
+            };
+
+            if polls.last().map(|poll| poll.poll) != Some(record.poll) {
+                polls.push(CapturedPoll {
+                    poll: record.poll,
+                    timestamp: record.timestamp,
+                    values: HashMap::new(),
+                });
+            }
+            polls.last_mut().unwrap().values.insert(uuid, value);
+        }
+
+        Ok(ReplayTransport {
+            name: format!("replay {} ({}, {} polls)", path, device, polls.len()),
+            polls,
+            current: Mutex::new(None),
+            repeat,
+        })
+    }
+
+    fn current_poll(&self) -> Option<&CapturedPoll> {
+        self.current
+            .lock()
+            .unwrap()
+            .and_then(|index| self.polls.get(index))
+    }
+}
+
+impl GattTransport for ReplayTransport {
+    fn connect<'a>(&'a self, _monitor: &'a ConnectionMonitor) -> BoxFuture<'a, bluer::Result<()>> {
+        let mut current = self.current.lock().unwrap();
+        let next = current.map_or(0, |index| index + 1);
+        let result = if next < self.polls.len() {
+            *current = Some(next);
+            Ok(())
+        } else if self.repeat && !self.polls.is_empty() {
+            *current = Some(0);
+            Ok(())
+        } else {
+            Err(bluer::Error {
+                kind: bluer::ErrorKind::NotFound,
+                message: "End of capture".to_owned(),
+            })
+        };
+        Box::pin(async move { result })
+    }
+
+    fn read(&self, uuid: Uuid) -> BoxFuture<'_, bluer::Result<Option<Vec<u8>>>> {
+        let result = match self.current_poll().and_then(|poll| poll.values.get(&uuid)) {
+            Some(Ok(value)) => Ok(Some(value.clone())),
+            Some(Err(error)) => Err(bluer::Error {
+                kind: bluer::ErrorKind::Failed,
+                message: error.clone(),
+            }),
+            None => Ok(None),
+        };
+        Box::pin(async move { result })
+    }
+
+    fn write<'a>(&'a self, _uuid: Uuid, _value: &'a [u8]) -> BoxFuture<'a, bluer::Result<()>> {
+        Box::pin(async {
+            Err(bluer::Error {
+                kind: bluer::ErrorKind::NotSupported,
+                message: "Parameters cannot be written while replaying a capture".to_owned(),
+            })
+        })
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+/// Decoded inverter data after a recorded poll, printed by `bt replay`.
+#[derive(Serialize)]
+struct DecodedPoll<'a> {
+    poll: u64,
+    timestamp: DateTime<Utc>,
+    data: &'a InverterData,
+}
+
+/// Feed every poll of a capture through the `InverterData` parsers and print the decoded
+/// data as JSON lines, so decoder changes can be compared offline.
+pub async fn decode_capture(path: &str, device: Option<&str>) -> Result<(), String> {
+    let transport = ReplayTransport::from_file(path, device, false)?;
+    let monitor = ConnectionMonitor::new();
+    let mut data = InverterData::new();
+    let mut reads = BTreeMap::new();
+
+    while transport.connect(&monitor).await.is_ok() {
+        let poll = transport.current_poll().unwrap();
+        if let Err(e) = data
+            .read_characteristics(&transport, &CharacteristicGroup::ALL, &mut reads)
+            .await
+        {
+            eprintln!("Poll {}: {}", poll.poll, e);
+        }
+
+        let decoded = DecodedPoll {
+            poll: poll.poll,
+            timestamp: poll.timestamp,
+            data: &data,
+        };
+        println!(
+            "{}",
+            serde_json::to_string(&decoded).map_err(|e| e.to_string())?
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inverter::transport::FixtureTransport;
+
+    fn write_capture(name: &str, records: &[(&str, u64)]) -> String {
+        let path = std::env::temp_dir().join(name);
+        let mut file = File::create(&path).unwrap();
+        for (device, poll) in records {
+            let record = CaptureRecord {
+                timestamp: Utc::now(),
+                device: device.to_string(),
+                poll: *poll,
+                characteristic: "2a03".to_owned(),
+                value: Some("00".to_owned()),
+                error: None,
+            };
+            writeln!(file, "{}", serde_json::to_string(&record).unwrap()).unwrap();
+        }
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn poll_numbers_carry_on_from_the_capture() {
+        let path = write_capture(
+            "bt_capture_last_poll.jsonl",
+            &[("a", 1), ("a", 2), ("b", 7)],
+        );
+        assert_eq!(CaptureTransport::last_poll(&path, "a"), 2);
+        assert_eq!(CaptureTransport::last_poll(&path, "b"), 7);
+        assert_eq!(CaptureTransport::last_poll(&path, "c"), 0);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn devices_sharing_a_capture_keep_whole_lines() {
+        let path = std::env::temp_dir().join("bt_capture_shared.jsonl");
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+        let uuid = uuid_from_short(0x2a03);
+
+        std::thread::scope(|scope| {
+            for device in ["a", "b"] {
+                let inner =
+                    Box::new(FixtureTransport::from_file("fixtures/inverter.json").unwrap());
+                let capture = CaptureTransport::new(inner, device, path).unwrap();
+                scope.spawn(move || {
+                    for _ in 0..500 {
+                        capture.record(uuid, &Ok(Some(vec![0x55; 64])));
+                    }
+                });
+            }
+        });
+
+        for device in ["a", "b"] {
+            let replay = ReplayTransport::from_file(path, Some(device), false).unwrap();
+            assert_eq!(replay.polls.len(), 1);
+        }
+        let lines = std::fs::read_to_string(path).unwrap();
+        assert_eq!(lines.lines().count(), 1000);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn replay_of_a_missing_device_fails() {
+        let path = write_capture("bt_capture_replay.jsonl", &[("a", 1), ("a", 2), ("b", 1)]);
+        assert!(ReplayTransport::from_file(&path, Some("c"), false).is_err());
+
+        let replay = ReplayTransport::from_file(&path, None, false).unwrap();
+        assert_eq!(replay.polls.len(), 2);
+        let replay = ReplayTransport::from_file(&path, Some("b"), false).unwrap();
+        assert_eq!(replay.polls.len(), 1);
+        std::fs::remove_file(path).unwrap();
+    }
+}
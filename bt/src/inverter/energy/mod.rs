@@ -0,0 +1,462 @@
+use crate::inverter::bt::InfluxData;
+use crate::inverter::{
+    InverterSnapshot, CHAR_UUID_0X2A03, CHAR_UUID_0X2A04, CHAR_UUID_0X2A11, CHAR_UUID_0X2A12,
+    CHAR_UUID_0X2A13, CHAR_UUID_0X2A14,
+};
+use chrono::{DateTime, Datelike, Local, NaiveDate, Utc};
+use futures::prelude::*;
+use influxdb2::models::DataPoint;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::ErrorKind;
+use std::time::{Duration, Instant};
+
+/// Samples further apart than this are not integrated, the inverter was not reachable.
+const MAX_SAMPLE_GAP_SECS: i64 = 15 * 60;
+
+/// Days kept in the ledger, enough for the monthly totals of the last two years.
+const MAX_DAYS: usize = 800;
+
+/// The ledger is saved at most this often, and on the first update after a restart.
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Energy counters in kWh.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct EnergyTotals {
+    pub pv_stage1: f64,
+    pub pv_stage2: f64,
+    pub pv_stage3: f64,
+    pub pv_stage4: f64,
+    pub pv: f64,
+    pub load: f64,
+    pub battery_charge: f64,
+    pub battery_discharge: f64,
+    /// Load and battery charge not covered by PV or the battery, losses are not accounted.
+    pub grid_import: f64,
+}
+
+impl EnergyTotals {
+    fn add(&mut self, other: &EnergyTotals) {
+        self.pv_stage1 += other.pv_stage1;
+        self.pv_stage2 += other.pv_stage2;
+        self.pv_stage3 += other.pv_stage3;
+        self.pv_stage4 += other.pv_stage4;
+        self.pv += other.pv;
+        self.load += other.load;
+        self.battery_charge += other.battery_charge;
+        self.battery_discharge += other.battery_discharge;
+        self.grid_import += other.grid_import;
+    }
+}
+
+/// Instantaneous power in W, `None` for values not read during the poll.
+#[derive(Debug, Clone, Copy)]
+struct PowerSample {
+    at: DateTime<Utc>,
+    pv: [Option<f64>; 4],
+    load: Option<f64>,
+    battery_charge: Option<f64>,
+    battery_discharge: Option<f64>,
+}
+
+impl PowerSample {
+    fn from_snapshot(snapshot: &InverterSnapshot) -> Self {
+        let live = &snapshot.data.live;
+        let pv = [
+            (CHAR_UUID_0X2A11, live.pv_input_power_stage1),
+            (CHAR_UUID_0X2A12, live.pv_input_power_stage2),
+            (CHAR_UUID_0X2A13, live.pv_input_power_stage3),
+            (CHAR_UUID_0X2A14, live.pv_input_power_stage4),
+        ]
+        .map(|(uuid, power)| snapshot.is_fresh(&uuid).then_some(power as f64));
+        let fresh_0x2a03 = snapshot.is_fresh(&CHAR_UUID_0X2A03);
+        let fresh_0x2a04 = snapshot.is_fresh(&CHAR_UUID_0X2A04);
+        let battery_voltage = live.battery_voltage as f64;
+
+        PowerSample {
+            at: snapshot.read_at,
+            pv,
+            load: fresh_0x2a03.then_some(live.output_active_power as f64),
+            battery_charge: fresh_0x2a03
+                .then_some(battery_voltage * live.battery_charge_current as f64),
+            battery_discharge: (fresh_0x2a03 && fresh_0x2a04)
+                .then_some(battery_voltage * live.battery_current_discharge as f64),
+        }
+    }
+
+    /// Estimated grid power, what the load and the battery charge take beyond PV and battery.
+    fn grid_import(&self) -> Option<f64> {
+        let pv: f64 = self.pv.iter().flatten().sum();
+        Some((self.load? + self.battery_charge? - pv - self.battery_discharge?).max(0.0))
+    }
+
+    /// Energy in kWh between two samples, integrating each power with the trapezoidal rule.
+    fn energy_since(&self, previous: &PowerSample) -> EnergyTotals {
+        let hours = (self.at - previous.at).num_milliseconds() as f64 / 3_600_000.0;
+        let kwh = |previous: Option<f64>, current: Option<f64>| match (previous, current) {
+            (Some(previous), Some(current)) => (previous + current) / 2.0 * hours / 1000.0,
+            _ => 0.0,
+        };
+
+        let pv_stages: [f64; 4] = std::array::from_fn(|i| kwh(previous.pv[i], self.pv[i]));
+        EnergyTotals {
+            pv_stage1: pv_stages[0],
+            pv_stage2: pv_stages[1],
+            pv_stage3: pv_stages[2],
+            pv_stage4: pv_stages[3],
+            pv: pv_stages.iter().sum(),
+            load: kwh(previous.load, self.load),
+            battery_charge: kwh(previous.battery_charge, self.battery_charge),
+            battery_discharge: kwh(previous.battery_discharge, self.battery_discharge),
+            grid_import: kwh(previous.grid_import(), self.grid_import()),
+        }
+    }
+}
+
+/// Period of an energy report.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EnergyPeriod {
+    #[default]
+    Day,
+    Month,
+}
+
+/// Query of `/api/energy`.
+#[derive(Debug, Default, Deserialize)]
+pub struct EnergyQuery {
+    #[serde(default)]
+    pub period: EnergyPeriod,
+    /// Any day within the period, today by default.
+    pub date: Option<NaiveDate>,
+}
+
+/// Energy of every inverter and of the whole system during a day or a month.
+#[derive(Debug, Serialize)]
+pub struct EnergyReport {
+    pub period: EnergyPeriod,
+    /// `YYYY-MM-DD` for a day, `YYYY-MM` for a month.
+    pub date: String,
+    pub inverters: BTreeMap<String, EnergyTotals>,
+    pub total: EnergyTotals,
+}
+
+/// Daily energy counters by inverter, integrated from the power readings of consecutive polls
+/// and saved to a JSON file.
+pub struct EnergyMeter {
+    path: Option<String>,
+    days: BTreeMap<String, BTreeMap<NaiveDate, EnergyTotals>>,
+    last_samples: HashMap<String, PowerSample>,
+    last_save: Option<Instant>,
+}
+
+impl EnergyMeter {
+    /// Load the counters saved at `path`, starting empty if there are none.
+    /// Without a path the counters are only kept in memory.
+    pub fn load(path: Option<String>) -> Self {
+        let days = match path.as_ref().map(fs::read_to_string) {
+            Some(Ok(json)) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                println!(
+                    "Ignoring invalid energy counters {}: {}",
+                    path.as_ref().unwrap(),
+                    e
+                );
+                BTreeMap::new()
+            }),
+            Some(Err(e)) if e.kind() != ErrorKind::NotFound => {
+                println!(
+                    "Unable to read energy counters {}: {}",
+                    path.as_ref().unwrap(),
+                    e
+                );
+                BTreeMap::new()
+            }
+            _ => BTreeMap::new(),
+        };
+
+        EnergyMeter {
+            path,
+            days,
+            last_samples: HashMap::new(),
+            last_save: None,
+        }
+    }
+
+    /// Add the energy since the previous poll of `device`, returning the counters of the day.
+    pub fn update(&mut self, device: &str, snapshot: &InverterSnapshot) -> Option<EnergyTotals> {
+        let sample = PowerSample::from_snapshot(snapshot);
+        let previous = self.last_samples.insert(device.to_owned(), sample)?;
+        let gap = (sample.at - previous.at).num_seconds();
+        if gap <= 0 || gap > MAX_SAMPLE_GAP_SECS {
+            return None;
+        }
+
+        let day = sample.at.with_timezone(&Local).date_naive();
+        let days = self.days.entry(device.to_owned()).or_default();
+        let totals = days.entry(day).or_default();
+        totals.add(&sample.energy_since(&previous));
+        let totals = *totals;
+
+        while days.len() > MAX_DAYS {
+            days.pop_first();
+        }
+        if self
+            .last_save
+            .is_none_or(|last_save| last_save.elapsed() >= SAVE_INTERVAL)
+        {
+            self.save();
+        }
+        Some(totals)
+    }
+
+    /// Energy during the day or the month containing `date`.
+    pub fn report(&self, period: EnergyPeriod, date: NaiveDate) -> EnergyReport {
+        let in_period = |day: &NaiveDate| match period {
+            EnergyPeriod::Day => *day == date,
+            EnergyPeriod::Month => day.year() == date.year() && day.month() == date.month(),
+        };
+
+        let mut report = EnergyReport {
+            period,
+            date: match period {
+                EnergyPeriod::Day => date.format("%Y-%m-%d").to_string(),
+                EnergyPeriod::Month => date.format("%Y-%m").to_string(),
+            },
+            inverters: BTreeMap::new(),
+            total: EnergyTotals::default(),
+        };
+        for (device, days) in &self.days {
+            let mut totals = EnergyTotals::default();
+            for (_, day_totals) in days.iter().filter(|(day, _)| in_period(day)) {
+                totals.add(day_totals);
+            }
+            report.total.add(&totals);
+            report.inverters.insert(device.clone(), totals);
+        }
+        report
+    }
+
+    fn save(&mut self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        self.last_save = Some(Instant::now());
+        let result = serde_json::to_string(&self.days)
+            .map_err(|e| e.to_string())
+            .and_then(|json| {
+                // Write a temporary file first so a crash never leaves truncated counters
+                let tmp_path = format!("{}.tmp", path);
+                fs::write(&tmp_path, json)
+                    .and_then(|_| fs::rename(&tmp_path, path))
+                    .map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            println!("Unable to save energy counters {}: {}", path, e);
+        }
+    }
+}
+
+/// Save the energy counters of the day of an inverter to InfluxDB.
+pub async fn save_to_db(
+    device: String,
+    totals: EnergyTotals,
+    read_at: DateTime<Utc>,
+    influx_data: InfluxData,
+) {
+    let point = DataPoint::builder("energy")
+        .tag("host", "inverter")
+        .tag("device", device)
+        .tag("period", "day")
+        .field("pv1", totals.pv_stage1)
+        .field("pv2", totals.pv_stage2)
+        .field("pv3", totals.pv_stage3)
+        .field("pv4", totals.pv_stage4)
+        .field("pv", totals.pv)
+        .field("load", totals.load)
+        .field("battery_charge", totals.battery_charge)
+        .field("battery_discharge", totals.battery_discharge)
+        .field("grid_import", totals.grid_import)
+        .timestamp(read_at.timestamp_nanos_opt().unwrap_or_default())
+        .build();
+
+    let Ok(point) = point else {
+        return;
+    };
+    let client = influx_data.create_client();
+    let result = client
+        .write(influx_data.get_bucket(), stream::iter([point]))
+        .await;
+    if let Err(e) = result {
+        println!("Influxdb client error: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inverter::transport::short_id;
+    use crate::inverter::{CharacteristicRead, InverterData};
+    use chrono::TimeZone;
+    use uuid::Uuid;
+
+    const LIVE: [Uuid; 6] = [
+        CHAR_UUID_0X2A03,
+        CHAR_UUID_0X2A04,
+        CHAR_UUID_0X2A11,
+        CHAR_UUID_0X2A12,
+        CHAR_UUID_0X2A13,
+        CHAR_UUID_0X2A14,
+    ];
+
+    /// Snapshot with PV on the first stage, 50V on the battery and only `fresh` read.
+    fn snapshot(
+        at: DateTime<Utc>,
+        pv: u16,
+        load: u16,
+        charge_current: u16,
+        fresh: &[Uuid],
+    ) -> InverterSnapshot {
+        let mut data = InverterData::new();
+        data.live.pv_input_power_stage1 = pv;
+        data.live.output_active_power = load;
+        data.live.battery_voltage = 50.0;
+        data.live.battery_charge_current = charge_current;
+        let characteristics = fresh
+            .iter()
+            .map(|uuid| {
+                let read = CharacteristicRead {
+                    success: true,
+                    read_at: at,
+                    last_success: Some(at),
+                    error: None,
+                    parse_errors: 0,
+                    latency_ms: 0.0,
+                };
+                (short_id(uuid), read)
+            })
+            .collect();
+        InverterSnapshot {
+            data,
+            read_at: at,
+            characteristics,
+        }
+    }
+
+    fn at(hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 3, 20, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn power_is_integrated_with_the_trapezoidal_rule() {
+        let previous = PowerSample::from_snapshot(&snapshot(at(10, 0), 0, 1000, 0, &LIVE));
+        let current = PowerSample::from_snapshot(&snapshot(at(11, 0), 2000, 500, 10, &LIVE));
+
+        // 500W charging the battery, the PV covers it along with the load
+        assert_eq!(previous.grid_import(), Some(1000.0));
+        assert_eq!(current.grid_import(), Some(0.0));
+
+        let energy = current.energy_since(&previous);
+        assert!((energy.pv_stage1 - 1.0).abs() < 1e-9);
+        assert!((energy.pv - 1.0).abs() < 1e-9);
+        assert!((energy.load - 0.75).abs() < 1e-9);
+        assert!((energy.battery_charge - 0.25).abs() < 1e-9);
+        assert_eq!(energy.battery_discharge, 0.0);
+        assert!((energy.grid_import - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn stale_characteristics_add_nothing() {
+        // PV read in the previous poll only, 0x2A04 never read
+        let previous = PowerSample::from_snapshot(&snapshot(at(10, 0), 1000, 1000, 0, &LIVE));
+        let fresh = [CHAR_UUID_0X2A03];
+        let current = PowerSample::from_snapshot(&snapshot(at(11, 0), 1000, 1000, 0, &fresh));
+
+        assert_eq!(current.pv[0], None);
+        assert_eq!(current.battery_discharge, None);
+        assert_eq!(current.grid_import(), None);
+        let energy = current.energy_since(&previous);
+        assert_eq!(energy.pv, 0.0);
+        assert_eq!(energy.battery_discharge, 0.0);
+        assert_eq!(energy.grid_import, 0.0);
+        assert!((energy.load - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn gaps_are_not_integrated() {
+        let mut meter = EnergyMeter::load(None);
+        assert!(meter
+            .update("a", &snapshot(at(10, 0), 1200, 0, 0, &LIVE))
+            .is_none());
+        // Same poll time and a gap longer than MAX_SAMPLE_GAP_SECS
+        assert!(meter
+            .update("a", &snapshot(at(10, 0), 1200, 0, 0, &LIVE))
+            .is_none());
+        assert!(meter
+            .update("a", &snapshot(at(10, 16), 1200, 0, 0, &LIVE))
+            .is_none());
+
+        let totals = meter
+            .update("a", &snapshot(at(10, 31), 1200, 0, 0, &LIVE))
+            .unwrap();
+        assert!((totals.pv - 0.3).abs() < 1e-9);
+        let totals = meter
+            .update("a", &snapshot(at(10, 46), 1200, 0, 0, &LIVE))
+            .unwrap();
+        assert!((totals.pv - 0.6).abs() < 1e-9);
+    }
+
+    #[test]
+    fn report_adds_the_days_of_every_inverter() {
+        let mut meter = EnergyMeter::load(None);
+        let day = |d: u32| NaiveDate::from_ymd_opt(2026, 3, d).unwrap();
+        let totals = |pv: f64| EnergyTotals {
+            pv,
+            load: pv / 2.0,
+            ..Default::default()
+        };
+        meter.days.insert(
+            "a".to_owned(),
+            BTreeMap::from([(day(1), totals(1.0)), (day(20), totals(2.0))]),
+        );
+        meter.days.insert(
+            "b".to_owned(),
+            BTreeMap::from([
+                (day(20), totals(4.0)),
+                (NaiveDate::from_ymd_opt(2026, 4, 1).unwrap(), totals(8.0)),
+            ]),
+        );
+
+        let report = meter.report(EnergyPeriod::Month, day(15));
+        assert_eq!(report.date, "2026-03");
+        assert_eq!(report.inverters["a"].pv, 3.0);
+        assert_eq!(report.inverters["b"].pv, 4.0);
+        assert_eq!(report.total.pv, 7.0);
+        assert_eq!(report.total.load, 3.5);
+
+        let report = meter.report(EnergyPeriod::Day, day(20));
+        assert_eq!(report.date, "2026-03-20");
+        assert_eq!(report.total.pv, 6.0);
+    }
+
+    #[test]
+    fn counters_are_saved_and_loaded() {
+        let path = std::env::temp_dir().join("bt_energy.json");
+        let path = path.to_str().unwrap().to_owned();
+        let _ = fs::remove_file(&path);
+
+        let mut meter = EnergyMeter::load(Some(path.clone()));
+        meter.update("a", &snapshot(at(10, 0), 1000, 500, 0, &LIVE));
+        meter.update("a", &snapshot(at(10, 15), 3000, 500, 0, &LIVE));
+        meter.save();
+
+        let loaded = EnergyMeter::load(Some(path.clone()));
+        assert_eq!(
+            serde_json::to_string(&loaded.days).unwrap(),
+            serde_json::to_string(&meter.days).unwrap()
+        );
+        let date = at(10, 15).with_timezone(&Local).date_naive();
+        assert!((loaded.report(EnergyPeriod::Day, date).total.pv - 0.5).abs() < 1e-9);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,340 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::ErrorKind;
+
+/// Finished events kept in the history, the oldest ones are dropped first.
+const MAX_HISTORY: usize = 1000;
+
+/// Severity of an inverter event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EventLevel {
+    Fault,
+    Warning,
+}
+
+impl EventLevel {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventLevel::Fault => "Fault",
+            EventLevel::Warning => "Warning",
+        }
+    }
+}
+
+/// A fault or warning currently reported by an inverter.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InverterEvent {
+    /// Fault or warning code shown by the inverter (e.g. `1007`).
+    pub code: String,
+    pub level: EventLevel,
+    pub message: String,
+    /// Bit of the event in the 0x2A04 fault and warning flags.
+    pub bit: usize,
+    /// First poll the event was seen active since it last cleared.
+    pub first_seen: DateTime<Utc>,
+}
+
+/// A fault or warning reported by an inverter, from its onset until it clears.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventRecord {
+    pub device: String,
+    /// Bit of the event in the 0x2A04 fault and warning flags.
+    pub bit: usize,
+    #[serde(alias = "id")]
+    pub code: String,
+    pub level: EventLevel,
+    pub message: String,
+    pub started_at: DateTime<Utc>,
+    /// `None` while the event is still active.
+    pub ended_at: Option<DateTime<Utc>>,
+    pub duration_secs: Option<i64>,
+}
+
+impl EventRecord {
+    pub fn is_active(&self) -> bool {
+        self.ended_at.is_none()
+    }
+}
+
+/// Filter of `/api/events`, every field is optional.
+#[derive(Debug, Default, Deserialize)]
+pub struct EventFilter {
+    pub device: Option<String>,
+    /// `Fault` or `Warning`, case insensitive.
+    pub level: Option<String>,
+    /// Events still active at or after this time.
+    pub from: Option<DateTime<Utc>>,
+    /// Events started at or before this time.
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl EventFilter {
+    fn matches(&self, event: &EventRecord) -> bool {
+        self.device
+            .as_ref()
+            .is_none_or(|device| device.eq_ignore_ascii_case(&event.device))
+            && self
+                .level
+                .as_ref()
+                .is_none_or(|level| level.eq_ignore_ascii_case(event.level.name()))
+            && self
+                .from
+                .is_none_or(|from| event.ended_at.is_none_or(|end| end >= from))
+            && self.to.is_none_or(|to| event.started_at <= to)
+    }
+}
+
+/// Tracks the onset and clearance of inverter events by diffing the raw event flags of
+/// consecutive polls. The history is saved to a JSON file on every change.
+pub struct EventTracker {
+    path: Option<String>,
+    events: Vec<EventRecord>,
+}
+
+impl EventTracker {
+    /// Load the history saved at `path`, starting empty if there is none.
+    /// Without a path the history is only kept in memory.
+    pub fn load(path: Option<String>) -> Self {
+        let events = match path.as_ref().map(fs::read_to_string) {
+            Some(Ok(json)) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                println!(
+                    "Ignoring invalid event history {}: {}",
+                    path.as_ref().unwrap(),
+                    e
+                );
+                Vec::new()
+            }),
+            Some(Err(e)) if e.kind() != ErrorKind::NotFound => {
+                println!(
+                    "Unable to read event history {}: {}",
+                    path.as_ref().unwrap(),
+                    e
+                );
+                Vec::new()
+            }
+            _ => Vec::new(),
+        };
+
+        EventTracker { path, events }
+    }
+
+    /// Record the events of `device` that started or cleared since its previous poll.
+    /// Events that were active when the service stopped carry on, or clear on the first poll.
+    /// A "T" level event changing between warning and fault is recorded as a new event.
+    pub fn update(&mut self, device: &str, events: &[InverterEvent], read_at: DateTime<Utc>) {
+        let mut changed = false;
+
+        for record in self
+            .events
+            .iter_mut()
+            .filter(|record| record.device == device && record.is_active())
+        {
+            if events
+                .iter()
+                .any(|event| event.bit == record.bit && event.code == record.code)
+            {
+                continue;
+            }
+            record.ended_at = Some(read_at);
+            record.duration_secs = Some((read_at - record.started_at).num_seconds());
+            println!(
+                "[{}] {} {} cleared after {}s: {}",
+                device,
+                record.level.name(),
+                record.code,
+                record.duration_secs.unwrap_or_default(),
+                record.message
+            );
+            changed = true;
+        }
+
+        for event in events {
+            if self.events.iter().any(|record| {
+                record.device == device
+                    && record.is_active()
+                    && record.bit == event.bit
+                    && record.code == event.code
+            }) {
+                continue;
+            }
+            println!(
+                "[{}] {} {} started: {}",
+                device,
+                event.level.name(),
+                event.code,
+                event.message
+            );
+            self.events.push(EventRecord {
+                device: device.to_owned(),
+                bit: event.bit,
+                code: event.code.clone(),
+                level: event.level,
+                message: event.message.clone(),
+                started_at: read_at,
+                ended_at: None,
+                duration_secs: None,
+            });
+            changed = true;
+        }
+
+        if changed {
+            self.prune();
+            self.save();
+        }
+    }
+
+    /// Events matching the filter, oldest first.
+    pub fn query(&self, filter: &EventFilter) -> Vec<EventRecord> {
+        self.events
+            .iter()
+            .filter(|event| filter.matches(event))
+            .cloned()
+            .collect()
+    }
+
+    fn prune(&mut self) {
+        let mut finished = self
+            .events
+            .iter()
+            .filter(|event| !event.is_active())
+            .count();
+        self.events.retain(|event| {
+            if finished > MAX_HISTORY && !event.is_active() {
+                finished -= 1;
+                return false;
+            }
+            true
+        });
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let result = serde_json::to_string(&self.events)
+            .map_err(|e| e.to_string())
+            .and_then(|json| {
+                // Write a temporary file first so a crash never leaves a truncated history
+                let tmp_path = format!("{}.tmp", path);
+                fs::write(&tmp_path, json)
+                    .and_then(|_| fs::rename(&tmp_path, path))
+                    .map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            println!("Unable to save event history {}: {}", path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 3, 20, 10, minute, 0).unwrap()
+    }
+
+    fn event(bit: usize, code: &str, level: EventLevel) -> InverterEvent {
+        InverterEvent {
+            code: code.to_owned(),
+            level,
+            message: format!("Event {}", code),
+            bit,
+            first_seen: at(0),
+        }
+    }
+
+    fn filter(level: Option<&str>, from: Option<u32>, to: Option<u32>) -> EventFilter {
+        EventFilter {
+            device: None,
+            level: level.map(str::to_owned),
+            from: from.map(at),
+            to: to.map(at),
+        }
+    }
+
+    /// Poll sequence of two devices: overheating warning on `a` turning into a fault, and a
+    /// fan fault on `b` still active.
+    fn tracker(path: Option<String>) -> EventTracker {
+        let mut tracker = EventTracker::load(path);
+        let warning = event(1, "02", EventLevel::Warning);
+        let fault = event(1, "01", EventLevel::Fault);
+        let fan = event(7, "08", EventLevel::Fault);
+
+        tracker.update("a", std::slice::from_ref(&warning), at(0));
+        tracker.update("b", std::slice::from_ref(&fan), at(1));
+        tracker.update("a", &[warning], at(5));
+        tracker.update("a", &[fault], at(10));
+        tracker.update("b", &[fan], at(12));
+        tracker.update("a", &[], at(30));
+        tracker
+    }
+
+    #[test]
+    fn events_are_opened_and_closed() {
+        let events = tracker(None).query(&EventFilter::default());
+        assert_eq!(events.len(), 3);
+
+        assert_eq!(events[0].device, "a");
+        assert_eq!(events[0].code, "02");
+        assert_eq!(events[0].started_at, at(0));
+        assert_eq!(events[0].ended_at, Some(at(10)));
+        assert_eq!(events[0].duration_secs, Some(600));
+
+        assert_eq!(events[1].device, "b");
+        assert!(events[1].is_active());
+        assert_eq!(events[1].started_at, at(1));
+        assert_eq!(events[1].duration_secs, None);
+
+        // The warning turning into a fault is a new event
+        assert_eq!(events[2].code, "01");
+        assert_eq!(events[2].level, EventLevel::Fault);
+        assert_eq!(events[2].started_at, at(10));
+        assert_eq!(events[2].duration_secs, Some(1200));
+    }
+
+    #[test]
+    fn events_are_filtered() {
+        let tracker = tracker(None);
+        let codes = |filter: &EventFilter| {
+            tracker
+                .query(filter)
+                .iter()
+                .map(|event| event.code.clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(codes(&filter(Some("fault"), None, None)), ["08", "01"]);
+        assert_eq!(codes(&filter(Some("WARNING"), None, None)), ["02"]);
+        // Ended before `from`, or started after `to`
+        assert_eq!(codes(&filter(None, Some(11), None)), ["08", "01"]);
+        assert_eq!(codes(&filter(None, None, Some(5))), ["02", "08"]);
+        assert_eq!(codes(&filter(None, Some(10), Some(10))), ["02", "08", "01"]);
+        assert_eq!(
+            codes(&EventFilter {
+                device: Some("B".to_owned()),
+                ..Default::default()
+            }),
+            ["08"]
+        );
+    }
+
+    #[test]
+    fn history_is_reloaded() {
+        let path = std::env::temp_dir().join("bt_events.json");
+        let path = path.to_str().unwrap().to_owned();
+        let _ = fs::remove_file(&path);
+        tracker(Some(path.clone()));
+
+        // The fan fault carries on after a restart and clears on the first poll without it
+        let mut tracker = EventTracker::load(Some(path.clone()));
+        assert_eq!(tracker.query(&EventFilter::default()).len(), 3);
+        tracker.update("b", &[], at(40));
+        let events = EventTracker::load(Some(path.clone())).query(&EventFilter::default());
+        assert_eq!(events[1].ended_at, Some(at(40)));
+        assert_eq!(events[1].duration_secs, Some(39 * 60));
+        fs::remove_file(&path).unwrap();
+    }
+}
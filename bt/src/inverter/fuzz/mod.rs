@@ -0,0 +1,79 @@
+//! Property tests of the characteristic parsers, fed seeded random values.
+
+use crate::inverter::transport::short_id;
+use crate::inverter::{InverterData, CHARACTERISTIC_PARSERS};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Longest random value fed to the parsers, longer than any known characteristic.
+const MAX_VALUE_LEN: usize = 64;
+const ITERATIONS: usize = 300;
+const SEED: u64 = 0x2A03;
+
+fn random_value(rng: &mut StdRng, len: usize) -> Vec<u8> {
+    (0..len).map(|_| rng.gen()).collect()
+}
+
+/// Parse `bytes`, checking that a failure reports the characteristic and the actual length
+/// of the value and leaves the previously parsed data untouched. Returns whether it parsed.
+fn check_parse(data: &mut InverterData, index: usize, bytes: Vec<u8>) -> bool {
+    let (uuid, _, parse) = CHARACTERISTIC_PARSERS[index];
+    let before = data.to_json().unwrap();
+    let len = bytes.len();
+    let value = hex::encode(&bytes);
+
+    match parse(data, bytes) {
+        Ok(()) => true,
+        Err(e) => {
+            assert_eq!(e.characteristic, uuid, "{} with {}", short_id(&uuid), value);
+            assert_eq!(e.actual, len, "{} with {}", short_id(&uuid), value);
+            assert_eq!(
+                data.to_json().unwrap(),
+                before,
+                "{} changed the data on a failed parse of {}",
+                short_id(&uuid),
+                value
+            );
+            false
+        }
+    }
+}
+
+#[test]
+fn truncated_values_are_rejected() {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let mut data = InverterData::new();
+
+    for (index, (uuid, _, parse)) in CHARACTERISTIC_PARSERS.iter().enumerate() {
+        // The error of an empty value gives the minimum length, if there is one
+        let Err(e) = parse(&mut InverterData::new(), Vec::new()) else {
+            continue;
+        };
+        for len in 0..e.expected {
+            let bytes = random_value(&mut rng, len);
+            assert!(
+                !check_parse(&mut data, index, bytes),
+                "{} accepted {} of {} bytes",
+                short_id(uuid),
+                len,
+                e.expected
+            );
+        }
+        let bytes = random_value(&mut rng, e.expected);
+        assert!(check_parse(&mut data, index, bytes), "{}", short_id(uuid));
+    }
+}
+
+#[test]
+fn random_values_never_panic() {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let mut data = InverterData::new();
+
+    for _ in 0..ITERATIONS {
+        for index in 0..CHARACTERISTIC_PARSERS.len() {
+            let len = rng.gen_range(0..=MAX_VALUE_LEN);
+            let bytes = random_value(&mut rng, len);
+            check_parse(&mut data, index, bytes);
+        }
+    }
+}
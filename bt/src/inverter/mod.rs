@@ -1,9 +1,25 @@
 pub mod bt;
+pub mod capture;
+pub mod energy;
+pub mod events;
+pub mod modes;
+pub mod pairing;
+pub mod parameters;
+pub mod pi30;
+pub mod transport;
 
 use bit_array::BitArray;
-use bluer::gatt::remote::Service;
+use chrono::{DateTime, Utc};
+use events::{EventLevel, InverterEvent};
+use modes::{
+    AcInputRange, BatteryType, ChargeMode, ChargerSourcePriority, OutputMode, OutputSourcePriority,
+    WorkMode,
+};
 use serde::{Deserialize, Serialize};
 use serde_json::Result;
+use std::collections::BTreeMap;
+use std::fmt;
+use transport::{short_id, GattTransport};
 use typenum::U8;
 
 const CHAR_UUID_0X2A01: uuid::Uuid = uuid::Uuid::from_u128(0x00002a0100001000800000805f9b34fb);
@@ -24,8 +40,100 @@ const CHAR_UUID_0X2A12: uuid::Uuid = uuid::Uuid::from_u128(0x00002a1200001000800
 const CHAR_UUID_0X2A13: uuid::Uuid = uuid::Uuid::from_u128(0x00002a1300001000800000805f9b34fb);
 const CHAR_UUID_0X2A14: uuid::Uuid = uuid::Uuid::from_u128(0x00002a1400001000800000805f9b34fb);
 
-// TODO send/set parameters
-// const SERVICE_UUID_0X1810: uuid::Uuid = uuid::Uuid::from_u128(0x0000181000001000800000805f9b34fb);
+// Writable parameter characteristics (0x2A0C, 0x2A0D) live under this service
+const SERVICE_UUID_0X1810: uuid::Uuid = uuid::Uuid::from_u128(0x0000181000001000800000805f9b34fb);
+
+type ParseResult = std::result::Result<(), ParseError>;
+type CharacteristicParser = fn(&mut InverterData, Vec<u8>) -> ParseResult;
+
+/// Readable characteristics with their refresh group and parser, in read order.
+const CHARACTERISTIC_PARSERS: [(uuid::Uuid, CharacteristicGroup, CharacteristicParser); 17] = [
+    (
+        CHAR_UUID_0X2A01,
+        CharacteristicGroup::ProductInfo,
+        InverterData::parse_0x2a01,
+    ),
+    (
+        CHAR_UUID_0X2A02,
+        CharacteristicGroup::ProductInfo,
+        InverterData::parse_0x2a02,
+    ),
+    (
+        CHAR_UUID_0X2A03,
+        CharacteristicGroup::Live,
+        InverterData::parse_0x2a03,
+    ),
+    (
+        CHAR_UUID_0X2A04,
+        CharacteristicGroup::Live,
+        InverterData::parse_0x2a04,
+    ),
+    (
+        CHAR_UUID_0X2A05,
+        CharacteristicGroup::Ratings,
+        InverterData::parse_0x2a05,
+    ),
+    (
+        CHAR_UUID_0X2A06,
+        CharacteristicGroup::Parameters,
+        InverterData::parse_0x2a06,
+    ),
+    (
+        CHAR_UUID_0X2A07,
+        CharacteristicGroup::Parameters,
+        InverterData::parse_0x2a07,
+    ),
+    (
+        CHAR_UUID_0X2A08,
+        CharacteristicGroup::Parameters,
+        InverterData::parse_0x2a08,
+    ),
+    (
+        CHAR_UUID_0X2A09,
+        CharacteristicGroup::Parameters,
+        InverterData::parse_0x2a09,
+    ),
+    (
+        CHAR_UUID_0X2A0B,
+        CharacteristicGroup::Parameters,
+        InverterData::parse_0x2a0b,
+    ),
+    (
+        CHAR_UUID_0X2A0C,
+        CharacteristicGroup::Parameters,
+        InverterData::parse_0x2a0c,
+    ),
+    (
+        CHAR_UUID_0X2A0D,
+        CharacteristicGroup::Parameters,
+        InverterData::parse_0x2a0d,
+    ),
+    (
+        CHAR_UUID_0X2A0E,
+        CharacteristicGroup::Parameters,
+        InverterData::parse_0x2a0e,
+    ),
+    (
+        CHAR_UUID_0X2A11,
+        CharacteristicGroup::Live,
+        InverterData::parse_0x2a11,
+    ),
+    (
+        CHAR_UUID_0X2A12,
+        CharacteristicGroup::Live,
+        InverterData::parse_0x2a12,
+    ),
+    (
+        CHAR_UUID_0X2A13,
+        CharacteristicGroup::Live,
+        InverterData::parse_0x2a13,
+    ),
+    (
+        CHAR_UUID_0X2A14,
+        CharacteristicGroup::Live,
+        InverterData::parse_0x2a14,
+    ),
+];
 
 const EVENT_MESSAGE: [&str; 32] = [
     "PV loss",
@@ -81,31 +189,196 @@ const EVENT_LEVEL: [&str; 32] = [
     "", "",
 ];
 
-struct EventLine {
-    id: String,
-    level: String,
-    message: String,
+/// Bit of the "Inverter fault" event, "T" level events are faults while it is set.
+const INVERTER_FAULT_BIT: usize = 1;
+
+/// A characteristic value that is too short for its parser, usually a truncated read or a
+/// firmware with a different layout.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub characteristic: uuid::Uuid,
+    /// Minimum length in bytes.
+    pub expected: usize,
+    pub actual: usize,
 }
 
-impl ToString for EventLine {
-    fn to_string(&self) -> String {
-        format!(
-            "ID:{};LEVEL:{};MESSAGE:{};",
-            self.id, self.level, self.message
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Unable to parse characteristic {}: expected at least {} bytes, got {}",
+            short_id(&self.characteristic),
+            self.expected,
+            self.actual
         )
     }
 }
 
+/// Outcome of the last read attempt of a single characteristic.
+#[derive(Debug, Clone, Serialize)]
+pub struct CharacteristicRead {
+    pub success: bool,
+    pub read_at: DateTime<Utc>,
+    pub last_success: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+    /// Values read that could not be parsed since the service started.
+    pub parse_errors: u64,
+    /// Time taken by the last read.
+    pub latency_ms: f64,
+}
+
+/// Inverter data together with the freshness of every characteristic it was parsed from.
+#[derive(Debug, Clone, Serialize)]
+pub struct InverterSnapshot {
+    #[serde(flatten)]
+    pub data: InverterData,
+    /// Start of the poll that produced this snapshot.
+    pub read_at: DateTime<Utc>,
+    /// Read status by characteristic id (e.g. `2a03`).
+    pub characteristics: BTreeMap<String, CharacteristicRead>,
+}
+
+impl InverterSnapshot {
+    /// Whether the characteristic was successfully read during the poll of this snapshot.
+    pub fn is_fresh(&self, uuid: &uuid::Uuid) -> bool {
+        self.characteristics
+            .get(&short_id(uuid))
+            .is_some_and(|read| read.success && read.read_at >= self.read_at)
+    }
+
+    /// Whether the characteristic has been read successfully at some point, maybe in an
+    /// earlier poll if its group was not due for a refresh.
+    pub fn has_value(&self, uuid: &uuid::Uuid) -> bool {
+        self.characteristics
+            .get(&short_id(uuid))
+            .is_some_and(|read| read.last_success.is_some())
+    }
+
+    /// Active faults and warnings, only if they were read during the poll of this snapshot.
+    pub fn events(&self) -> Option<&[InverterEvent]> {
+        self.is_fresh(&CHAR_UUID_0X2A04)
+            .then_some(self.data.live.events.as_slice())
+    }
+
+    /// Serialize into JSON adding the age of the snapshot and of each characteristic value.
+    pub fn to_json_with_age(&self, now: DateTime<Utc>) -> Result<String> {
+        let mut json = serde_json::to_value(self)?;
+        json["age_secs"] = serde_json::json!((now - self.read_at).num_seconds());
+        for (id, read) in &self.characteristics {
+            json["characteristics"][id]["age_secs"] =
+                serde_json::json!(read.last_success.map(|t| (now - t).num_seconds()));
+        }
+        serde_json::to_string(&json)
+    }
+}
+
+/// Combined readings of all the inverters of a parallel or 3-phase install.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct InverterTotals {
+    /// Inverters with data included in the totals.
+    pub inverters: usize,
+    /// Inverters left out because their last snapshot is too old, e.g. offline ones.
+    pub stale_inverters: Vec<String>,
+    /// Oldest poll time among the included inverters.
+    pub read_at: Option<DateTime<Utc>>,
+    pub pv_input_power: u32,
+    pub output_apparent_power: u32,
+    pub output_active_power: u32,
+    pub nominal_output_active_power: u32,
+    /// Combined output active power relative to the combined nominal power.
+    pub load_percentage: f32,
+}
+
+impl InverterTotals {
+    /// Combine the snapshots by inverter id, skipping those read more than `max_age` ago.
+    pub fn from_snapshots<'a>(
+        snapshots: impl IntoIterator<Item = (&'a str, &'a InverterSnapshot)>,
+        now: DateTime<Utc>,
+        max_age: chrono::Duration,
+    ) -> Self {
+        let mut totals = InverterTotals::default();
+        for (id, snapshot) in snapshots {
+            if now - snapshot.read_at > max_age {
+                totals.stale_inverters.push(id.to_owned());
+                continue;
+            }
+            let data = &snapshot.data;
+            totals.inverters += 1;
+            totals.read_at = Some(
+                totals
+                    .read_at
+                    .map_or(snapshot.read_at, |t| t.min(snapshot.read_at)),
+            );
+            totals.pv_input_power += data.live.pv_input_power_stage1 as u32
+                + data.live.pv_input_power_stage2 as u32
+                + data.live.pv_input_power_stage3 as u32
+                + data.live.pv_input_power_stage4 as u32;
+            totals.output_apparent_power += data.live.output_apparent_power as u32;
+            totals.output_active_power += data.live.output_active_power as u32;
+            totals.nominal_output_active_power += data.ratings.nominal_output_active_power as u32;
+        }
+
+        if totals.nominal_output_active_power > 0 {
+            totals.load_percentage = totals.output_active_power as f32 * 100.0
+                / totals.nominal_output_active_power as f32;
+        }
+        totals
+    }
+}
+
+/// Characteristics that are refreshed together, each group with its own interval.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CharacteristicGroup {
+    /// Firmware versions and model (0x2A01, 0x2A02).
+    ProductInfo,
+    /// Nominal ratings (0x2A05).
+    Ratings,
+    /// Live readings (0x2A03, 0x2A04, 0x2A11-0x2A14), read on every poll.
+    Live,
+    /// Settings and their limits (0x2A0B-0x2A0E), along with the undecoded charging data
+    /// (0x2A06-0x2A09) which is not worth reading on every poll until it is understood.
+    Parameters,
+}
+
+impl CharacteristicGroup {
+    pub const ALL: [CharacteristicGroup; 4] = [
+        CharacteristicGroup::ProductInfo,
+        CharacteristicGroup::Ratings,
+        CharacteristicGroup::Live,
+        CharacteristicGroup::Parameters,
+    ];
+
+    /// Characteristics read for this group.
+    pub fn characteristics(&self) -> impl Iterator<Item = uuid::Uuid> + '_ {
+        CHARACTERISTIC_PARSERS
+            .iter()
+            .filter(move |(_, group, _)| group == self)
+            .map(|(uuid, _, _)| *uuid)
+    }
+}
+
 #[derive(Debug, Default, Serialize, Deserialize, Clone)]
-pub struct InverterData {
-    // Product info
-    model_type: u8,
+pub struct ProductInfo {
     model_identification: u8,
     topology: u8, // Note: 0 => "transformerless" 1 => "transformer". Unkown from where to get it!
     cpu_version: String,
     blt_version: String,
-    operation_logic: String,
+}
 
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct Ratings {
+    model_type: u8,
+    nominal_ac_voltage: f32,
+    nominal_ac_current: f32,
+    rated_battery_voltage: f32,
+    nominal_output_voltage: f32,
+    nominal_output_frequency: f32,
+    nominal_output_apparent_power: u16,
+    nominal_output_active_power: u16,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct LiveReadings {
     // Basic info
     ac_voltage: f32,
     ac_frequency: f32,
@@ -123,41 +396,44 @@ pub struct InverterData {
     output_active_power: u16,
     load_percentage: u16,
 
-    // Modes
-    output_mode: u8,
-    charge_mode: u8,
-    bulk_charge: i16,
-
-    // Unknown values
+    // Solar charger versions, sent along with the PV readings
     scc_cpu1: String,
     scc_cpu2: String,
     scc_cpu3: String,
     scc_cpu4: String,
-    charging_data1: Vec<u8>,
-    charging_data2: Vec<u8>,
-    ac_charging_data1: Vec<u8>,
-    ac_charging_data2: Vec<u8>,
+
+    // Unknown values
     watts_unkown_01: u16,
     unkown_02: f32,
     unkown_03: u16,
-    discharge_current: u8,
-
-    // Rated information
-    nominal_ac_voltage: f32,
-    nominal_ac_current: f32,
-    rated_battery_voltage: f32,
-    nominal_output_voltage: f32,
-    nominal_output_frequency: f32,
-    nominal_output_apparent_power: u16,
-    nominal_output_active_power: u16,
 
     // Battery info
-    workmode: String,
+    workmode: WorkMode,
     battery_voltage: f32,
     battery_capacity: u16,
     battery_charge_current: u16,
     battery_current_discharge: u16,
 
+    // Event Log (Faults and Warnings)
+    events: Vec<InverterEvent>,
+    raw_event_flags: [u8; 32],
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct Parameters {
+    // Undecoded charging data
+    charging_data1: Vec<u8>,
+    charging_data2: Vec<u8>,
+    ac_charging_data1: Vec<u8>,
+    ac_charging_data2: Vec<u8>,
+
+    // Modes
+    output_mode: OutputMode,
+    charge_mode: ChargeMode,
+    bulk_charge: i16,
+    operation_logic: String,
+    discharge_current: u8,
+
     // Battery parameters
     p_bulk_charging_voltage: f32,
     p_float_charging_voltage: f32,
@@ -183,10 +459,10 @@ pub struct InverterData {
     p_overload_bypass: bool,
     p_lcd_to_default_after_one_min: bool,
     p_fault_code_record: bool,
-    p_charger_source_priority: u8,
-    p_output_source_priotrity: u8,
-    p_ac_input_range: u8,
-    p_battery_type: u8,
+    p_charger_source_priority: ChargerSourcePriority,
+    p_output_source_priotrity: OutputSourcePriority,
+    p_ac_input_range: AcInputRange,
+    p_battery_type: BatteryType,
     p_output_frequency: f32,
     p_output_voltage: u16,
     p_back_to_grid_voltage: f32,
@@ -198,10 +474,19 @@ pub struct InverterData {
     p_min_undervoltage: f32,
     p_max_undervoltage: f32,
     p_bulk_charge_time_range: u8,
+}
 
-    // Event Log (Faults and Warnings)
-    events: Vec<String>,
-    raw_event_flags: [u8; 32],
+/// Inverter data, grouped by how often it changes. The groups are flattened in JSON.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct InverterData {
+    #[serde(flatten)]
+    product: ProductInfo,
+    #[serde(flatten)]
+    ratings: Ratings,
+    #[serde(flatten)]
+    live: LiveReadings,
+    #[serde(flatten)]
+    parameters: Parameters,
 }
 
 impl InverterData {
@@ -221,131 +506,121 @@ impl InverterData {
 
     #[allow(dead_code)]
     pub fn print_inverter_info(&self) {
-        let model_type = match self.model_type {
+        let model_type = match self.ratings.model_type {
             0 => "Grid tie".to_owned(),
             1 => "Off grid".to_owned(),
             _ => "Hybrid".to_owned(),
         };
         println!("Model type: {}", model_type);
-        println!("Main CPU version: {}", self.cpu_version);
-        println!("Bluetooth version: {}", self.blt_version);
+        println!("Main CPU version: {}", self.product.cpu_version);
+        println!("Bluetooth version: {}", self.product.blt_version);
     }
 
     #[allow(dead_code)]
     pub fn print_basic_info(&self) {
-        println!("AC voltage: {}V", self.ac_voltage);
-        println!("AC frequency: {}Hz", self.ac_frequency);
-        println!("PV input voltage: {}V", self.pv_input_voltage_stage1);
-        println!("PV input power: {}W", self.pv_input_power_stage1);
-        println!("PV input voltage: {}V", self.pv_input_voltage_stage2);
-        println!("PV input power: {}W", self.pv_input_power_stage2);
-        println!("PV input voltage: {}V", self.pv_input_voltage_stage3);
-        println!("PV input power: {}W", self.pv_input_power_stage3);
-        println!("PV input voltage: {}V", self.pv_input_voltage_stage4);
-        println!("PV input power: {}W", self.pv_input_power_stage4);
-        println!("Output voltage: {}V", self.output_voltage);
-        println!("Output frequency: {}Hz", self.output_frequency);
-        println!("Output apparent power: {}VA", self.output_apparent_power);
-        println!("Output active power: {}W", self.output_active_power);
-        println!("Load: {}%", self.load_percentage);
-        println!("Nominal AC voltage: {}V", self.nominal_ac_voltage);
-        println!("Nominal AC current: {}A", self.nominal_ac_current);
-        println!("Rated battery voltage: {}V", self.rated_battery_voltage);
-        println!("Nominal output voltage: {}V", self.nominal_output_voltage);
+        println!("AC voltage: {}V", self.live.ac_voltage);
+        println!("AC frequency: {}Hz", self.live.ac_frequency);
+        println!("PV input voltage: {}V", self.live.pv_input_voltage_stage1);
+        println!("PV input power: {}W", self.live.pv_input_power_stage1);
+        println!("PV input voltage: {}V", self.live.pv_input_voltage_stage2);
+        println!("PV input power: {}W", self.live.pv_input_power_stage2);
+        println!("PV input voltage: {}V", self.live.pv_input_voltage_stage3);
+        println!("PV input power: {}W", self.live.pv_input_power_stage3);
+        println!("PV input voltage: {}V", self.live.pv_input_voltage_stage4);
+        println!("PV input power: {}W", self.live.pv_input_power_stage4);
+        println!("Output voltage: {}V", self.live.output_voltage);
+        println!("Output frequency: {}Hz", self.live.output_frequency);
+        println!(
+            "Output apparent power: {}VA",
+            self.live.output_apparent_power
+        );
+        println!("Output active power: {}W", self.live.output_active_power);
+        println!("Load: {}%", self.live.load_percentage);
+        println!("Nominal AC voltage: {}V", self.ratings.nominal_ac_voltage);
+        println!("Nominal AC current: {}A", self.ratings.nominal_ac_current);
+        println!(
+            "Rated battery voltage: {}V",
+            self.ratings.rated_battery_voltage
+        );
+        println!(
+            "Nominal output voltage: {}V",
+            self.ratings.nominal_output_voltage
+        );
         println!(
             "Nominal output frequency: {}Hz",
-            self.nominal_output_frequency
+            self.ratings.nominal_output_frequency
         );
         println!(
             "Nominal output apparent power: {}VA",
-            self.nominal_output_apparent_power
+            self.ratings.nominal_output_apparent_power
         );
         println!(
             "Nominal output active power: {}W",
-            self.nominal_output_active_power
+            self.ratings.nominal_output_active_power
         );
     }
 
     #[allow(dead_code)]
     pub fn print_parameters(&self) {
-        let input_voltage_range = match self.p_ac_input_range {
-            0 => "Appliance",
-            _ => "UPS",
-        };
-        println!("Input voltage range: {}", input_voltage_range);
-
-        let charge_source_str = match self.p_charger_source_priority {
-            0 => "Utility first",
-            1 => "Solar first",
-            2 => "Utility and Solar",
-            3 => "Only Solar Charging",
-            _ => "Only Solar Charging",
-        };
-        println!("Charge source priority: {}", charge_source_str);
-
-        let source_output = match self.p_output_source_priotrity {
-            0 => "USB Priority",
-            1 => "SUB Priority",
-            2 => "SBU Priority",
-            _ => "SBU Priority",
-        };
-        println!("Source output priority: {}", source_output);
-
-        println!("Output voltage: {}", self.p_output_voltage);
-
-        let battery_type = match self.p_battery_type {
-            0 => "AGM",
-            1 => "Flooded",
-            2 => "User define",
-            3 => "Pylon",
-            4 => "WECO",
-            5 => "Other Li battery",
-            _ => "Other Li battery",
-        };
-        println!("Battery type: {}", battery_type);
+        println!("Input voltage range: {}", self.parameters.p_ac_input_range);
+        println!(
+            "Charge source priority: {}",
+            self.parameters.p_charger_source_priority
+        );
+        println!(
+            "Source output priority: {}",
+            self.parameters.p_output_source_priotrity
+        );
+        println!("Output voltage: {}", self.parameters.p_output_voltage);
+        println!("Battery type: {}", self.parameters.p_battery_type);
 
-        println!("Bulk charging voltage: {}V", self.p_bulk_charging_voltage);
-        println!("Float charging voltage: {}V", self.p_float_charging_voltage);
+        println!(
+            "Bulk charging voltage: {}V",
+            self.parameters.p_bulk_charging_voltage
+        );
+        println!(
+            "Float charging voltage: {}V",
+            self.parameters.p_float_charging_voltage
+        );
         println!(
             "Battery cut-off voltage: {}V",
-            self.p_battery_cutoff_voltage
+            self.parameters.p_battery_cutoff_voltage
         );
 
-        let output_mode_str = match self.output_mode {
-            0 => "Single machine output",
-            1 => "Parallel output",
-            2 => "Phase 1 of 3 Phase output",
-            3 => "Phase 2 of 3 Phase output",
-            4 => "Phase 3 of 3 Phase output",
-            _ => "Phase 3 of 3 Phase output",
-        };
-        println!("Output mode: {}", output_mode_str);
-
-        let charge_mode_str = match self.charge_mode {
-            0 => "Auto",
-            1 => "2-stage",
-            2 => "3-stage",
-            _ => "3-stage",
-        };
-        println!("Charge mode: {}", charge_mode_str);
+        println!("Output mode: {}", self.parameters.output_mode);
+        println!("Charge mode: {}", self.parameters.charge_mode);
 
-        if self.bulk_charge == -1 {
+        if self.parameters.bulk_charge == -1 {
             println!("Bulk charge: Auto");
         }
     }
 
     #[allow(dead_code)]
     pub fn print_battery_info(&self) {
-        println!("Workmode: {}", self.workmode);
-        println!("Battery voltage: {}V", self.battery_voltage);
-        println!("Battery capacity: {}%", self.battery_capacity);
-        println!("Battery charge current: {}A", self.battery_charge_current);
+        println!("Workmode: {}", self.live.workmode);
+        println!("Battery voltage: {}V", self.live.battery_voltage);
+        println!("Battery capacity: {}%", self.live.battery_capacity);
+        println!(
+            "Battery charge current: {}A",
+            self.live.battery_charge_current
+        );
         println!(
             "Battery current discharge: {}A",
-            self.battery_current_discharge
+            self.live.battery_current_discharge
         );
     }
 
+    fn expect_len(bytes: &[u8], characteristic: uuid::Uuid, expected: usize) -> ParseResult {
+        if bytes.len() < expected {
+            return Err(ParseError {
+                characteristic,
+                expected,
+                actual: bytes.len(),
+            });
+        }
+        Ok(())
+    }
+
     fn b_to_f32(bytes: [u8; 2], factor: Option<f32>) -> f32 {
         u16::from_le_bytes(bytes) as f32 * factor.unwrap_or(0.1)
     }
@@ -363,39 +638,54 @@ impl InverterData {
         bits_array
     }
 
-    fn parse_0x2a01(&mut self, bytes: Vec<u8>) {
-        self.cpu_version = String::from_utf8_lossy(&bytes[3..11]).into_owned();
-        self.blt_version = String::from_utf8_lossy(&bytes[12..]).into_owned();
+    fn parse_0x2a01(&mut self, bytes: Vec<u8>) -> ParseResult {
+        InverterData::expect_len(&bytes, CHAR_UUID_0X2A01, 12)?;
+
+        self.product.cpu_version = String::from_utf8_lossy(&bytes[3..11]).into_owned();
+        self.product.blt_version = String::from_utf8_lossy(&bytes[12..]).into_owned();
+
+        Ok(())
     }
 
-    fn parse_0x2a02(&mut self, bytes: Vec<u8>) {
-        self.model_identification = bytes[19];
+    fn parse_0x2a02(&mut self, bytes: Vec<u8>) -> ParseResult {
+        InverterData::expect_len(&bytes, CHAR_UUID_0X2A02, 20)?;
+
+        self.product.model_identification = bytes[19];
+
+        Ok(())
     }
 
-    fn parse_0x2a03(&mut self, bytes: Vec<u8>) {
-        self.ac_voltage = InverterData::b_to_f32([bytes[0], bytes[1]], None);
-        self.ac_frequency = InverterData::b_to_f32([bytes[2], bytes[3]], None);
-        self.output_voltage = InverterData::b_to_f32([bytes[4], bytes[5]], None);
-        self.output_frequency = InverterData::b_to_f32([bytes[6], bytes[7]], None);
-        self.output_apparent_power = u16::from_le_bytes([bytes[8], bytes[9]]);
-        self.output_active_power = u16::from_le_bytes([bytes[10], bytes[11]]);
-        self.load_percentage = u16::from_le_bytes([bytes[12], bytes[13]]);
-        self.unkown_02 = InverterData::b_to_f32([bytes[14], bytes[15]], None);
-        self.battery_voltage = u16::from_le_bytes([bytes[16], bytes[17]]) as f32 * 0.01;
-        self.battery_charge_current = u16::from_le_bytes([bytes[18], bytes[19]]);
+    fn parse_0x2a03(&mut self, bytes: Vec<u8>) -> ParseResult {
+        InverterData::expect_len(&bytes, CHAR_UUID_0X2A03, 20)?;
+
+        self.live.ac_voltage = InverterData::b_to_f32([bytes[0], bytes[1]], None);
+        self.live.ac_frequency = InverterData::b_to_f32([bytes[2], bytes[3]], None);
+        self.live.output_voltage = InverterData::b_to_f32([bytes[4], bytes[5]], None);
+        self.live.output_frequency = InverterData::b_to_f32([bytes[6], bytes[7]], None);
+        self.live.output_apparent_power = u16::from_le_bytes([bytes[8], bytes[9]]);
+        self.live.output_active_power = u16::from_le_bytes([bytes[10], bytes[11]]);
+        self.live.load_percentage = u16::from_le_bytes([bytes[12], bytes[13]]);
+        self.live.unkown_02 = InverterData::b_to_f32([bytes[14], bytes[15]], None);
+        self.live.battery_voltage = u16::from_le_bytes([bytes[16], bytes[17]]) as f32 * 0.01;
+        self.live.battery_charge_current = u16::from_le_bytes([bytes[18], bytes[19]]);
+
+        Ok(())
     }
 
-    fn parse_0x2a04(&mut self, bytes: Vec<u8>) {
-        let workmode_binding = [bytes[12]];
-        self.workmode = String::from_utf8_lossy(&workmode_binding).into_owned();
+    fn parse_0x2a04(&mut self, bytes: Vec<u8>) -> ParseResult {
+        InverterData::expect_len(&bytes, CHAR_UUID_0X2A04, 14)?;
+
+        self.live.workmode = WorkMode::from(bytes[12]);
 
         if bytes[13] != 1 {
-            self.watts_unkown_01 = u16::from_le_bytes([bytes[14], bytes[15]]);
+            if let Some(watts) = bytes.get(14..16) {
+                self.live.watts_unkown_01 = u16::from_le_bytes([watts[0], watts[1]]);
+            }
         }
 
-        self.battery_capacity = u16::from_le_bytes([bytes[0], bytes[1]]);
-        self.unkown_03 = u16::from_le_bytes([bytes[2], bytes[3]]);
-        self.battery_current_discharge = u16::from_le_bytes([bytes[4], bytes[5]]);
+        self.live.battery_capacity = u16::from_le_bytes([bytes[0], bytes[1]]);
+        self.live.unkown_03 = u16::from_le_bytes([bytes[2], bytes[3]]);
+        self.live.battery_current_discharge = u16::from_le_bytes([bytes[4], bytes[5]]);
 
         // Fault & Warning codes
         let mut event: Vec<u8> = Vec::new();
@@ -407,77 +697,106 @@ impl InverterData {
 
         // println!("Event flags: {:?}", event);
 
-        self.events = InverterData::parse_event_message(&event);
+        self.live.events = self.parse_event_message(&event);
         if event.len() == 32 {
-            self.raw_event_flags = event.try_into().unwrap_or_default();
+            self.live.raw_event_flags = event.try_into().unwrap_or_default();
         }
+
+        Ok(())
     }
 
-    fn parse_0x2a05(&mut self, bytes: Vec<u8>) {
-        self.model_type = bytes[16];
-        self.nominal_ac_voltage = InverterData::b_to_f32([bytes[0], bytes[1]], None);
-        self.nominal_ac_current = InverterData::b_to_f32([bytes[8], bytes[9]], None);
-        self.rated_battery_voltage = InverterData::b_to_f32([bytes[14], bytes[15]], None);
-        self.nominal_output_voltage = InverterData::b_to_f32([bytes[4], bytes[5]], None);
-        self.nominal_output_frequency = InverterData::b_to_f32([bytes[6], bytes[7]], None);
-        self.nominal_output_apparent_power = u16::from_le_bytes([bytes[10], bytes[11]]);
-        self.nominal_output_active_power = u16::from_le_bytes([bytes[12], bytes[13]]);
+    fn parse_0x2a05(&mut self, bytes: Vec<u8>) -> ParseResult {
+        InverterData::expect_len(&bytes, CHAR_UUID_0X2A05, 17)?;
+
+        self.ratings.model_type = bytes[16];
+        self.ratings.nominal_ac_voltage = InverterData::b_to_f32([bytes[0], bytes[1]], None);
+        self.ratings.nominal_ac_current = InverterData::b_to_f32([bytes[8], bytes[9]], None);
+        self.ratings.rated_battery_voltage = InverterData::b_to_f32([bytes[14], bytes[15]], None);
+        self.ratings.nominal_output_voltage = InverterData::b_to_f32([bytes[4], bytes[5]], None);
+        self.ratings.nominal_output_frequency = InverterData::b_to_f32([bytes[6], bytes[7]], None);
+        self.ratings.nominal_output_apparent_power = u16::from_le_bytes([bytes[10], bytes[11]]);
+        self.ratings.nominal_output_active_power = u16::from_le_bytes([bytes[12], bytes[13]]);
+
+        Ok(())
     }
 
-    fn parse_0x2a06(&mut self, bytes: Vec<u8>) {
+    fn parse_0x2a06(&mut self, bytes: Vec<u8>) -> ParseResult {
         // TODO What contains this package?
-        self.charging_data1 = bytes;
+        self.parameters.charging_data1 = bytes;
+
+        Ok(())
     }
 
-    fn parse_0x2a07(&mut self, bytes: Vec<u8>) {
+    fn parse_0x2a07(&mut self, bytes: Vec<u8>) -> ParseResult {
         // TODO What contains this package?
-        self.charging_data2 = bytes;
+        self.parameters.charging_data2 = bytes;
+
+        Ok(())
     }
 
-    fn parse_0x2a08(&mut self, bytes: Vec<u8>) {
+    fn parse_0x2a08(&mut self, bytes: Vec<u8>) -> ParseResult {
         // TODO What contains this package?
-        self.ac_charging_data1 = bytes;
+        self.parameters.ac_charging_data1 = bytes;
+
+        Ok(())
     }
 
-    fn parse_0x2a09(&mut self, bytes: Vec<u8>) {
+    fn parse_0x2a09(&mut self, bytes: Vec<u8>) -> ParseResult {
         // TODO What contains this package?
-        self.ac_charging_data2 = bytes;
+        self.parameters.ac_charging_data2 = bytes;
+
+        Ok(())
     }
 
-    fn parse_0x2a0b(&mut self, bytes: Vec<u8>) {
-        self.p_min_bulk_voltage = InverterData::b_to_f32([bytes[6], bytes[7]], None);
-        self.p_max_bulk_voltage = InverterData::b_to_f32([bytes[8], bytes[9]], None);
-        self.p_min_undervoltage = InverterData::b_to_f32([bytes[10], bytes[11]], None);
-        self.p_max_undervoltage = InverterData::b_to_f32([bytes[12], bytes[13]], None);
-        self.p_bulk_charge_time_range = bytes[5]; // if 0 => "AUTO" else "Number value"
+    fn parse_0x2a0b(&mut self, bytes: Vec<u8>) -> ParseResult {
+        InverterData::expect_len(&bytes, CHAR_UUID_0X2A0B, 14)?;
+
+        self.parameters.p_min_bulk_voltage = InverterData::b_to_f32([bytes[6], bytes[7]], None);
+        self.parameters.p_max_bulk_voltage = InverterData::b_to_f32([bytes[8], bytes[9]], None);
+        self.parameters.p_min_undervoltage = InverterData::b_to_f32([bytes[10], bytes[11]], None);
+        self.parameters.p_max_undervoltage = InverterData::b_to_f32([bytes[12], bytes[13]], None);
+        self.parameters.p_bulk_charge_time_range = bytes[5]; // if 0 => "AUTO" else "Number value"
 
         // Flags
         // let flags_01 = BitArray::<u32, U8>::from_bytes(&[bytes[14]]);
         // TODO parse this flags
+
+        Ok(())
     }
 
-    fn parse_0x2a0c(&mut self, bytes: Vec<u8>) {
-        self.p_output_voltage = u16::from_le_bytes([bytes[0], bytes[1]]);
-        self.p_output_frequency = InverterData::b_to_f32([bytes[2], bytes[3]], None);
-        self.p_max_charging_current = bytes[4] & 0xff;
-        self.p_max_ac_charging_current = bytes[5] & 0xff;
-        self.p_back_to_grid_voltage = InverterData::b_to_f32([bytes[12], bytes[13]], None);
+    fn parse_0x2a0c(&mut self, bytes: Vec<u8>) -> ParseResult {
+        InverterData::expect_len(&bytes, CHAR_UUID_0X2A0C, 20)?;
+
+        self.parameters.p_output_voltage = u16::from_le_bytes([bytes[0], bytes[1]]);
+        self.parameters.p_output_frequency = InverterData::b_to_f32([bytes[2], bytes[3]], None);
+        self.parameters.p_max_charging_current = bytes[4] & 0xff;
+        self.parameters.p_max_ac_charging_current = bytes[5] & 0xff;
+        self.parameters.p_back_to_grid_voltage =
+            InverterData::b_to_f32([bytes[12], bytes[13]], None);
 
         // if p_back_to_discharge == 0.0 => "FULL"
-        self.p_back_to_discharge_voltage = InverterData::b_to_f32([bytes[14], bytes[15]], None);
-        self.p_bulk_charging_voltage = InverterData::b_to_f32([bytes[8], bytes[9]], None);
-        self.p_float_charging_voltage = InverterData::b_to_f32([bytes[6], bytes[7]], None);
-        self.p_battery_cutoff_voltage = InverterData::b_to_f32([bytes[10], bytes[11]], None);
-        self.p_charger_source_priority = bytes[18];
-        self.p_ac_input_range = bytes[16];
-        self.p_output_source_priotrity = bytes[17];
-        self.p_battery_type = bytes[19];
+        self.parameters.p_back_to_discharge_voltage =
+            InverterData::b_to_f32([bytes[14], bytes[15]], None);
+        self.parameters.p_bulk_charging_voltage =
+            InverterData::b_to_f32([bytes[8], bytes[9]], None);
+        self.parameters.p_float_charging_voltage =
+            InverterData::b_to_f32([bytes[6], bytes[7]], None);
+        self.parameters.p_battery_cutoff_voltage =
+            InverterData::b_to_f32([bytes[10], bytes[11]], None);
+        self.parameters.p_charger_source_priority = ChargerSourcePriority::from(bytes[18]);
+        self.parameters.p_ac_input_range = AcInputRange::from(bytes[16]);
+        self.parameters.p_output_source_priotrity = OutputSourcePriority::from(bytes[17]);
+        self.parameters.p_battery_type = BatteryType::from(bytes[19]);
+
+        Ok(())
     }
 
-    fn parse_0x2a0d(&mut self, bytes: Vec<u8>) {
-        self.output_mode = bytes[2];
-        self.charge_mode = bytes[15];
-        self.bulk_charge = i16::from_le_bytes([bytes[16], bytes[17]]);
+    fn parse_0x2a0d(&mut self, bytes: Vec<u8>) -> ParseResult {
+        InverterData::expect_len(&bytes, CHAR_UUID_0X2A0D, 18)?;
+
+        self.parameters.output_mode = OutputMode::from(bytes[2]);
+        self.parameters.charge_mode = ChargeMode::from(bytes[15]);
+        self.parameters.bulk_charge = i16::from_le_bytes([bytes[16], bytes[17]]);
 
         // Flags
         let flags_01 = BitArray::<u32, U8>::from_bytes(&[bytes[0]]);
@@ -499,28 +818,33 @@ impl InverterData {
         /* TODO [flag_10 == 0 => No permited | flag_30 == 1 => Enabled | flag_30 == 0 => Disabled] */
         // let flag_10 = flags_02.get(5).unwrap_or_default();
 
-        self.p_buzzer_alarm = flag_01; // Buzzer alarm
-        self.p_feed_into_the_grid = flag_02; // Feed into the grid
-        self.p_backlight = flag_03; // Backlight
-        self.p_overload_auto_restart = flag_04; // Overload auto restart
-        self.p_overtemp_auto_restart = flag_05; // Over temperature auto restart
-        self.p_beeps_while_primary_source_interrupt = flag_06; // Beeps while primary source interrupt
-        self.p_must_be_connected_to_pv = flag_21; // All inverters must connected to PV as PV OK
-        self.p_solar_power_balance = flag_22; // Solar power balance
-        self.p_battery_equalization_enable = flag_29; // Battery equalization setting
-        self.p_overload_bypass = flag_30; // Overload bypass
-        self.p_lcd_to_default_after_one_min = flag_31; // LCD screen returns to default display screen after 1 min.
-        self.p_fault_code_record = flag_32; // Fault code record
-
-        self.p_equalization_time = u16::from_le_bytes([bytes[6], bytes[7]]); // minutes
-        self.p_equalization_period = u16::from_le_bytes([bytes[8], bytes[9]]); // days
-        self.p_equalization_timeout = u16::from_le_bytes([bytes[12], bytes[13]]); // minutes
-        self.p_equalization_voltage = InverterData::b_to_f32([bytes[10], bytes[11]], Some(0.01));
-        // TODO self.p_rt_activate_battery_equalization = ; // Real-time activate battery equalization
-    }
-
-    fn parse_0x2a0e(&mut self, bytes: Vec<u8>) {
-        self.operation_logic = match bytes[0] {
+        self.parameters.p_buzzer_alarm = flag_01; // Buzzer alarm
+        self.parameters.p_feed_into_the_grid = flag_02; // Feed into the grid
+        self.parameters.p_backlight = flag_03; // Backlight
+        self.parameters.p_overload_auto_restart = flag_04; // Overload auto restart
+        self.parameters.p_overtemp_auto_restart = flag_05; // Over temperature auto restart
+        self.parameters.p_beeps_while_primary_source_interrupt = flag_06; // Beeps while primary source interrupt
+        self.parameters.p_must_be_connected_to_pv = flag_21; // All inverters must connected to PV as PV OK
+        self.parameters.p_solar_power_balance = flag_22; // Solar power balance
+        self.parameters.p_battery_equalization_enable = flag_29; // Battery equalization setting
+        self.parameters.p_overload_bypass = flag_30; // Overload bypass
+        self.parameters.p_lcd_to_default_after_one_min = flag_31; // LCD screen returns to default display screen after 1 min.
+        self.parameters.p_fault_code_record = flag_32; // Fault code record
+
+        self.parameters.p_equalization_time = u16::from_le_bytes([bytes[6], bytes[7]]); // minutes
+        self.parameters.p_equalization_period = u16::from_le_bytes([bytes[8], bytes[9]]); // days
+        self.parameters.p_equalization_timeout = u16::from_le_bytes([bytes[12], bytes[13]]); // minutes
+        self.parameters.p_equalization_voltage =
+            InverterData::b_to_f32([bytes[10], bytes[11]], Some(0.01));
+        // TODO self.parameters.p_rt_activate_battery_equalization = ; // Real-time activate battery equalization
+
+        Ok(())
+    }
+
+    fn parse_0x2a0e(&mut self, bytes: Vec<u8>) -> ParseResult {
+        InverterData::expect_len(&bytes, CHAR_UUID_0X2A0E, 2)?;
+
+        self.parameters.operation_logic = match bytes[0] {
             0 => "AUTO".to_owned(),
             1 => "ONLINE".to_owned(),
             2 => "ECO".to_owned(),
@@ -528,176 +852,279 @@ impl InverterData {
         };
 
         // NOTE: Discharge current when max discharge current is enabled?
-        self.discharge_current = bytes[1]; // Is Amps
+        self.parameters.discharge_current = bytes[1]; // Is Amps
+
+        Ok(())
     }
 
-    fn parse_0x2a11(&mut self, bytes: Vec<u8>) {
-        self.scc_cpu1 = String::from_utf8_lossy(&bytes[0..8]).into_owned();
-        self.pv_input_voltage_stage1 = InverterData::b_to_f32([bytes[12], bytes[13]], None);
-        self.pv_input_power_stage1 = u16::from_le_bytes([bytes[14], bytes[15]]);
+    fn parse_0x2a11(&mut self, bytes: Vec<u8>) -> ParseResult {
+        InverterData::expect_len(&bytes, CHAR_UUID_0X2A11, 16)?;
+
+        self.live.scc_cpu1 = String::from_utf8_lossy(&bytes[0..8]).into_owned();
+        self.live.pv_input_voltage_stage1 = InverterData::b_to_f32([bytes[12], bytes[13]], None);
+        self.live.pv_input_power_stage1 = u16::from_le_bytes([bytes[14], bytes[15]]);
+
+        Ok(())
     }
 
-    fn parse_0x2a12(&mut self, bytes: Vec<u8>) {
-        self.scc_cpu2 = String::from_utf8_lossy(&bytes[0..8]).into_owned();
-        self.pv_input_voltage_stage2 = InverterData::b_to_f32([bytes[12], bytes[13]], None);
-        self.pv_input_power_stage2 = u16::from_le_bytes([bytes[14], bytes[15]]);
+    fn parse_0x2a12(&mut self, bytes: Vec<u8>) -> ParseResult {
+        InverterData::expect_len(&bytes, CHAR_UUID_0X2A12, 16)?;
+
+        self.live.scc_cpu2 = String::from_utf8_lossy(&bytes[0..8]).into_owned();
+        self.live.pv_input_voltage_stage2 = InverterData::b_to_f32([bytes[12], bytes[13]], None);
+        self.live.pv_input_power_stage2 = u16::from_le_bytes([bytes[14], bytes[15]]);
+
+        Ok(())
     }
 
-    fn parse_0x2a13(&mut self, bytes: Vec<u8>) {
-        self.scc_cpu3 = String::from_utf8_lossy(&bytes[0..8]).into_owned();
-        self.pv_input_voltage_stage3 = InverterData::b_to_f32([bytes[12], bytes[13]], None);
-        self.pv_input_power_stage3 = u16::from_le_bytes([bytes[14], bytes[15]]);
+    fn parse_0x2a13(&mut self, bytes: Vec<u8>) -> ParseResult {
+        InverterData::expect_len(&bytes, CHAR_UUID_0X2A13, 16)?;
+
+        self.live.scc_cpu3 = String::from_utf8_lossy(&bytes[0..8]).into_owned();
+        self.live.pv_input_voltage_stage3 = InverterData::b_to_f32([bytes[12], bytes[13]], None);
+        self.live.pv_input_power_stage3 = u16::from_le_bytes([bytes[14], bytes[15]]);
+
+        Ok(())
     }
 
-    fn parse_0x2a14(&mut self, bytes: Vec<u8>) {
-        self.scc_cpu4 = String::from_utf8_lossy(&bytes[0..8]).into_owned();
-        self.pv_input_voltage_stage4 = InverterData::b_to_f32([bytes[12], bytes[13]], None);
-        self.pv_input_power_stage4 = u16::from_le_bytes([bytes[14], bytes[15]]);
+    fn parse_0x2a14(&mut self, bytes: Vec<u8>) -> ParseResult {
+        InverterData::expect_len(&bytes, CHAR_UUID_0X2A14, 16)?;
+
+        self.live.scc_cpu4 = String::from_utf8_lossy(&bytes[0..8]).into_owned();
+        self.live.pv_input_voltage_stage4 = InverterData::b_to_f32([bytes[12], bytes[13]], None);
+        self.live.pv_input_power_stage4 = u16::from_le_bytes([bytes[14], bytes[15]]);
+
+        Ok(())
     }
 
-    fn parse_event_message(event: &Vec<u8>) -> Vec<String> {
-        let mut events: Vec<String> = Vec::new();
+    /// Describe the event of a bit of the 0x2A04 event flags, `None` for reserved bits.
+    /// Events with a "T" level are faults (EVENT_ID_01) while the inverter is faulted and
+    /// warnings (EVENT_ID_02) otherwise.
+    fn resolve_event(bit: usize, fault: bool) -> Option<InverterEvent> {
+        let (code, level) = match *EVENT_LEVEL.get(bit)? {
+            "Fault" => (EVENT_ID_01[bit], EventLevel::Fault),
+            "Warning" => (EVENT_ID_01[bit], EventLevel::Warning),
+            "T" if fault => (EVENT_ID_01[bit], EventLevel::Fault),
+            "T" => (EVENT_ID_02[bit], EventLevel::Warning),
+            _ => return None,
+        };
 
-        for i in 0..event.len() {
-            let mut event_line = EventLine {
-                id: EVENT_ID_01[i].to_string(),
-                level: EVENT_LEVEL[i].to_string(),
-                message: EVENT_MESSAGE[i].to_string(),
-            };
+        Some(InverterEvent {
+            code: code.to_owned(),
+            level,
+            message: EVENT_MESSAGE[bit].to_owned(),
+            bit,
+            first_seen: Utc::now(),
+        })
+    }
 
-            if event[i] == 1 && EVENT_LEVEL[i] != "" {
-                if EVENT_LEVEL[i] == "T" {
-                    if event[i] == 1 {
-                        event_line.id = EVENT_ID_01[i].to_string();
-                        event_line.level = "Fault".to_owned();
-                    } else {
-                        event_line.id = EVENT_ID_02[i].to_string();
-                        event_line.level = "Warning".to_owned();
-                    }
-                }
+    fn parse_event_message(&self, event: &[u8]) -> Vec<InverterEvent> {
+        let fault =
+            event.get(INVERTER_FAULT_BIT) == Some(&1) || self.live.workmode == WorkMode::Fault;
+        let mut events = Vec::new();
 
-                events.push(event_line.to_string());
+        for (bit, flag) in event.iter().enumerate() {
+            if *flag != 1 {
+                continue;
             }
+            let Some(mut inverter_event) = InverterData::resolve_event(bit, fault) else {
+                continue;
+            };
 
-            // if i != 1 {
-            //     return Some(event_line.to_string());
-            // }
+            // Keep the time the event was first seen for as long as it stays active
+            if let Some(previous) = self
+                .live
+                .events
+                .iter()
+                .find(|previous| previous.bit == bit && previous.code == inverter_event.code)
+            {
+                inverter_event.first_seen = previous.first_seen;
+            }
+            events.push(inverter_event);
         }
 
         events
     }
 
-    pub async fn read_characteristics(&mut self, service: Service) -> bluer::Result<()> {
-        for char in service.characteristics().await? {
-            let uuid = char.uuid().await?;
-
-            match uuid {
-                CHAR_UUID_0X2A01 => {
-                    if char.flags().await?.read {
-                        let value = char.read().await?;
-                        self.parse_0x2a01(value);
-                    }
-                }
-                CHAR_UUID_0X2A02 => {
-                    if char.flags().await?.read {
-                        let value = char.read().await?;
-                        self.parse_0x2a02(value);
-                    }
-                }
-                CHAR_UUID_0X2A03 => {
-                    if char.flags().await?.read {
-                        let value = char.read().await?;
-                        self.parse_0x2a03(value);
-                    }
-                }
-                CHAR_UUID_0X2A04 => {
-                    if char.flags().await?.read {
-                        let value = char.read().await?;
-                        self.parse_0x2a04(value);
-                    }
-                }
-                CHAR_UUID_0X2A05 => {
-                    if char.flags().await?.read {
-                        let value = char.read().await?;
-                        self.parse_0x2a05(value);
-                    }
-                }
-                CHAR_UUID_0X2A06 => {
-                    if char.flags().await?.read {
-                        let value = char.read().await?;
-                        self.parse_0x2a06(value);
-                    }
-                }
-                CHAR_UUID_0X2A07 => {
-                    if char.flags().await?.read {
-                        let value = char.read().await?;
-                        self.parse_0x2a07(value);
-                    }
-                }
-                CHAR_UUID_0X2A08 => {
-                    if char.flags().await?.read {
-                        let value = char.read().await?;
-                        self.parse_0x2a08(value);
-                    }
-                }
-                CHAR_UUID_0X2A09 => {
-                    if char.flags().await?.read {
-                        let value = char.read().await?;
-                        self.parse_0x2a09(value);
-                    }
-                }
-                CHAR_UUID_0X2A0B => {
-                    if char.flags().await?.read {
-                        let value = char.read().await?;
-                        self.parse_0x2a0b(value);
-                    }
-                }
-                CHAR_UUID_0X2A0C => {
-                    if char.flags().await?.read {
-                        let value = char.read().await?;
-                        self.parse_0x2a0c(value);
-                    }
-                }
-                CHAR_UUID_0X2A0D => {
-                    if char.flags().await?.read {
-                        let value = char.read().await?;
-                        self.parse_0x2a0d(value);
-                    }
-                }
-                CHAR_UUID_0X2A0E => {
-                    if char.flags().await?.read {
-                        let value = char.read().await?;
-                        self.parse_0x2a0e(value);
-                    }
-                }
-                CHAR_UUID_0X2A11 => {
-                    if char.flags().await?.read {
-                        let value = char.read().await?;
-                        self.parse_0x2a11(value);
-                    }
-                }
-                CHAR_UUID_0X2A12 => {
-                    if char.flags().await?.read {
-                        let value = char.read().await?;
-                        self.parse_0x2a12(value);
-                    }
+    /// Read and parse the known characteristics of the given groups exposed by the transport,
+    /// recording the outcome of each read. A value that cannot be parsed is reported as a failed read and
+    /// leaves the previous values of that characteristic untouched.
+    /// Fails only if no characteristic could be read at all.
+    pub async fn read_characteristics(
+        &mut self,
+        transport: &dyn GattTransport,
+        groups: &[CharacteristicGroup],
+        reads: &mut BTreeMap<String, CharacteristicRead>,
+    ) -> bluer::Result<()> {
+        let mut last_error = None;
+        let mut successful_reads = 0;
+
+        for (uuid, group, parse) in CHARACTERISTIC_PARSERS {
+            if !groups.contains(&group) {
+                continue;
+            }
+            let read_at = Utc::now();
+            let started = std::time::Instant::now();
+            let result = transport.read(uuid).await;
+            let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+            let id = short_id(&uuid);
+            let last_success = reads.get(&id).and_then(|read| read.last_success);
+            let parse_errors = reads.get(&id).map_or(0, |read| read.parse_errors);
+
+            match result {
+                Ok(Some(value)) => {
+                    successful_reads += 1;
+                    let read = match parse(self, value) {
+                        Ok(()) => CharacteristicRead {
+                            success: true,
+                            read_at,
+                            last_success: Some(read_at),
+                            error: None,
+                            parse_errors,
+                            latency_ms,
+                        },
+                        Err(e) => {
+                            println!("{}", e);
+                            CharacteristicRead {
+                                success: false,
+                                read_at,
+                                last_success,
+                                error: Some(e.to_string()),
+                                parse_errors: parse_errors + 1,
+                                latency_ms,
+                            }
+                        }
+                    };
+                    reads.insert(id, read);
                 }
-                CHAR_UUID_0X2A13 => {
-                    if char.flags().await?.read {
-                        let value = char.read().await?;
-                        self.parse_0x2a13(value);
-                    }
+                Ok(None) => {
+                    reads.remove(&id);
                 }
-                CHAR_UUID_0X2A14 => {
-                    if char.flags().await?.read {
-                        let value = char.read().await?;
-                        self.parse_0x2a14(value);
-                    }
+                Err(e) => {
+                    reads.insert(
+                        id,
+                        CharacteristicRead {
+                            success: false,
+                            read_at,
+                            last_success,
+                            error: Some(e.to_string()),
+                            parse_errors,
+                            latency_ms,
+                        },
+                    );
+                    last_error = Some(e);
                 }
-                _ => {}
             }
         }
 
-        Ok(())
+        match last_error {
+            Some(e) if successful_reads == 0 => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod fuzz;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(read_at: DateTime<Utc>, pv_power: u16, output_power: u16) -> InverterSnapshot {
+        let mut data = InverterData::new();
+        data.live.pv_input_power_stage1 = pv_power;
+        data.live.output_active_power = output_power;
+        InverterSnapshot {
+            data,
+            read_at,
+            characteristics: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn totals_skip_stale_snapshots() {
+        let now = Utc::now();
+        let fresh = snapshot(now - chrono::Duration::seconds(10), 1000, 800);
+        let stale = snapshot(now - chrono::Duration::hours(1), 2000, 1500);
+
+        let totals = InverterTotals::from_snapshots(
+            [("a", &fresh), ("b", &stale)],
+            now,
+            chrono::Duration::seconds(120),
+        );
+        assert_eq!(totals.inverters, 1);
+        assert_eq!(totals.stale_inverters, vec!["b".to_owned()]);
+        assert_eq!(totals.pv_input_power, 1000);
+        assert_eq!(totals.output_active_power, 800);
+        assert_eq!(totals.read_at, Some(fresh.read_at));
+    }
+
+    /// Event flags with the given bits set.
+    fn flags(bits: &[usize]) -> Vec<u8> {
+        (0..32).map(|bit| bits.contains(&bit) as u8).collect()
+    }
+
+    fn codes(events: &[InverterEvent]) -> Vec<(&str, EventLevel)> {
+        events
+            .iter()
+            .map(|event| (event.code.as_str(), event.level))
+            .collect()
+    }
+
+    #[test]
+    fn t_level_events_are_warnings_without_a_fault() {
+        let data = InverterData::new();
+        // Bit 9 is a "T" level event, 30 is reserved
+        let events = data.parse_event_message(&flags(&[0, 9, 30]));
+        assert_eq!(
+            codes(&events),
+            [("1000", EventLevel::Warning), ("2003", EventLevel::Warning)]
+        );
+        assert_eq!(events[1].message, EVENT_MESSAGE[9]);
+    }
+
+    #[test]
+    fn t_level_events_are_faults_with_the_fault_bit() {
+        let data = InverterData::new();
+        let events = data.parse_event_message(&flags(&[INVERTER_FAULT_BIT, 9, 16]));
+        assert_eq!(
+            codes(&events),
+            [
+                ("1001", EventLevel::Fault),
+                ("1007", EventLevel::Fault),
+                ("1010", EventLevel::Fault)
+            ]
+        );
+    }
+
+    #[test]
+    fn t_level_events_are_faults_in_fault_mode() {
+        let mut data = InverterData::new();
+        data.live.workmode = WorkMode::Fault;
+        let events = data.parse_event_message(&flags(&[10, 12]));
+        assert_eq!(
+            codes(&events),
+            [("1008", EventLevel::Fault), ("2006", EventLevel::Warning)]
+        );
+    }
+
+    #[test]
+    fn first_seen_is_kept_while_the_event_is_active() {
+        let mut data = InverterData::new();
+        let started = Utc::now() - chrono::Duration::hours(1);
+        data.live.events = data.parse_event_message(&flags(&[9, 12]));
+        for event in data.live.events.iter_mut() {
+            event.first_seen = started;
+        }
+
+        let events = data.parse_event_message(&flags(&[9, 12]));
+        assert!(events.iter().all(|event| event.first_seen == started));
+
+        // Bit 9 turns into a fault, a different event, while bit 12 stays the same
+        let events = data.parse_event_message(&flags(&[INVERTER_FAULT_BIT, 9, 12]));
+        assert_eq!(codes(&events)[1], ("1007", EventLevel::Fault));
+        assert!(events[1].first_seen > started);
+        assert_eq!(events[2].first_seen, started);
     }
 }
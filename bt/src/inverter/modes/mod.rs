@@ -0,0 +1,296 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+/// Raw value accepted when deserializing a mode: its stable name or the inverter value.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawMode {
+    Value(u8),
+    Name(String),
+}
+
+/// Declare an inverter mode enum with an `Unknown(u8)` fallback.
+/// Every variant has the raw inverter value, a stable name used in the JSON API and Influx
+/// tags, and a human readable description.
+macro_rules! inverter_mode {
+    (
+        $(#[$meta:meta])*
+        $enum_name:ident {
+            $($variant:ident = $value:expr, $name:expr, $description:expr;)*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum $enum_name {
+            $($variant,)*
+            Unknown(u8),
+        }
+
+        impl $enum_name {
+            /// Stable name, e.g. `UNKNOWN_7` for unknown values.
+            pub fn name(&self) -> String {
+                match self {
+                    $($enum_name::$variant => $name.to_owned(),)*
+                    $enum_name::Unknown(value) => format!("UNKNOWN_{}", value),
+                }
+            }
+
+            pub fn from_name(name: &str) -> Option<Self> {
+                match name {
+                    $($name => Some($enum_name::$variant),)*
+                    _ => name
+                        .strip_prefix("UNKNOWN_")
+                        .and_then(|value| value.parse::<u8>().ok())
+                        .map($enum_name::from),
+                }
+            }
+        }
+
+        impl Default for $enum_name {
+            fn default() -> Self {
+                $enum_name::Unknown(0)
+            }
+        }
+
+        impl From<u8> for $enum_name {
+            fn from(value: u8) -> Self {
+                match value {
+                    $($value => $enum_name::$variant,)*
+                    _ => $enum_name::Unknown(value),
+                }
+            }
+        }
+
+        impl From<$enum_name> for u8 {
+            fn from(mode: $enum_name) -> Self {
+                match mode {
+                    $($enum_name::$variant => $value,)*
+                    $enum_name::Unknown(value) => value,
+                }
+            }
+        }
+
+        impl fmt::Display for $enum_name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                    $($enum_name::$variant => write!(f, "{}", $description),)*
+                    $enum_name::Unknown(value) => write!(f, "Unknown ({})", value),
+                }
+            }
+        }
+
+        impl Serialize for $enum_name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(&self.name())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $enum_name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                match RawMode::deserialize(deserializer)? {
+                    RawMode::Value(value) => Ok($enum_name::from(value)),
+                    RawMode::Name(name) => $enum_name::from_name(&name).ok_or_else(|| {
+                        serde::de::Error::custom(format!(
+                            "unknown {} {}",
+                            stringify!($enum_name),
+                            name
+                        ))
+                    }),
+                }
+            }
+        }
+    };
+}
+
+inverter_mode! {
+    /// Output source priority (setting program 01).
+    OutputSourcePriority {
+        UtilitySolarBattery = 0, "USB", "USB Priority";
+        SolarUtilityBattery = 1, "SUB", "SUB Priority";
+        SolarBatteryUtility = 2, "SBU", "SBU Priority";
+    }
+}
+
+inverter_mode! {
+    /// Charger source priority (setting program 16).
+    ChargerSourcePriority {
+        UtilityFirst = 0, "UTILITY_FIRST", "Utility first";
+        SolarFirst = 1, "SOLAR_FIRST", "Solar first";
+        UtilityAndSolar = 2, "UTILITY_AND_SOLAR", "Utility and Solar";
+        OnlySolar = 3, "ONLY_SOLAR", "Only Solar Charging";
+    }
+}
+
+inverter_mode! {
+    /// AC input voltage range (setting program 03).
+    AcInputRange {
+        Appliance = 0, "APPLIANCE", "Appliance";
+        Ups = 1, "UPS", "UPS";
+    }
+}
+
+inverter_mode! {
+    /// Battery type (setting program 05).
+    BatteryType {
+        Agm = 0, "AGM", "AGM";
+        Flooded = 1, "FLOODED", "Flooded";
+        UserDefined = 2, "USER_DEFINED", "User define";
+        Pylon = 3, "PYLON", "Pylon";
+        Weco = 4, "WECO", "WECO";
+        OtherLithium = 5, "OTHER_LITHIUM", "Other Li battery";
+    }
+}
+
+inverter_mode! {
+    /// Output mode of a single, parallel or 3-phase install.
+    OutputMode {
+        Single = 0, "SINGLE", "Single machine output";
+        Parallel = 1, "PARALLEL", "Parallel output";
+        Phase1Of3 = 2, "PHASE_1_OF_3", "Phase 1 of 3 Phase output";
+        Phase2Of3 = 3, "PHASE_2_OF_3", "Phase 2 of 3 Phase output";
+        Phase3Of3 = 4, "PHASE_3_OF_3", "Phase 3 of 3 Phase output";
+    }
+}
+
+inverter_mode! {
+    /// Battery charge mode.
+    ChargeMode {
+        Auto = 0, "AUTO", "Auto";
+        TwoStage = 1, "TWO_STAGE", "2-stage";
+        ThreeStage = 2, "THREE_STAGE", "3-stage";
+    }
+}
+
+inverter_mode! {
+    /// Inverter work mode, reported as an ASCII letter.
+    WorkMode {
+        PowerOn = b'P', "POWER_ON", "Power on";
+        Standby = b'S', "STANDBY", "Stand by";
+        Line = b'L', "LINE", "Line";
+        Battery = b'B', "BATTERY", "Battery";
+        Fault = b'F', "FAULT", "Fault";
+        PowerSaving = b'H', "POWER_SAVING", "Power saving";
+        Charge = b'C', "CHARGE", "Charge";
+        Shutdown = b'D', "SHUTDOWN", "Shutdown";
+        Eco = b'E', "ECO", "ECO";
+        Bypass = b'Y', "BYPASS", "Bypass";
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check the raw value, stable name and JSON of every variant of a mode.
+    macro_rules! assert_modes {
+        ($enum_name:ident, [$(($value:expr, $variant:ident, $name:expr)),* $(,)?]) => {
+            $(
+                let mode = $enum_name::$variant;
+                assert_eq!($enum_name::from($value), mode);
+                assert_eq!(u8::from(mode), $value);
+                assert_eq!(mode.name(), $name);
+                assert_eq!($enum_name::from_name($name), Some(mode));
+                assert_eq!(serde_json::to_string(&mode).unwrap(), format!("\"{}\"", $name));
+                let json = format!("\"{}\"", $name);
+                assert_eq!(serde_json::from_str::<$enum_name>(&json).unwrap(), mode);
+                let json = $value.to_string();
+                assert_eq!(serde_json::from_str::<$enum_name>(&json).unwrap(), mode);
+            )*
+        };
+    }
+
+    #[test]
+    fn modes_have_stable_names() {
+        assert_modes!(
+            OutputSourcePriority,
+            [
+                (0, UtilitySolarBattery, "USB"),
+                (1, SolarUtilityBattery, "SUB"),
+                (2, SolarBatteryUtility, "SBU"),
+            ]
+        );
+        assert_modes!(
+            ChargerSourcePriority,
+            [
+                (0, UtilityFirst, "UTILITY_FIRST"),
+                (1, SolarFirst, "SOLAR_FIRST"),
+                (2, UtilityAndSolar, "UTILITY_AND_SOLAR"),
+                (3, OnlySolar, "ONLY_SOLAR"),
+            ]
+        );
+        assert_modes!(AcInputRange, [(0, Appliance, "APPLIANCE"), (1, Ups, "UPS")]);
+        assert_modes!(
+            BatteryType,
+            [
+                (0, Agm, "AGM"),
+                (1, Flooded, "FLOODED"),
+                (2, UserDefined, "USER_DEFINED"),
+                (3, Pylon, "PYLON"),
+                (4, Weco, "WECO"),
+                (5, OtherLithium, "OTHER_LITHIUM"),
+            ]
+        );
+        assert_modes!(
+            OutputMode,
+            [
+                (0, Single, "SINGLE"),
+                (1, Parallel, "PARALLEL"),
+                (2, Phase1Of3, "PHASE_1_OF_3"),
+                (3, Phase2Of3, "PHASE_2_OF_3"),
+                (4, Phase3Of3, "PHASE_3_OF_3"),
+            ]
+        );
+        assert_modes!(
+            ChargeMode,
+            [
+                (0, Auto, "AUTO"),
+                (1, TwoStage, "TWO_STAGE"),
+                (2, ThreeStage, "THREE_STAGE"),
+            ]
+        );
+        assert_modes!(
+            WorkMode,
+            [
+                (b'P', PowerOn, "POWER_ON"),
+                (b'S', Standby, "STANDBY"),
+                (b'L', Line, "LINE"),
+                (b'B', Battery, "BATTERY"),
+                (b'F', Fault, "FAULT"),
+                (b'H', PowerSaving, "POWER_SAVING"),
+                (b'C', Charge, "CHARGE"),
+                (b'D', Shutdown, "SHUTDOWN"),
+                (b'E', Eco, "ECO"),
+                (b'Y', Bypass, "BYPASS"),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_values_are_kept() {
+        let mode = BatteryType::from(9);
+        assert_eq!(mode, BatteryType::Unknown(9));
+        assert_eq!(u8::from(mode), 9);
+        assert_eq!(mode.name(), "UNKNOWN_9");
+        assert_eq!(mode.to_string(), "Unknown (9)");
+        assert_eq!(BatteryType::default(), BatteryType::Unknown(0));
+        assert_eq!(BatteryType::from_name("UNKNOWN_9"), Some(mode));
+
+        assert_eq!(serde_json::to_string(&mode).unwrap(), "\"UNKNOWN_9\"");
+        assert_eq!(
+            serde_json::from_str::<BatteryType>("\"UNKNOWN_9\"").unwrap(),
+            mode
+        );
+        assert_eq!(serde_json::from_str::<BatteryType>("9").unwrap(), mode);
+        // An unknown value named like a known one is the known mode
+        assert_eq!(
+            serde_json::from_str::<BatteryType>("\"UNKNOWN_3\"").unwrap(),
+            BatteryType::Pylon
+        );
+
+        assert_eq!(BatteryType::from_name("LEAD"), None);
+        assert_eq!(BatteryType::from_name("UNKNOWN_256"), None);
+        let error = serde_json::from_str::<BatteryType>("\"LEAD\"").unwrap_err();
+        assert!(error.to_string().contains("unknown BatteryType LEAD"));
+        assert!(serde_json::from_str::<WorkMode>("256").is_err());
+    }
+}
@@ -0,0 +1,280 @@
+use crate::config::INVERTER_BT_ADDRESS;
+use crate::inverter::bt::{select_adapter, BluerTransport};
+use crate::inverter::SERVICE_UUID_0X1810;
+use bluer::agent::Agent;
+use bluer::{Adapter, AdapterEvent, Address};
+use futures::{pin_mut, StreamExt};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use tokio::time::timeout;
+
+/// A device seen during `bt scan`.
+#[derive(Debug)]
+pub struct Candidate {
+    pub address: Address,
+    pub name: Option<String>,
+    pub rssi: Option<i16>,
+    /// Whether the device advertises the inverter service (0x1810).
+    pub inverter: bool,
+}
+
+/// Discover nearby devices for `duration`, strongest signal first.
+pub async fn scan(adapter: Option<&str>, duration: Duration) -> bluer::Result<Vec<Candidate>> {
+    let session = bluer::Session::new().await?;
+    let adapter = select_adapter(&session, adapter).await?;
+    adapter.set_powered(true).await?;
+    println!(
+        "Scanning for {}s using Bluetooth adapter {}...",
+        duration.as_secs(),
+        adapter.name()
+    );
+
+    let mut addresses = BTreeSet::new();
+    {
+        let device_events = adapter.discover_devices().await?;
+        pin_mut!(device_events);
+        let _ = timeout(duration, async {
+            while let Some(device_event) = device_events.next().await {
+                if let AdapterEvent::DeviceAdded(address) = device_event {
+                    addresses.insert(address);
+                }
+            }
+        })
+        .await;
+    }
+
+    let mut candidates = Vec::new();
+    for address in addresses {
+        let Ok(device) = adapter.device(address) else {
+            continue;
+        };
+        let uuids = device.uuids().await.ok().flatten().unwrap_or_default();
+        candidates.push(Candidate {
+            address,
+            name: device.name().await.ok().flatten(),
+            rssi: device.rssi().await.ok().flatten(),
+            inverter: uuids.contains(&SERVICE_UUID_0X1810),
+        });
+    }
+    candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.rssi.unwrap_or(i16::MIN)));
+    Ok(candidates)
+}
+
+/// Discover, pair and trust the inverter at `address` with the passkey agent of the service.
+/// A previous pairing is removed first so the inverter always ends up in a known state.
+pub async fn pair(
+    address: Address,
+    adapter: Option<&str>,
+    discovery_timeout: Duration,
+) -> bluer::Result<()> {
+    let session = bluer::Session::new().await?;
+    let _agent = session
+        .register_agent(Agent {
+            request_default: true,
+            request_passkey: Some(Box::new(|req| {
+                Box::pin(BluerTransport::request_passkey(req))
+            })),
+            ..Default::default()
+        })
+        .await?;
+    let adapter = select_adapter(&session, adapter).await?;
+    adapter.set_powered(true).await?;
+
+    remove(&adapter, address).await?;
+
+    println!(
+        "Discovering {} using Bluetooth adapter {}...",
+        address,
+        adapter.name()
+    );
+    {
+        let device_events = adapter.discover_devices().await?;
+        pin_mut!(device_events);
+        let discovery = async {
+            while let Some(device_event) = device_events.next().await {
+                if matches!(device_event, AdapterEvent::DeviceAdded(addr) if addr == address) {
+                    return true;
+                }
+            }
+            false
+        };
+        if !matches!(timeout(discovery_timeout, discovery).await, Ok(true)) {
+            return Err(bluer::Error {
+                kind: bluer::ErrorKind::NotFound,
+                message: format!(
+                    "Device {} not found after {}s",
+                    address,
+                    discovery_timeout.as_secs()
+                ),
+            });
+        }
+    }
+
+    let device = adapter.device(address)?;
+    println!("Pairing {}...", address);
+    device.pair().await?;
+    device.set_trusted(true).await?;
+    println!(
+        "Paired and trusted {} ({})",
+        address,
+        device.name().await?.unwrap_or_default()
+    );
+    Ok(())
+}
+
+/// Remove the pairing of the inverter at `address`.
+pub async fn unpair(address: Address, adapter: Option<&str>) -> bluer::Result<()> {
+    let session = bluer::Session::new().await?;
+    let adapter = select_adapter(&session, adapter).await?;
+    remove(&adapter, address).await
+}
+
+/// Remove the device from BlueZ, doing nothing if it is unknown.
+async fn remove(adapter: &Adapter, address: Address) -> bluer::Result<()> {
+    if !adapter.device_addresses().await?.contains(&address) {
+        return Ok(());
+    }
+    adapter.remove_device(address).await?;
+    println!("Removed previous pairing of {}", address);
+    Ok(())
+}
+
+/// Add the address to the inverter addresses of the config file, or remove it. The key is
+/// removed along with the last address, the service refusing an empty list.
+pub fn update_config(path: &Path, address: Address, add: bool) -> Result<(), String> {
+    let config = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let address = address.to_string();
+    let mut found = false;
+    let mut lines = Vec::new();
+
+    for line in config.lines() {
+        let assignment = line.trim_start();
+        let (export, assignment) = match assignment.strip_prefix("export ") {
+            Some(rest) => ("export ", rest.trim_start()),
+            None => ("", assignment),
+        };
+        let Some(value) = assignment
+            .strip_prefix(INVERTER_BT_ADDRESS)
+            .and_then(|rest| rest.trim_start().strip_prefix('='))
+        else {
+            lines.push(line.to_owned());
+            continue;
+        };
+        found = true;
+        let value = value.trim();
+        let value = ['"', '\'']
+            .iter()
+            .find_map(|quote| value.strip_prefix(*quote)?.strip_suffix(*quote))
+            .unwrap_or(value);
+        let mut addresses: Vec<String> = value
+            .split(',')
+            .map(|address| address.trim().to_uppercase())
+            .filter(|address| !address.is_empty())
+            .collect();
+        if !add {
+            addresses.retain(|other| *other != address);
+        } else if !addresses.contains(&address) {
+            addresses.push(address.clone());
+        }
+        if !addresses.is_empty() {
+            lines.push(format!(
+                "{}{}=\"{}\"",
+                export,
+                INVERTER_BT_ADDRESS,
+                addresses.join(",")
+            ));
+        }
+    }
+    if !found && add {
+        lines.push(format!("{}=\"{}\"", INVERTER_BT_ADDRESS, address));
+    }
+
+    let mut config = lines.join("\n");
+    config.push('\n');
+    fs::write(path, config).map_err(|e| e.to_string())?;
+    println!("Updated {} in {}", INVERTER_BT_ADDRESS, path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIRST: &str = "48:70:1E:53:38:FC";
+    const SECOND: &str = "48:70:1E:53:38:FD";
+
+    /// Run `update_config` on a temporary config file and return the result.
+    fn update(name: &str, config: &str, address: &str, add: bool) -> String {
+        let path = std::env::temp_dir().join(name);
+        fs::write(&path, config).unwrap();
+        update_config(&path, address.parse().unwrap(), add).unwrap();
+        let config = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        config
+    }
+
+    #[test]
+    fn addresses_are_added() {
+        let config = update("bt_pairing_add.env", "WEB_SERVER_PORT=9999\n", FIRST, true);
+        assert_eq!(
+            config,
+            format!("WEB_SERVER_PORT=9999\nINVERTER_BT_ADDRESS=\"{}\"\n", FIRST)
+        );
+
+        let config = format!("INVERTER_BT_ADDRESS=\"{}\"\nWEB_SERVER_PORT=9999\n", FIRST);
+        let config = update("bt_pairing_add_second.env", &config, SECOND, true);
+        assert_eq!(
+            config,
+            format!(
+                "INVERTER_BT_ADDRESS=\"{},{}\"\nWEB_SERVER_PORT=9999\n",
+                FIRST, SECOND
+            )
+        );
+    }
+
+    #[test]
+    fn duplicate_addresses_are_not_added() {
+        let config = format!("INVERTER_BT_ADDRESS=\"{}\"\n", FIRST.to_lowercase());
+        let config = update("bt_pairing_duplicate.env", &config, FIRST, true);
+        assert_eq!(config, format!("INVERTER_BT_ADDRESS=\"{}\"\n", FIRST));
+    }
+
+    #[test]
+    fn addresses_are_removed() {
+        let config = format!(
+            "# Inverters\nINVERTER_BT_ADDRESS=\"{},{}\"\n",
+            FIRST, SECOND
+        );
+        let config = update("bt_pairing_remove.env", &config, FIRST, false);
+        assert_eq!(
+            config,
+            format!("# Inverters\nINVERTER_BT_ADDRESS=\"{}\"\n", SECOND)
+        );
+    }
+
+    #[test]
+    fn key_is_removed_with_the_last_address() {
+        let config = format!("INVERTER_BT_ADDRESS=\"{}\"\nWEB_SERVER_PORT=9999\n", FIRST);
+        let config = update("bt_pairing_remove_last.env", &config, FIRST, false);
+        assert_eq!(config, "WEB_SERVER_PORT=9999\n");
+    }
+
+    #[test]
+    fn quoted_and_exported_values_are_updated() {
+        let config = format!("INVERTER_BT_ADDRESS='{}'\n", FIRST);
+        let config = update("bt_pairing_single_quotes.env", &config, SECOND, true);
+        assert_eq!(
+            config,
+            format!("INVERTER_BT_ADDRESS=\"{},{}\"\n", FIRST, SECOND)
+        );
+
+        let config = format!("export INVERTER_BT_ADDRESS={}\n", FIRST);
+        let config = update("bt_pairing_export.env", &config, SECOND, true);
+        assert_eq!(
+            config,
+            format!("export INVERTER_BT_ADDRESS=\"{},{}\"\n", FIRST, SECOND)
+        );
+    }
+}
@@ -0,0 +1,557 @@
+use super::modes::{AcInputRange, BatteryType, ChargerSourcePriority, OutputSourcePriority};
+use super::{InverterData, CHAR_UUID_0X2A0C, CHAR_UUID_0X2A0D};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, oneshot};
+
+// Limits from the Axpert VM III setting programs 02 and 11
+const MIN_CHARGING_CURRENT: u8 = 10;
+const MAX_CHARGING_CURRENT: u8 = 80;
+const MIN_AC_CHARGING_CURRENT: u8 = 2;
+const MAX_AC_CHARGING_CURRENT: u8 = 60;
+
+/// Tolerance used when comparing a written voltage with the value read back.
+const VOLTAGE_TOLERANCE: f32 = 0.05;
+
+/// A single inverter setting that can be changed over Bluetooth.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "parameter", content = "value", rename_all = "snake_case")]
+pub enum InverterParameter {
+    // 0x2A0C settings
+    OutputSourcePriority(OutputSourcePriority),
+    ChargerSourcePriority(ChargerSourcePriority),
+    AcInputRange(AcInputRange),
+    BatteryType(BatteryType),
+    MaxChargingCurrent(u8),
+    MaxAcChargingCurrent(u8),
+    BulkChargingVoltage(f32),
+    FloatChargingVoltage(f32),
+    BatteryCutoffVoltage(f32),
+    BackToGridVoltage(f32),
+    BackToDischargeVoltage(f32),
+
+    // 0x2A0D flags
+    BuzzerAlarm(bool),
+    Backlight(bool),
+    OverloadAutoRestart(bool),
+    OvertempAutoRestart(bool),
+    BeepsWhilePrimarySourceInterrupt(bool),
+    OverloadBypass(bool),
+    LcdToDefaultAfterOneMin(bool),
+    FaultCodeRecord(bool),
+    BatteryEqualization(bool),
+}
+
+impl InverterParameter {
+    /// Characteristic that holds this parameter.
+    pub fn characteristic(&self) -> uuid::Uuid {
+        match self {
+            InverterParameter::OutputSourcePriority(_)
+            | InverterParameter::ChargerSourcePriority(_)
+            | InverterParameter::AcInputRange(_)
+            | InverterParameter::BatteryType(_)
+            | InverterParameter::MaxChargingCurrent(_)
+            | InverterParameter::MaxAcChargingCurrent(_)
+            | InverterParameter::BulkChargingVoltage(_)
+            | InverterParameter::FloatChargingVoltage(_)
+            | InverterParameter::BatteryCutoffVoltage(_)
+            | InverterParameter::BackToGridVoltage(_)
+            | InverterParameter::BackToDischargeVoltage(_) => CHAR_UUID_0X2A0C,
+            _ => CHAR_UUID_0X2A0D,
+        }
+    }
+
+    /// Patch the current raw value of the characteristic with the new parameter value.
+    pub fn encode(&self, bytes: &mut [u8]) -> Result<(), String> {
+        let required = match self.characteristic() {
+            CHAR_UUID_0X2A0C => 20,
+            _ => 2,
+        };
+        if bytes.len() < required {
+            return Err(format!(
+                "Invalid characteristic length {} for {:?}, expected at least {}",
+                bytes.len(),
+                self,
+                required
+            ));
+        }
+
+        match *self {
+            InverterParameter::OutputSourcePriority(value) => bytes[17] = value.into(),
+            InverterParameter::ChargerSourcePriority(value) => bytes[18] = value.into(),
+            InverterParameter::AcInputRange(value) => bytes[16] = value.into(),
+            InverterParameter::BatteryType(value) => bytes[19] = value.into(),
+            InverterParameter::MaxChargingCurrent(value) => bytes[4] = value,
+            InverterParameter::MaxAcChargingCurrent(value) => bytes[5] = value,
+            InverterParameter::FloatChargingVoltage(value) => {
+                bytes[6..8].copy_from_slice(&f32_to_b(value, 0.1))
+            }
+            InverterParameter::BulkChargingVoltage(value) => {
+                bytes[8..10].copy_from_slice(&f32_to_b(value, 0.1))
+            }
+            InverterParameter::BatteryCutoffVoltage(value) => {
+                bytes[10..12].copy_from_slice(&f32_to_b(value, 0.1))
+            }
+            InverterParameter::BackToGridVoltage(value) => {
+                bytes[12..14].copy_from_slice(&f32_to_b(value, 0.1))
+            }
+            InverterParameter::BackToDischargeVoltage(value) => {
+                bytes[14..16].copy_from_slice(&f32_to_b(value, 0.1))
+            }
+            _ => {
+                let (index, mask) = self.flag_position();
+                if self.flag_value() {
+                    bytes[index] |= mask;
+                } else {
+                    bytes[index] &= !mask;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Byte index and bit mask of a 0x2A0D flag (see `InverterData::parse_0x2a0d`).
+    fn flag_position(&self) -> (usize, u8) {
+        match self {
+            InverterParameter::OverloadBypass(_) => (0, 0x80),
+            InverterParameter::LcdToDefaultAfterOneMin(_) => (0, 0x20),
+            InverterParameter::OverloadAutoRestart(_) => (0, 0x10),
+            InverterParameter::OvertempAutoRestart(_) => (0, 0x08),
+            InverterParameter::Backlight(_) => (0, 0x04),
+            InverterParameter::BeepsWhilePrimarySourceInterrupt(_) => (0, 0x02),
+            InverterParameter::FaultCodeRecord(_) => (0, 0x01),
+            InverterParameter::BatteryEqualization(_) => (1, 0x02),
+            InverterParameter::BuzzerAlarm(_) => (1, 0x01),
+            _ => unreachable!("{:?} is not a flag parameter", self),
+        }
+    }
+
+    fn flag_value(&self) -> bool {
+        match *self {
+            InverterParameter::BuzzerAlarm(value)
+            | InverterParameter::Backlight(value)
+            | InverterParameter::OverloadAutoRestart(value)
+            | InverterParameter::OvertempAutoRestart(value)
+            | InverterParameter::BeepsWhilePrimarySourceInterrupt(value)
+            | InverterParameter::OverloadBypass(value)
+            | InverterParameter::LcdToDefaultAfterOneMin(value)
+            | InverterParameter::FaultCodeRecord(value)
+            | InverterParameter::BatteryEqualization(value) => value,
+            _ => false,
+        }
+    }
+}
+
+fn f32_to_b(value: f32, factor: f32) -> [u8; 2] {
+    ((value / factor).round() as u16).to_le_bytes()
+}
+
+fn check_range<T: PartialOrd + std::fmt::Display>(
+    name: &str,
+    value: T,
+    min: T,
+    max: T,
+) -> Result<(), String> {
+    if value < min || value > max {
+        return Err(format!(
+            "{} {} out of range [{}, {}]",
+            name, value, min, max
+        ));
+    }
+    Ok(())
+}
+
+impl InverterData {
+    /// Check a parameter change against the limits reported by the inverter. Battery voltages
+    /// are rejected until the inverter has reported their limits (never over PI30).
+    pub fn validate_parameter(&self, parameter: &InverterParameter) -> Result<(), String> {
+        let voltage_limits =
+            self.parameters.p_max_bulk_voltage != 0.0 && self.parameters.p_max_undervoltage != 0.0;
+
+        match *parameter {
+            InverterParameter::BulkChargingVoltage(_)
+            | InverterParameter::FloatChargingVoltage(_)
+            | InverterParameter::BatteryCutoffVoltage(_)
+            | InverterParameter::BackToGridVoltage(_)
+            | InverterParameter::BackToDischargeVoltage(_)
+                if !voltage_limits =>
+            {
+                Err("Battery voltage limits have not been reported by the inverter".to_owned())
+            }
+            InverterParameter::OutputSourcePriority(OutputSourcePriority::Unknown(value))
+            | InverterParameter::ChargerSourcePriority(ChargerSourcePriority::Unknown(value))
+            | InverterParameter::AcInputRange(AcInputRange::Unknown(value))
+            | InverterParameter::BatteryType(BatteryType::Unknown(value)) => {
+                Err(format!("Unsupported value {} for {:?}", value, parameter))
+            }
+            InverterParameter::MaxChargingCurrent(value) => check_range(
+                "Max charging current",
+                value,
+                MIN_CHARGING_CURRENT,
+                MAX_CHARGING_CURRENT,
+            ),
+            InverterParameter::MaxAcChargingCurrent(value) => check_range(
+                "Max AC charging current",
+                value,
+                MIN_AC_CHARGING_CURRENT,
+                MAX_AC_CHARGING_CURRENT,
+            ),
+            InverterParameter::BulkChargingVoltage(value) => check_range(
+                "Bulk charging voltage",
+                value,
+                self.parameters
+                    .p_min_bulk_voltage
+                    .max(self.parameters.p_float_charging_voltage),
+                self.parameters.p_max_bulk_voltage,
+            ),
+            InverterParameter::FloatChargingVoltage(value) => check_range(
+                "Float charging voltage",
+                value,
+                self.parameters.p_min_bulk_voltage,
+                self.parameters
+                    .p_bulk_charging_voltage
+                    .min(self.parameters.p_max_bulk_voltage),
+            ),
+            InverterParameter::BatteryCutoffVoltage(value) => check_range(
+                "Battery cut-off voltage",
+                value,
+                self.parameters.p_min_undervoltage,
+                self.parameters.p_max_undervoltage,
+            ),
+            InverterParameter::BackToGridVoltage(value) => check_range(
+                "Back to grid voltage",
+                value,
+                self.parameters.p_battery_cutoff_voltage,
+                self.parameters.p_bulk_charging_voltage,
+            ),
+            InverterParameter::BackToDischargeVoltage(value) => {
+                // 0.0 => "FULL"
+                if value == 0.0 {
+                    return Ok(());
+                }
+                check_range(
+                    "Back to discharge voltage",
+                    value,
+                    self.parameters.p_back_to_grid_voltage,
+                    self.parameters.p_bulk_charging_voltage,
+                )
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether the last read inverter parameters contain the given value.
+    pub fn has_parameter(&self, parameter: &InverterParameter) -> bool {
+        let voltage_eq = |a: f32, b: f32| (a - b).abs() < VOLTAGE_TOLERANCE;
+
+        match *parameter {
+            InverterParameter::OutputSourcePriority(value) => {
+                self.parameters.p_output_source_priotrity == value
+            }
+            InverterParameter::ChargerSourcePriority(value) => {
+                self.parameters.p_charger_source_priority == value
+            }
+            InverterParameter::AcInputRange(value) => self.parameters.p_ac_input_range == value,
+            InverterParameter::BatteryType(value) => self.parameters.p_battery_type == value,
+            InverterParameter::MaxChargingCurrent(value) => {
+                self.parameters.p_max_charging_current == value
+            }
+            InverterParameter::MaxAcChargingCurrent(value) => {
+                self.parameters.p_max_ac_charging_current == value
+            }
+            InverterParameter::BulkChargingVoltage(value) => {
+                voltage_eq(self.parameters.p_bulk_charging_voltage, value)
+            }
+            InverterParameter::FloatChargingVoltage(value) => {
+                voltage_eq(self.parameters.p_float_charging_voltage, value)
+            }
+            InverterParameter::BatteryCutoffVoltage(value) => {
+                voltage_eq(self.parameters.p_battery_cutoff_voltage, value)
+            }
+            InverterParameter::BackToGridVoltage(value) => {
+                voltage_eq(self.parameters.p_back_to_grid_voltage, value)
+            }
+            InverterParameter::BackToDischargeVoltage(value) => {
+                voltage_eq(self.parameters.p_back_to_discharge_voltage, value)
+            }
+            InverterParameter::BuzzerAlarm(value) => self.parameters.p_buzzer_alarm == value,
+            InverterParameter::Backlight(value) => self.parameters.p_backlight == value,
+            InverterParameter::OverloadAutoRestart(value) => {
+                self.parameters.p_overload_auto_restart == value
+            }
+            InverterParameter::OvertempAutoRestart(value) => {
+                self.parameters.p_overtemp_auto_restart == value
+            }
+            InverterParameter::BeepsWhilePrimarySourceInterrupt(value) => {
+                self.parameters.p_beeps_while_primary_source_interrupt == value
+            }
+            InverterParameter::OverloadBypass(value) => self.parameters.p_overload_bypass == value,
+            InverterParameter::LcdToDefaultAfterOneMin(value) => {
+                self.parameters.p_lcd_to_default_after_one_min == value
+            }
+            InverterParameter::FaultCodeRecord(value) => {
+                self.parameters.p_fault_code_record == value
+            }
+            InverterParameter::BatteryEqualization(value) => {
+                self.parameters.p_battery_equalization_enable == value
+            }
+        }
+    }
+}
+
+/// Pending parameter change waiting to be written by the Bluetooth service.
+pub struct ParameterRequest {
+    pub parameter: InverterParameter,
+    pub reply: oneshot::Sender<Result<(), String>>,
+}
+
+/// Cloneable handle used to queue parameter changes on a running `BTInterface`.
+#[derive(Clone)]
+pub struct ParameterWriter {
+    sender: mpsc::Sender<ParameterRequest>,
+}
+
+impl ParameterWriter {
+    pub fn new(sender: mpsc::Sender<ParameterRequest>) -> Self {
+        ParameterWriter { sender }
+    }
+
+    /// Queue a parameter change and wait until it has been written and confirmed.
+    pub async fn write(&self, parameter: InverterParameter) -> Result<(), String> {
+        let (reply, response) = oneshot::channel();
+        self.sender
+            .send(ParameterRequest { parameter, reply })
+            .await
+            .map_err(|_| "Bluetooth service is not running".to_owned())?;
+
+        response
+            .await
+            .map_err(|_| "Bluetooth service dropped the request".to_owned())?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 0x2A0C read from an inverter: 230V 50Hz, 60A/30A, float 54V, bulk 56.4V, cut-off 44V,
+    /// back to grid 46V, back to discharge 54V, UPS, SBU, solar first, user defined.
+    const SETTINGS: [u8; 20] = [
+        0xE6, 0x00, 0xF4, 0x01, 0x3C, 0x1E, 0x1C, 0x02, 0x34, 0x02, 0xB8, 0x01, 0xCC, 0x01, 0x1C,
+        0x02, 0x01, 0x02, 0x01, 0x02,
+    ];
+
+    const FLAGS: [fn(bool) -> InverterParameter; 9] = [
+        InverterParameter::BuzzerAlarm,
+        InverterParameter::Backlight,
+        InverterParameter::OverloadAutoRestart,
+        InverterParameter::OvertempAutoRestart,
+        InverterParameter::BeepsWhilePrimarySourceInterrupt,
+        InverterParameter::OverloadBypass,
+        InverterParameter::LcdToDefaultAfterOneMin,
+        InverterParameter::FaultCodeRecord,
+        InverterParameter::BatteryEqualization,
+    ];
+
+    /// Inverter data with the battery voltage limits reported: bulk 48-58.4V, cut-off 40-48V.
+    fn data() -> InverterData {
+        let mut data = InverterData::new();
+        data.parse_0x2a0c(SETTINGS.to_vec()).unwrap();
+        data.parameters.p_min_bulk_voltage = 48.0;
+        data.parameters.p_max_bulk_voltage = 58.4;
+        data.parameters.p_min_undervoltage = 40.0;
+        data.parameters.p_max_undervoltage = 48.0;
+        data
+    }
+
+    /// Indexes of the bytes that differ.
+    fn changed(before: &[u8], after: &[u8]) -> Vec<usize> {
+        (0..before.len())
+            .filter(|&i| before[i] != after[i])
+            .collect()
+    }
+
+    #[test]
+    fn settings_are_decoded_back() {
+        let parameters = [
+            (
+                InverterParameter::OutputSourcePriority(OutputSourcePriority::SolarUtilityBattery),
+                vec![17],
+                vec![0x01],
+            ),
+            (
+                InverterParameter::ChargerSourcePriority(ChargerSourcePriority::OnlySolar),
+                vec![18],
+                vec![0x03],
+            ),
+            (
+                InverterParameter::AcInputRange(AcInputRange::Appliance),
+                vec![16],
+                vec![0x00],
+            ),
+            (
+                InverterParameter::BatteryType(BatteryType::Pylon),
+                vec![19],
+                vec![0x03],
+            ),
+            (InverterParameter::MaxChargingCurrent(40), vec![4], vec![40]),
+            (
+                InverterParameter::MaxAcChargingCurrent(10),
+                vec![5],
+                vec![10],
+            ),
+            (
+                InverterParameter::FloatChargingVoltage(53.5),
+                vec![6, 7],
+                vec![0x17, 0x02],
+            ),
+            (
+                InverterParameter::BulkChargingVoltage(57.6),
+                vec![8, 9],
+                vec![0x40, 0x02],
+            ),
+            (
+                InverterParameter::BatteryCutoffVoltage(42.0),
+                vec![10, 11],
+                vec![0xA4, 0x01],
+            ),
+            (
+                InverterParameter::BackToGridVoltage(51.2),
+                vec![12, 13],
+                vec![0x00, 0x02],
+            ),
+            (
+                InverterParameter::BackToDischargeVoltage(0.0),
+                vec![14, 15],
+                vec![0x00, 0x00],
+            ),
+        ];
+
+        for (parameter, indexes, expected) in parameters {
+            assert_eq!(parameter.characteristic(), CHAR_UUID_0X2A0C);
+            let mut bytes = SETTINGS;
+            parameter.encode(&mut bytes).unwrap();
+            // Only the bytes of the parameter change
+            assert!(
+                changed(&SETTINGS, &bytes)
+                    .iter()
+                    .all(|i| indexes.contains(i)),
+                "{:?}",
+                parameter
+            );
+            let encoded: Vec<u8> = indexes.iter().map(|i| bytes[*i]).collect();
+            assert_eq!(encoded, expected, "{:?}", parameter);
+
+            let mut data = InverterData::new();
+            data.parse_0x2a0c(bytes.to_vec()).unwrap();
+            assert!(data.has_parameter(&parameter), "{:?}", parameter);
+        }
+    }
+
+    #[test]
+    fn flags_are_decoded_back() {
+        for flag in FLAGS {
+            assert_eq!(flag(true).characteristic(), CHAR_UUID_0X2A0D);
+
+            // Set on an all clear value and cleared on an all set one, one bit changing
+            for (parameter, initial) in [(flag(true), 0x00), (flag(false), 0xFF)] {
+                let mut bytes = [initial; 18];
+                parameter.encode(&mut bytes).unwrap();
+                let changed_bits: u32 = bytes[..2]
+                    .iter()
+                    .map(|byte| (byte ^ initial).count_ones())
+                    .sum();
+                assert_eq!(changed_bits, 1, "{:?}", parameter);
+                assert_eq!(bytes[2..], [initial; 16]);
+
+                let mut data = InverterData::new();
+                data.parse_0x2a0d(bytes.to_vec()).unwrap();
+                assert!(data.has_parameter(&parameter), "{:?}", parameter);
+            }
+        }
+    }
+
+    #[test]
+    fn short_values_are_not_encoded() {
+        assert!(InverterParameter::MaxChargingCurrent(40)
+            .encode(&mut [0; 19])
+            .is_err());
+        assert!(InverterParameter::BuzzerAlarm(true)
+            .encode(&mut [0; 1])
+            .is_err());
+    }
+
+    #[test]
+    fn voltages_need_the_reported_limits() {
+        let mut data = InverterData::new();
+        data.parse_0x2a0c(SETTINGS.to_vec()).unwrap();
+        assert!(data
+            .validate_parameter(&InverterParameter::BulkChargingVoltage(56.4))
+            .is_err());
+        assert!(data
+            .validate_parameter(&InverterParameter::BackToDischargeVoltage(0.0))
+            .is_err());
+        assert!(data
+            .validate_parameter(&InverterParameter::MaxChargingCurrent(40))
+            .is_ok());
+    }
+
+    #[test]
+    fn values_out_of_range_are_rejected() {
+        let data = data();
+        let valid = [
+            InverterParameter::BulkChargingVoltage(54.0),
+            InverterParameter::BulkChargingVoltage(58.4),
+            InverterParameter::FloatChargingVoltage(48.0),
+            InverterParameter::FloatChargingVoltage(56.4),
+            InverterParameter::BatteryCutoffVoltage(40.0),
+            InverterParameter::BatteryCutoffVoltage(48.0),
+            InverterParameter::BackToGridVoltage(44.0),
+            InverterParameter::BackToGridVoltage(56.4),
+            InverterParameter::BackToDischargeVoltage(0.0),
+            InverterParameter::BackToDischargeVoltage(46.0),
+            InverterParameter::MaxChargingCurrent(10),
+            InverterParameter::MaxChargingCurrent(80),
+            InverterParameter::MaxAcChargingCurrent(2),
+            InverterParameter::MaxAcChargingCurrent(60),
+            InverterParameter::BatteryType(BatteryType::Pylon),
+            InverterParameter::BuzzerAlarm(false),
+        ];
+        let invalid = [
+            // Below the float charging voltage, above the maximum
+            InverterParameter::BulkChargingVoltage(53.9),
+            InverterParameter::BulkChargingVoltage(58.5),
+            // Below the minimum, above the bulk charging voltage
+            InverterParameter::FloatChargingVoltage(47.9),
+            InverterParameter::FloatChargingVoltage(56.5),
+            InverterParameter::BatteryCutoffVoltage(39.9),
+            InverterParameter::BatteryCutoffVoltage(48.1),
+            // Outside the cut-off to bulk charging voltage range
+            InverterParameter::BackToGridVoltage(43.9),
+            InverterParameter::BackToGridVoltage(56.5),
+            InverterParameter::BackToDischargeVoltage(45.9),
+            InverterParameter::BackToDischargeVoltage(56.5),
+            InverterParameter::MaxChargingCurrent(9),
+            InverterParameter::MaxChargingCurrent(81),
+            InverterParameter::MaxAcChargingCurrent(1),
+            InverterParameter::MaxAcChargingCurrent(61),
+            InverterParameter::BatteryType(BatteryType::Unknown(9)),
+            InverterParameter::OutputSourcePriority(OutputSourcePriority::Unknown(3)),
+        ];
+
+        for parameter in valid {
+            assert!(
+                data.validate_parameter(&parameter).is_ok(),
+                "{:?}",
+                parameter
+            );
+        }
+        for parameter in invalid {
+            assert!(
+                data.validate_parameter(&parameter).is_err(),
+                "{:?}",
+                parameter
+            );
+        }
+    }
+}
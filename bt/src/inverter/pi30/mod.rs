@@ -0,0 +1,683 @@
+use crate::inverter::transport::{ConnectionMonitor, ConnectionState, GattTransport};
+use crate::inverter::{
+    CHAR_UUID_0X2A01, CHAR_UUID_0X2A03, CHAR_UUID_0X2A04, CHAR_UUID_0X2A05, CHAR_UUID_0X2A0C,
+    CHAR_UUID_0X2A0D, CHAR_UUID_0X2A11,
+};
+use futures::future::BoxFuture;
+use serialport::{ClearBuffer, SerialPort};
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Default baud rate of the Axpert RS232/USB port.
+pub const DEFAULT_BAUD_RATE: u32 = 2400;
+
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(3);
+const MAX_RESPONSE_LENGTH: usize = 256;
+
+const QPIGS: &str = "QPIGS";
+const QPIRI: &str = "QPIRI";
+const QMOD: &str = "QMOD";
+const QPIWS: &str = "QPIWS";
+const QFLAG: &str = "QFLAG";
+const QVFW: &str = "QVFW";
+
+/// QFLAG letters and their position in the 0x2A0D flags (see `InverterData::parse_0x2a0d`).
+const FLAGS: [(char, usize, u8); 8] = [
+    ('b', 0, 0x80), // Overload bypass
+    ('k', 0, 0x20), // LCD returns to default screen after 1 min
+    ('u', 0, 0x10), // Overload auto restart
+    ('v', 0, 0x08), // Over temperature auto restart
+    ('x', 0, 0x04), // Backlight
+    ('y', 0, 0x02), // Beeps while primary source interrupt
+    ('z', 0, 0x01), // Fault code record
+    ('a', 1, 0x01), // Buzzer alarm
+];
+
+/// CRC-16/XMODEM as used by the Voltronic protocol, where the bytes `(`, CR and LF
+/// are avoided in the result by incrementing them.
+pub fn crc(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+
+    let mut bytes = crc.to_be_bytes();
+    for byte in bytes.iter_mut() {
+        if matches!(*byte, 0x28 | 0x0d | 0x0a) {
+            *byte += 1;
+        }
+    }
+    u16::from_be_bytes(bytes)
+}
+
+/// Frame a command: ASCII command, CRC (big endian) and carriage return.
+pub fn frame(command: &str) -> Vec<u8> {
+    let mut frame = command.as_bytes().to_vec();
+    frame.extend_from_slice(&crc(command.as_bytes()).to_be_bytes());
+    frame.push(b'\r');
+    frame
+}
+
+/// Check the CRC of a response (without the trailing CR) and return its payload
+/// without the leading `(`.
+pub fn parse_response(response: &[u8]) -> Result<String, String> {
+    if response.len() < 3 || response[0] != b'(' {
+        return Err(format!(
+            "Invalid response {:?}",
+            String::from_utf8_lossy(response)
+        ));
+    }
+
+    let (payload, received) = response.split_at(response.len() - 2);
+    let received = u16::from_be_bytes([received[0], received[1]]);
+    let expected = crc(payload);
+    if received != expected {
+        return Err(format!(
+            "CRC mismatch in response {:?}: {:04x} != {:04x}",
+            String::from_utf8_lossy(payload),
+            received,
+            expected
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&payload[1..]).into_owned())
+}
+
+/// Send a command and wait for its response.
+fn transact(port: &mut dyn SerialPort, command: &str) -> Result<String, String> {
+    port.clear(ClearBuffer::Input).map_err(|e| e.to_string())?;
+    port.write_all(&frame(command))
+        .map_err(|e| format!("{} write failed: {}", command, e))?;
+
+    let started = Instant::now();
+    let mut response = Vec::new();
+    let mut byte = [0u8; 1];
+    loop {
+        match port.read(&mut byte) {
+            Ok(1) if byte[0] == b'\r' => break,
+            Ok(1) => {
+                response.push(byte[0]);
+                if response.len() > MAX_RESPONSE_LENGTH {
+                    return Err(format!("{} response too long", command));
+                }
+            }
+            Ok(_) => {}
+            Err(ref e) if e.kind() == io::ErrorKind::TimedOut => {}
+            Err(e) => return Err(format!("{} read failed: {}", command, e)),
+        }
+        if started.elapsed() > RESPONSE_TIMEOUT {
+            return Err(format!("{} timed out", command));
+        }
+    }
+
+    parse_response(&response)
+}
+
+fn field<T: std::str::FromStr>(fields: &[&str], index: usize, command: &str) -> Result<T, String> {
+    fields
+        .get(index)
+        .and_then(|value| value.parse::<T>().ok())
+        .ok_or_else(|| format!("Missing or invalid field {} in {} response", index, command))
+}
+
+fn f32_to_b(value: f32, factor: f32) -> [u8; 2] {
+    ((value / factor).round() as u16).to_le_bytes()
+}
+
+fn u16_to_b(value: u16) -> [u8; 2] {
+    value.to_le_bytes()
+}
+
+fn put(bytes: &mut [u8], offset: usize, value: [u8; 2]) {
+    bytes[offset..offset + 2].copy_from_slice(&value);
+}
+
+fn failed(message: String) -> bluer::Error {
+    bluer::Error {
+        kind: bluer::ErrorKind::Failed,
+        message,
+    }
+}
+
+/// Inverter reached over its serial port with the Voltronic PI30 protocol.
+/// The responses are laid out as the equivalent GATT characteristics, so the same
+/// `InverterData` parsers, freshness tracking and parameter writes apply.
+pub struct Pi30Transport {
+    device: String,
+    baud_rate: u32,
+    port: Arc<Mutex<Option<Box<dyn SerialPort>>>>,
+    /// Responses received during the current poll.
+    responses: Mutex<HashMap<&'static str, Result<String, String>>>,
+    /// Last characteristic values built from the responses, used to find written changes.
+    characteristics: Mutex<HashMap<Uuid, Vec<u8>>>,
+}
+
+impl Pi30Transport {
+    pub fn new(device: &str, baud_rate: u32) -> Self {
+        Pi30Transport {
+            device: device.to_owned(),
+            baud_rate,
+            port: Arc::new(Mutex::new(None)),
+            responses: Mutex::new(HashMap::new()),
+            characteristics: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Run a command on a blocking thread, closing the port on communication errors so it
+    /// is reopened on the next poll.
+    async fn send(&self, command: String) -> Result<String, String> {
+        let port = self.port.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut guard = port.lock().unwrap();
+            let result = match guard.as_mut() {
+                Some(port) => transact(port.as_mut(), &command),
+                None => return Err("Serial port is not open".to_owned()),
+            };
+            if result.is_err() {
+                *guard = None;
+            }
+            result
+        })
+        .await
+        .map_err(|e| e.to_string())?
+    }
+
+    /// Query command, answered once per poll.
+    async fn query(&self, command: &'static str) -> Result<String, String> {
+        if let Some(response) = self.responses.lock().unwrap().get(command) {
+            return response.clone();
+        }
+        let response = match self.send(command.to_owned()).await {
+            Ok(response) if response == "NAK" => Err(format!("{} not supported", command)),
+            response => response,
+        };
+        self.responses
+            .lock()
+            .unwrap()
+            .insert(command, response.clone());
+        response
+    }
+
+    async fn fields(&self, command: &'static str) -> Result<Vec<String>, String> {
+        Ok(self
+            .query(command)
+            .await?
+            .split_whitespace()
+            .map(str::to_owned)
+            .collect())
+    }
+
+    /// Build the value of a characteristic from the PI30 responses,
+    /// `None` for the characteristics without an equivalent. The setting limits (0x2A0B)
+    /// are not reported over PI30, so battery voltage settings can't be validated nor written.
+    async fn build(&self, uuid: Uuid) -> Result<Option<Vec<u8>>, String> {
+        let mut bytes = vec![0u8; 20];
+
+        match uuid {
+            CHAR_UUID_0X2A01 => {
+                let version = self.query(QVFW).await?;
+                let version = version.trim_start_matches("VERFW:");
+                bytes = vec![0u8; 12];
+                for (i, byte) in version.bytes().take(8).enumerate() {
+                    bytes[3 + i] = byte;
+                }
+            }
+            CHAR_UUID_0X2A03 => {
+                let fields = self.fields(QPIGS).await?;
+                let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+                put(&mut bytes, 0, f32_to_b(field(&fields, 0, QPIGS)?, 0.1));
+                put(&mut bytes, 2, f32_to_b(field(&fields, 1, QPIGS)?, 0.1));
+                put(&mut bytes, 4, f32_to_b(field(&fields, 2, QPIGS)?, 0.1));
+                put(&mut bytes, 6, f32_to_b(field(&fields, 3, QPIGS)?, 0.1));
+                put(&mut bytes, 8, u16_to_b(field(&fields, 4, QPIGS)?));
+                put(&mut bytes, 10, u16_to_b(field(&fields, 5, QPIGS)?));
+                put(&mut bytes, 12, u16_to_b(field(&fields, 6, QPIGS)?));
+                put(&mut bytes, 16, f32_to_b(field(&fields, 8, QPIGS)?, 0.01));
+                put(&mut bytes, 18, u16_to_b(field(&fields, 9, QPIGS)?));
+            }
+            CHAR_UUID_0X2A04 => {
+                let fields = self.fields(QPIGS).await?;
+                let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+                put(&mut bytes, 0, u16_to_b(field(&fields, 10, QPIGS)?));
+                put(&mut bytes, 4, u16_to_b(field(&fields, 15, QPIGS)?));
+
+                // One warning per character, in the same order as the event table
+                let warnings = self.query(QPIWS).await?;
+                for (i, flag) in warnings.bytes().take(32).enumerate() {
+                    if flag == b'1' {
+                        bytes[8 + i / 8] |= 1 << (i % 8);
+                    }
+                }
+
+                bytes[12] = self.query(QMOD).await?.bytes().next().unwrap_or_default();
+                bytes[13] = 1;
+            }
+            CHAR_UUID_0X2A05 => {
+                let fields = self.fields(QPIRI).await?;
+                let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+                put(&mut bytes, 0, f32_to_b(field(&fields, 0, QPIRI)?, 0.1));
+                put(&mut bytes, 4, f32_to_b(field(&fields, 2, QPIRI)?, 0.1));
+                put(&mut bytes, 6, f32_to_b(field(&fields, 3, QPIRI)?, 0.1));
+                put(&mut bytes, 8, f32_to_b(field(&fields, 1, QPIRI)?, 0.1));
+                put(&mut bytes, 10, u16_to_b(field(&fields, 5, QPIRI)?));
+                put(&mut bytes, 12, u16_to_b(field(&fields, 6, QPIRI)?));
+                put(&mut bytes, 14, f32_to_b(field(&fields, 7, QPIRI)?, 0.1));
+                // Machine type: 00 grid tie, 01 off grid, 10 hybrid
+                bytes[16] = match fields.get(19) {
+                    Some(&"00") => 0,
+                    Some(&"01") => 1,
+                    _ => 2,
+                };
+            }
+            CHAR_UUID_0X2A0C => {
+                let fields = self.fields(QPIRI).await?;
+                let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+                put(
+                    &mut bytes,
+                    0,
+                    u16_to_b(field::<f32>(&fields, 2, QPIRI)? as u16),
+                );
+                put(&mut bytes, 2, f32_to_b(field(&fields, 3, QPIRI)?, 0.1));
+                bytes[4] = field(&fields, 14, QPIRI)?;
+                bytes[5] = field(&fields, 13, QPIRI)?;
+                put(&mut bytes, 6, f32_to_b(field(&fields, 11, QPIRI)?, 0.1));
+                put(&mut bytes, 8, f32_to_b(field(&fields, 10, QPIRI)?, 0.1));
+                put(&mut bytes, 10, f32_to_b(field(&fields, 9, QPIRI)?, 0.1));
+                put(&mut bytes, 12, f32_to_b(field(&fields, 8, QPIRI)?, 0.1));
+                put(&mut bytes, 14, f32_to_b(field(&fields, 22, QPIRI)?, 0.1));
+                bytes[16] = field(&fields, 15, QPIRI)?;
+                bytes[17] = field(&fields, 16, QPIRI)?;
+                bytes[18] = field(&fields, 17, QPIRI)?;
+                bytes[19] = field(&fields, 12, QPIRI)?;
+            }
+            CHAR_UUID_0X2A0D => {
+                let fields = self.fields(QPIRI).await?;
+                let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+                bytes[2] = field(&fields, 21, QPIRI)?;
+                bytes[3] = field(&fields, 23, QPIRI).unwrap_or_default();
+                bytes[4] = field(&fields, 24, QPIRI).unwrap_or_default();
+
+                // Enabled flags follow `E`, disabled ones follow `D`
+                let flags = self.query(QFLAG).await?;
+                let enabled = flags
+                    .split('D')
+                    .next()
+                    .unwrap_or_default()
+                    .trim_start_matches('E');
+                for (letter, index, mask) in FLAGS {
+                    if enabled.contains(letter) {
+                        bytes[index] |= mask;
+                    }
+                }
+            }
+            CHAR_UUID_0X2A11 => {
+                let fields = self.fields(QPIGS).await?;
+                let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+                let pv_voltage: f32 = field(&fields, 13, QPIGS)?;
+                // PV charging power is only reported by recent firmware
+                let pv_power: u16 = field(&fields, 19, QPIGS).or_else(|_| {
+                    let pv_current: f32 = field(&fields, 12, QPIGS)?;
+                    Ok::<u16, String>((pv_current * pv_voltage).round() as u16)
+                })?;
+                bytes[0..8].copy_from_slice(b"        ");
+                put(&mut bytes, 12, f32_to_b(pv_voltage, 0.1));
+                put(&mut bytes, 14, u16_to_b(pv_power));
+            }
+            _ => return Ok(None),
+        }
+
+        self.characteristics
+            .lock()
+            .unwrap()
+            .insert(uuid, bytes.clone());
+        Ok(Some(bytes))
+    }
+
+    /// PI30 setting commands for the values changed in a written characteristic.
+    fn setting_commands(&self, uuid: Uuid, value: &[u8]) -> Result<Vec<String>, String> {
+        let characteristics = self.characteristics.lock().unwrap();
+        let current = characteristics
+            .get(&uuid)
+            .ok_or_else(|| format!("Characteristic {} has not been read yet", uuid))?;
+        if value.len() < current.len() {
+            return Err(format!("Invalid value length {} for {}", value.len(), uuid));
+        }
+
+        let voltage =
+            |offset: usize| u16::from_le_bytes([value[offset], value[offset + 1]]) as f32 * 0.1;
+        let changed = |offset: usize, length: usize| {
+            value[offset..offset + length] != current[offset..offset + length]
+        };
+        let mut commands = Vec::new();
+
+        match uuid {
+            CHAR_UUID_0X2A0C => {
+                if changed(4, 1) {
+                    commands.push(format!("MCHGC{:03}", value[4]));
+                }
+                if changed(5, 1) {
+                    commands.push(format!("MUCHGC{:03}", value[5]));
+                }
+                if changed(6, 2) {
+                    commands.push(format!("PBFT{:04.1}", voltage(6)));
+                }
+                if changed(8, 2) {
+                    commands.push(format!("PCVV{:04.1}", voltage(8)));
+                }
+                if changed(10, 2) {
+                    commands.push(format!("PSDV{:04.1}", voltage(10)));
+                }
+                if changed(12, 2) {
+                    commands.push(format!("PBCV{:04.1}", voltage(12)));
+                }
+                if changed(14, 2) {
+                    commands.push(format!("PBDV{:04.1}", voltage(14)));
+                }
+                if changed(16, 1) {
+                    commands.push(format!("PGR{:02}", value[16]));
+                }
+                if changed(17, 1) {
+                    commands.push(format!("POP{:02}", value[17]));
+                }
+                if changed(18, 1) {
+                    commands.push(format!("PCP{:02}", value[18]));
+                }
+                if changed(19, 1) {
+                    commands.push(format!("PBT{:02}", value[19]));
+                }
+            }
+            CHAR_UUID_0X2A0D => {
+                for (letter, index, mask) in FLAGS {
+                    if (value[index] ^ current[index]) & mask != 0 {
+                        let action = if value[index] & mask != 0 { 'E' } else { 'D' };
+                        commands.push(format!("P{}{}", action, letter));
+                    }
+                }
+                if (value[1] ^ current[1]) & 0x02 != 0 {
+                    return Err("Battery equalization cannot be changed over PI30".to_owned());
+                }
+            }
+            _ => return Err(format!("Characteristic {} is not writable", uuid)),
+        }
+
+        Ok(commands)
+    }
+}
+
+impl GattTransport for Pi30Transport {
+    fn connect<'a>(&'a self, monitor: &'a ConnectionMonitor) -> BoxFuture<'a, bluer::Result<()>> {
+        Box::pin(async move {
+            self.responses.lock().unwrap().clear();
+            if self.port.lock().unwrap().is_some() {
+                return Ok(());
+            }
+
+            monitor.set_state(ConnectionState::Connecting);
+            let port = serialport::new(&self.device, self.baud_rate)
+                .timeout(READ_TIMEOUT)
+                .open()
+                .map_err(|e| failed(format!("Unable to open {}: {}", self.device, e)))?;
+            *self.port.lock().unwrap() = Some(port);
+            Ok(())
+        })
+    }
+
+    fn read(&self, uuid: Uuid) -> BoxFuture<'_, bluer::Result<Option<Vec<u8>>>> {
+        Box::pin(async move { self.build(uuid).await.map_err(failed) })
+    }
+
+    fn write<'a>(&'a self, uuid: Uuid, value: &'a [u8]) -> BoxFuture<'a, bluer::Result<()>> {
+        Box::pin(async move {
+            let commands = self.setting_commands(uuid, value).map_err(failed)?;
+            for command in commands {
+                let response = self.send(command.clone()).await.map_err(failed)?;
+                if response != "ACK" {
+                    return Err(failed(format!("{} answered {}", command, response)));
+                }
+            }
+
+            // Read the settings again on the confirmation read
+            self.responses.lock().unwrap().clear();
+            Ok(())
+        })
+    }
+
+    fn name(&self) -> String {
+        format!("PI30 {} at {} baud", self.device, self.baud_rate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inverter::modes::{
+        AcInputRange, BatteryType, ChargerSourcePriority, OutputSourcePriority, WorkMode,
+    };
+    use crate::inverter::parameters::InverterParameter;
+    use crate::inverter::{CharacteristicGroup, InverterData};
+    use std::collections::BTreeMap;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Command, Stdio};
+
+    /// Responses of the PI30 simulator.
+    const RESPONSES: [(&str, &str); 6] = [
+        (
+            QPIGS,
+            "232.0 50.0 229.9 50.0 0459 0415 009 394 52.70 004 095 0043 0009 286.4 52.68 00000 \
+             00110110 00 00 00258 010",
+        ),
+        (
+            QPIRI,
+            "230.0 21.7 230.0 50.0 21.7 5000 5000 48.0 46.0 42.0 56.4 54.0 2 30 060 0 2 1 9 01 \
+             0 0 54.0 0 1 000",
+        ),
+        (QMOD, "L"),
+        (QPIWS, "00000000000000000000000000000000000000"),
+        (QFLAG, "EakxyzDbuv"),
+        (QVFW, "VERFW:00092.70"),
+    ];
+
+    /// Transport answering from `RESPONSES`, without a serial port.
+    fn transport() -> Pi30Transport {
+        let transport = Pi30Transport::new("/dev/null", DEFAULT_BAUD_RATE);
+        transport.responses.lock().unwrap().extend(
+            RESPONSES
+                .iter()
+                .map(|(command, response)| (*command, Ok(response.to_string()))),
+        );
+        transport
+    }
+
+    async fn read(transport: &Pi30Transport) -> InverterData {
+        let mut data = InverterData::new();
+        let mut reads = BTreeMap::new();
+        data.read_characteristics(transport, &CharacteristicGroup::ALL, &mut reads)
+            .await
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn crc_of_known_commands() {
+        assert_eq!(crc(b"QPIGS"), 0xB7A9);
+        assert_eq!(crc(b"QPIRI"), 0xF854);
+        assert_eq!(crc(b"(NAK"), 0x7373);
+        // 0x2824, `(` is avoided
+        assert_eq!(crc(b"MCHGC056"), 0x2924);
+    }
+
+    #[test]
+    fn commands_are_framed() {
+        assert_eq!(frame(QPIGS), b"QPIGS\xB7\xA9\r");
+        assert_eq!(frame("MCHGC056"), b"MCHGC056\x29\x24\r");
+    }
+
+    #[test]
+    fn responses_are_checked() {
+        assert_eq!(parse_response(b"(ACK\x39\x20").unwrap(), "ACK");
+        // A NAK is a valid response, rejected by the caller
+        assert_eq!(parse_response(b"(NAKss").unwrap(), "NAK");
+        assert!(parse_response(b"(NAKsr")
+            .unwrap_err()
+            .contains("CRC mismatch"));
+        assert!(parse_response(b"NAKss").is_err());
+        assert!(parse_response(b"(s").is_err());
+        assert!(parse_response(b"").is_err());
+    }
+
+    #[tokio::test]
+    async fn responses_are_laid_out_as_characteristics() {
+        let data = read(&transport()).await;
+
+        // QPIGS
+        assert_eq!(data.live.ac_voltage, 232.0);
+        assert_eq!(data.live.ac_frequency, 50.0);
+        assert!((data.live.output_voltage - 229.9).abs() < 0.01);
+        assert_eq!(data.live.output_apparent_power, 459);
+        assert_eq!(data.live.output_active_power, 415);
+        assert_eq!(data.live.load_percentage, 9);
+        assert!((data.live.battery_voltage - 52.7).abs() < 0.01);
+        assert_eq!(data.live.battery_charge_current, 4);
+        assert_eq!(data.live.battery_capacity, 95);
+        assert_eq!(data.live.battery_current_discharge, 0);
+        assert!((data.live.pv_input_voltage_stage1 - 286.4).abs() < 0.01);
+        assert_eq!(data.live.pv_input_power_stage1, 258);
+        assert_eq!(data.live.workmode, WorkMode::Line);
+        assert!(data.live.events.is_empty());
+
+        // QPIRI
+        assert_eq!(data.ratings.nominal_ac_voltage, 230.0);
+        assert!((data.ratings.nominal_ac_current - 21.7).abs() < 0.01);
+        assert_eq!(data.ratings.nominal_output_active_power, 5000);
+        assert_eq!(data.ratings.rated_battery_voltage, 48.0);
+        assert_eq!(data.ratings.model_type, 1);
+        assert_eq!(data.parameters.p_output_voltage, 230);
+        assert_eq!(data.parameters.p_max_charging_current, 60);
+        assert_eq!(data.parameters.p_max_ac_charging_current, 30);
+        assert!((data.parameters.p_bulk_charging_voltage - 56.4).abs() < 0.01);
+        assert_eq!(data.parameters.p_float_charging_voltage, 54.0);
+        assert_eq!(data.parameters.p_battery_cutoff_voltage, 42.0);
+        assert_eq!(data.parameters.p_back_to_grid_voltage, 46.0);
+        assert_eq!(data.parameters.p_back_to_discharge_voltage, 54.0);
+        assert_eq!(data.parameters.p_ac_input_range, AcInputRange::Appliance);
+        assert_eq!(
+            data.parameters.p_output_source_priotrity,
+            OutputSourcePriority::SolarBatteryUtility
+        );
+        assert_eq!(
+            data.parameters.p_charger_source_priority,
+            ChargerSourcePriority::SolarFirst
+        );
+        assert_eq!(data.parameters.p_battery_type, BatteryType::UserDefined);
+
+        // QFLAG
+        assert!(data.parameters.p_buzzer_alarm);
+        assert!(data.parameters.p_backlight);
+        assert!(data.parameters.p_lcd_to_default_after_one_min);
+        assert!(data.parameters.p_fault_code_record);
+        assert!(!data.parameters.p_overload_bypass);
+        assert!(!data.parameters.p_overload_auto_restart);
+        assert!(!data.parameters.p_overtemp_auto_restart);
+    }
+
+    #[tokio::test]
+    async fn changed_settings_are_written_as_commands() {
+        let transport = transport();
+        let data = read(&transport).await;
+        let commands = |parameters: &[InverterParameter]| {
+            let uuid = parameters[0].characteristic();
+            let mut value = transport.characteristics.lock().unwrap()[&uuid].clone();
+            for parameter in parameters {
+                parameter.encode(&mut value).unwrap();
+            }
+            transport.setting_commands(uuid, &value)
+        };
+
+        assert_eq!(
+            commands(&[
+                InverterParameter::MaxChargingCurrent(40),
+                InverterParameter::BulkChargingVoltage(57.6),
+                InverterParameter::OutputSourcePriority(OutputSourcePriority::SolarUtilityBattery),
+                InverterParameter::BatteryType(BatteryType::Pylon),
+            ])
+            .unwrap(),
+            ["MCHGC040", "PCVV57.6", "POP01", "PBT03"]
+        );
+        assert_eq!(
+            commands(&[
+                InverterParameter::BuzzerAlarm(false),
+                InverterParameter::OverloadBypass(true),
+            ])
+            .unwrap(),
+            ["PEb", "PDa"]
+        );
+        // Unchanged values send nothing
+        assert!(commands(&[InverterParameter::MaxAcChargingCurrent(
+            data.parameters.p_max_ac_charging_current
+        )])
+        .unwrap()
+        .is_empty());
+        assert!(commands(&[InverterParameter::BatteryEqualization(true)]).is_err());
+
+        assert!(transport
+            .setting_commands(CHAR_UUID_0X2A03, &[0; 20])
+            .is_err());
+        assert!(transport
+            .setting_commands(CHAR_UUID_0X2A0C, &[0; 19])
+            .is_err());
+        let unread = Pi30Transport::new("/dev/null", DEFAULT_BAUD_RATE);
+        assert!(unread.setting_commands(CHAR_UUID_0X2A0C, &[0; 20]).is_err());
+    }
+
+    /// Needs python3, run with `cargo test -- --ignored`.
+    #[tokio::test]
+    #[ignore]
+    async fn simulator_values_are_decoded() {
+        let link = std::env::temp_dir().join("bt_pi30_simulator");
+        let mut simulator = Command::new("python3")
+            .arg("../extras/pi30_simulator/pi30_simulator.py")
+            .arg(&link)
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Unable to start the PI30 simulator");
+        // The simulator prints its device once it listens, then logs every command
+        let mut output = BufReader::new(simulator.stdout.take().unwrap());
+        output.read_line(&mut String::new()).unwrap();
+
+        let transport = Pi30Transport::new(link.to_str().unwrap(), DEFAULT_BAUD_RATE);
+        let mut data = InverterData::new();
+        let mut reads = BTreeMap::new();
+        let result = async {
+            transport.connect(&ConnectionMonitor::new()).await?;
+            data.read_characteristics(&transport, &CharacteristicGroup::ALL, &mut reads)
+                .await
+        }
+        .await;
+        simulator.kill().unwrap();
+        simulator.wait().unwrap();
+        result.unwrap();
+
+        assert_eq!(data.live.ac_voltage, 232.0);
+        assert!((data.live.battery_voltage - 52.7).abs() < 0.01);
+        assert!((data.parameters.p_bulk_charging_voltage - 56.4).abs() < 0.01);
+        assert_eq!(data.parameters.p_max_charging_current, 60);
+        // No setting limits over PI30, battery voltages can't be validated
+        assert!(data
+            .validate_parameter(&InverterParameter::BulkChargingVoltage(56.0))
+            .is_err());
+        assert!(data
+            .validate_parameter(&InverterParameter::MaxChargingCurrent(30))
+            .is_ok());
+    }
+}
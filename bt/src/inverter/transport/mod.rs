@@ -0,0 +1,306 @@
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Samples kept for the rolling link statistics.
+const LINK_STATS_WINDOW: usize = 100;
+
+/// Connection state of an inverter transport.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ConnectionState {
+    Disconnected,
+    Scanning,
+    Connecting,
+    Reading,
+    Idle,
+    Backoff,
+}
+
+/// State of the Bluetooth adapter used by a transport.
+#[derive(Debug, Clone, Serialize)]
+pub struct AdapterStatus {
+    pub name: String,
+    pub address: Option<String>,
+    pub powered: bool,
+    /// Power cycles done to recover from repeated failures.
+    pub resets: u32,
+    pub last_reset: Option<DateTime<Utc>>,
+}
+
+/// Rolling statistics over the last samples of a link metric.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(into = "StatsSummary")]
+pub struct RollingStats {
+    samples: VecDeque<f64>,
+}
+
+impl RollingStats {
+    pub fn push(&mut self, value: f64) {
+        if self.samples.len() == LINK_STATS_WINDOW {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(value);
+    }
+}
+
+/// Serialized form of [`RollingStats`].
+#[derive(Debug, Serialize)]
+pub struct StatsSummary {
+    pub last: Option<f64>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub mean: Option<f64>,
+    pub samples: usize,
+}
+
+impl From<RollingStats> for StatsSummary {
+    fn from(stats: RollingStats) -> Self {
+        let samples = &stats.samples;
+        let fold = |f: fn(f64, f64) -> f64| samples.iter().copied().reduce(f);
+        StatsSummary {
+            last: samples.back().copied(),
+            min: fold(f64::min),
+            max: fold(f64::max),
+            mean: fold(|a, b| a + b).map(|sum| sum / samples.len() as f64),
+            samples: samples.len(),
+        }
+    }
+}
+
+/// Link metrics measured during a single poll, `None` for the steps it did not go through.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LinkSample {
+    pub rssi: Option<i16>,
+    pub discovery_ms: Option<f64>,
+    pub connect_ms: Option<f64>,
+    /// Mean read latency of the characteristics read during the poll.
+    pub read_ms: Option<f64>,
+    #[serde(skip)]
+    reads: usize,
+}
+
+/// Bluetooth link quality, to tell range or interference issues from inverter ones.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LinkQuality {
+    /// Signal strength in dBm.
+    pub rssi: RollingStats,
+    pub discovery_ms: RollingStats,
+    /// Pairing (when needed) and connection time.
+    pub connect_ms: RollingStats,
+    pub read_ms: RollingStats,
+    /// Read latency by characteristic id (e.g. `2a03`).
+    pub characteristic_read_ms: BTreeMap<String, RollingStats>,
+    pub last_poll: LinkSample,
+}
+
+/// Snapshot of the connection state machine, exposed through `/api/status`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionStatus {
+    pub state: ConnectionState,
+    pub since: DateTime<Utc>,
+    pub consecutive_failures: u32,
+    pub next_attempt: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    /// `None` for transports that do not use a Bluetooth adapter.
+    pub adapter: Option<AdapterStatus>,
+    pub link: LinkQuality,
+}
+
+/// Shared handle to the connection status of a running inverter service.
+#[derive(Clone)]
+pub struct ConnectionMonitor {
+    status: Arc<RwLock<ConnectionStatus>>,
+}
+
+impl ConnectionMonitor {
+    pub fn new() -> Self {
+        ConnectionMonitor {
+            status: Arc::new(RwLock::new(ConnectionStatus {
+                state: ConnectionState::Disconnected,
+                since: Utc::now(),
+                consecutive_failures: 0,
+                next_attempt: None,
+                last_error: None,
+                adapter: None,
+                link: LinkQuality::default(),
+            })),
+        }
+    }
+
+    /// Move to a new state, keeping the failure counters.
+    pub fn set_state(&self, state: ConnectionState) {
+        let mut status = self.status.write().unwrap();
+        if status.state != state {
+            status.state = state;
+            status.since = Utc::now();
+        }
+        if state != ConnectionState::Backoff {
+            status.next_attempt = None;
+        }
+    }
+
+    /// Record a successful poll and reset the failure counter.
+    pub fn set_success(&self) {
+        self.set_state(ConnectionState::Idle);
+        let mut status = self.status.write().unwrap();
+        status.consecutive_failures = 0;
+        status.last_error = None;
+    }
+
+    /// Record a failed poll and enter backoff until `next_attempt`.
+    /// Returns the number of consecutive failures.
+    pub fn set_failure(&self, error: String, next_attempt: DateTime<Utc>) -> u32 {
+        self.set_state(ConnectionState::Backoff);
+        let mut status = self.status.write().unwrap();
+        status.consecutive_failures += 1;
+        status.next_attempt = Some(next_attempt);
+        status.last_error = Some(error);
+        status.consecutive_failures
+    }
+
+    /// Record the state of the Bluetooth adapter used for the connection.
+    pub fn set_adapter(&self, adapter: AdapterStatus) {
+        self.status.write().unwrap().adapter = Some(adapter);
+    }
+
+    /// Start collecting the link metrics of a new poll.
+    pub fn start_poll(&self) {
+        self.status.write().unwrap().link.last_poll = LinkSample::default();
+    }
+
+    /// Record the signal strength of the inverter in dBm.
+    pub fn record_rssi(&self, rssi: i16) {
+        let link = &mut self.status.write().unwrap().link;
+        link.rssi.push(rssi as f64);
+        link.last_poll.rssi = Some(rssi);
+    }
+
+    /// Record the time taken to discover the inverter.
+    pub fn record_discovery(&self, elapsed: Duration) {
+        let link = &mut self.status.write().unwrap().link;
+        let ms = elapsed.as_secs_f64() * 1000.0;
+        link.discovery_ms.push(ms);
+        link.last_poll.discovery_ms = Some(ms);
+    }
+
+    /// Record the time taken to pair and connect to the inverter.
+    pub fn record_connect(&self, elapsed: Duration) {
+        let link = &mut self.status.write().unwrap().link;
+        let ms = elapsed.as_secs_f64() * 1000.0;
+        link.connect_ms.push(ms);
+        link.last_poll.connect_ms = Some(ms);
+    }
+
+    /// Record the latency of a characteristic read.
+    pub fn record_read(&self, uuid: &Uuid, elapsed: Duration) {
+        let link = &mut self.status.write().unwrap().link;
+        let ms = elapsed.as_secs_f64() * 1000.0;
+        link.read_ms.push(ms);
+        link.characteristic_read_ms
+            .entry(short_id(uuid))
+            .or_default()
+            .push(ms);
+
+        let last_poll = &mut link.last_poll;
+        let total = last_poll.read_ms.unwrap_or(0.0) * last_poll.reads as f64 + ms;
+        last_poll.reads += 1;
+        last_poll.read_ms = Some(total / last_poll.reads as f64);
+    }
+
+    pub fn status(&self) -> ConnectionStatus {
+        self.status.read().unwrap().clone()
+    }
+}
+
+/// Access to the inverter GATT characteristics, independent of the Bluetooth stack.
+pub trait GattTransport: Send + Sync {
+    /// Make the inverter reachable before reading or writing characteristics,
+    /// reporting the scanning/connecting steps to the monitor.
+    fn connect<'a>(&'a self, monitor: &'a ConnectionMonitor) -> BoxFuture<'a, bluer::Result<()>>;
+
+    /// Read a characteristic by UUID, `None` if the inverter does not expose it.
+    fn read(&self, uuid: Uuid) -> BoxFuture<'_, bluer::Result<Option<Vec<u8>>>>;
+
+    /// Write a parameter characteristic by UUID.
+    fn write<'a>(&'a self, uuid: Uuid, value: &'a [u8]) -> BoxFuture<'a, bluer::Result<()>>;
+
+    /// Signal strength of the inverter in dBm, `None` when unknown or not applicable.
+    fn rssi(&self) -> BoxFuture<'_, Option<i16>> {
+        Box::pin(async { None })
+    }
+
+    /// Human readable description used in logs.
+    fn name(&self) -> String;
+}
+
+/// Build the full Bluetooth base UUID from a 16 bit characteristic id (e.g. `0x2A03`).
+pub fn uuid_from_short(id: u16) -> Uuid {
+    Uuid::from_u128(0x0000_0000_0000_1000_8000_0080_5f9b_34fb | ((id as u128) << 96))
+}
+
+/// Short characteristic id (e.g. `2a03`) of a Bluetooth base UUID.
+pub fn short_id(uuid: &Uuid) -> String {
+    format!("{:04x}", (uuid.as_u128() >> 96) as u16)
+}
+
+/// In-memory transport serving fixed characteristic values, used to run without BlueZ.
+pub struct FixtureTransport {
+    name: String,
+    values: Mutex<HashMap<Uuid, Vec<u8>>>,
+}
+
+impl FixtureTransport {
+    pub fn new(name: &str, values: HashMap<Uuid, Vec<u8>>) -> Self {
+        FixtureTransport {
+            name: name.to_owned(),
+            values: Mutex::new(values),
+        }
+    }
+
+    /// Load a fixture from a JSON object mapping characteristic ids to hex values,
+    /// e.g. `{ "2a03": "e6080a02..." }`.
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read fixture {}: {}", path, e))?;
+        let raw: HashMap<String, String> = serde_json::from_str(&content)
+            .map_err(|e| format!("Invalid fixture {}: {}", path, e))?;
+
+        let mut values = HashMap::new();
+        for (id, value) in raw {
+            let uuid = u16::from_str_radix(id.trim_start_matches("0x"), 16)
+                .map(uuid_from_short)
+                .or_else(|_| Uuid::parse_str(&id))
+                .map_err(|_| format!("Invalid characteristic id {} in {}", id, path))?;
+            let value = hex::decode(value.replace(' ', ""))
+                .map_err(|e| format!("Invalid value for {} in {}: {}", id, path, e))?;
+            values.insert(uuid, value);
+        }
+
+        Ok(FixtureTransport::new(&format!("fixture {}", path), values))
+    }
+}
+
+impl GattTransport for FixtureTransport {
+    fn connect<'a>(&'a self, _monitor: &'a ConnectionMonitor) -> BoxFuture<'a, bluer::Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn read(&self, uuid: Uuid) -> BoxFuture<'_, bluer::Result<Option<Vec<u8>>>> {
+        let value = self.values.lock().unwrap().get(&uuid).cloned();
+        Box::pin(async move { Ok(value) })
+    }
+
+    fn write<'a>(&'a self, uuid: Uuid, value: &'a [u8]) -> BoxFuture<'a, bluer::Result<()>> {
+        self.values.lock().unwrap().insert(uuid, value.to_vec());
+        Box::pin(async { Ok(()) })
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
@@ -1,4 +1,5 @@
 mod inverter;
+mod schedule;
 mod usb_can_battery;
 mod config {
     pub const INFLUXDB2_HOST: &str = "INFLUXDB2_HOST";
@@ -8,50 +9,166 @@ mod config {
 
     pub const POOLING_PERIOD: &str = "POOLING_PERIOD";
     pub const POOLING_NIGHT_PERIOD: &str = "POOLING_NIGHT_PERIOD";
+    pub const POOLING_LATITUDE: &str = "POOLING_LATITUDE";
+    pub const POOLING_LONGITUDE: &str = "POOLING_LONGITUDE";
+    pub const POOLING_SUNRISE_OFFSET: &str = "POOLING_SUNRISE_OFFSET";
+    pub const POOLING_SUNSET_OFFSET: &str = "POOLING_SUNSET_OFFSET";
+    pub const POOLING_TIMETABLE: &str = "POOLING_TIMETABLE";
 
     pub const INVERTER_BT_ADDRESS: &str = "INVERTER_BT_ADDRESS";
+    pub const INVERTER_BT_PERSISTENT: &str = "INVERTER_BT_PERSISTENT";
+    pub const INVERTER_BT_DISCOVERY_TIMEOUT: &str = "INVERTER_BT_DISCOVERY_TIMEOUT";
+    pub const INVERTER_BT_CONNECT_TIMEOUT: &str = "INVERTER_BT_CONNECT_TIMEOUT";
+    pub const INVERTER_BT_BACKOFF_MIN: &str = "INVERTER_BT_BACKOFF_MIN";
+    pub const INVERTER_BT_BACKOFF_MAX: &str = "INVERTER_BT_BACKOFF_MAX";
+    pub const INVERTER_BT_ADAPTER: &str = "INVERTER_BT_ADAPTER";
+    pub const INVERTER_BT_ADAPTER_RESET_FAILURES: &str = "INVERTER_BT_ADAPTER_RESET_FAILURES";
+    pub const INVERTER_TRANSPORT: &str = "INVERTER_TRANSPORT";
+    pub const INVERTER_FIXTURE_FILE: &str = "INVERTER_FIXTURE_FILE";
+    pub const INVERTER_REPLAY_FILE: &str = "INVERTER_REPLAY_FILE";
+    pub const INVERTER_CAPTURE_FILE: &str = "INVERTER_CAPTURE_FILE";
+    pub const INVERTER_SERIAL_DEVICE: &str = "INVERTER_SERIAL_DEVICE";
+    pub const INVERTER_SERIAL_BAUD_RATE: &str = "INVERTER_SERIAL_BAUD_RATE";
+    pub const INVERTER_EVENTS_FILE: &str = "INVERTER_EVENTS_FILE";
+    pub const INVERTER_ENERGY_FILE: &str = "INVERTER_ENERGY_FILE";
+    pub const INVERTER_REFRESH_PRODUCT_INFO: &str = "INVERTER_REFRESH_PRODUCT_INFO";
+    pub const INVERTER_REFRESH_RATINGS: &str = "INVERTER_REFRESH_RATINGS";
+    pub const INVERTER_REFRESH_PARAMETERS: &str = "INVERTER_REFRESH_PARAMETERS";
 
     pub const WEB_SERVER_PORT: &str = "WEB_SERVER_PORT";
+    pub const WEB_PARAMETERS_ENABLED: &str = "WEB_PARAMETERS_ENABLED";
 
     pub const CANBUS_DEBUG_MSGS: &str = "CANBUS_DEBUG_MSGS";
     pub const CANBUS_TTY_DEVICE: &str = "CANBUS_TTY_DEVICE";
     pub const CANBUS_TTY_BAUD_RATE: &str = "CANBUS_TTY_BAUD_RATE";
+    pub const CANBUS_INTERFACE: &str = "CANBUS_INTERFACE";
+    pub const CANBUS_PROTOCOL: &str = "CANBUS_PROTOCOL";
+    pub const CANBUS_TTY_PROTOCOL: &str = "CANBUS_TTY_PROTOCOL";
+    pub const CANBUS_BITRATE: &str = "CANBUS_BITRATE";
+    pub const CANBUS_ADAPTER_SETUP: &str = "CANBUS_ADAPTER_SETUP";
+    pub const CANBUS_MODE: &str = "CANBUS_MODE";
+    pub const CANBUS_FRAME_TYPE: &str = "CANBUS_FRAME_TYPE";
+    pub const CANBUS_FILTER_ID: &str = "CANBUS_FILTER_ID";
+    pub const CANBUS_FILTER_MASK: &str = "CANBUS_FILTER_MASK";
+    pub const CANBUS_RECORD_FILE: &str = "CANBUS_RECORD_FILE";
+    pub const CANBUS_RECORD_MAX_SIZE: &str = "CANBUS_RECORD_MAX_SIZE";
+    pub const CANBUS_RECORD_FILES: &str = "CANBUS_RECORD_FILES";
+    pub const CANBUS_REPLAY_FILE: &str = "CANBUS_REPLAY_FILE";
+    pub const CANBUS_REPLAY_SPEED: &str = "CANBUS_REPLAY_SPEED";
+    pub const CANBUS_REPLAY_SAVE_TO_DB: &str = "CANBUS_REPLAY_SAVE_TO_DB";
+    pub const CANBUS_SEND_ENABLED: &str = "CANBUS_SEND_ENABLED";
 
     pub const DEFAULT_WEB_SERVER_PORT: u16 = 9999;
     pub const DEFAULT_CANBUS_BAUD_RATE: u32 = 2_000_000;
+    pub const DEFAULT_CANBUS_BITRATE: u32 = 500_000;
+    pub const DEFAULT_CANBUS_PROTOCOL: &str = "dyness";
+    pub const DEFAULT_CANBUS_RECORD_MAX_SIZE: u64 = 10_000_000;
+    pub const DEFAULT_CANBUS_RECORD_FILES: usize = 5;
     pub const DEFAULT_BT_PERIOD: u64 = 30;
     pub const DEFAULT_BT_NIGHT_PERIOD: u64 = 300;
+    pub const DEFAULT_BT_DISCOVERY_TIMEOUT: u64 = 30;
+    pub const DEFAULT_BT_CONNECT_TIMEOUT: u64 = 30;
+    pub const DEFAULT_BT_BACKOFF_MIN: u64 = 5;
+    pub const DEFAULT_BT_BACKOFF_MAX: u64 = 600;
+    pub const DEFAULT_BT_ADAPTER_RESET_FAILURES: u32 = 5;
+    pub const DEFAULT_EVENTS_FILE: &str = "events.json";
+    pub const DEFAULT_ENERGY_FILE: &str = "energy.json";
 }
 
-use crate::usb_can_battery::{Decoder, DynessBatteryStatus, FrameType};
+use crate::usb_can_battery::candump::{CandumpRecorder, CandumpReplaySource};
+use crate::usb_can_battery::protocol::BatteryProtocol;
+use crate::usb_can_battery::slcan::SlcanFrameSource;
+use crate::usb_can_battery::socketcan::SocketCanSource;
+use crate::usb_can_battery::{
+    AdapterMode, AdapterSettings, FrameRequest, FrameSender, FrameSource, FrameStats, FrameType,
+    SerialFrameSource,
+};
 use actix_cors::Cors;
-use actix_web::{get, rt, web, App, HttpResponse, HttpServer, Responder};
+use actix_web::{get, post, rt, web, App, HttpResponse, HttpServer, Responder};
 use bluer::Address;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Local, Utc};
 use dotenvy::dotenv;
 use inverter::{
-    bt::{BTInterface, InfluxData},
-    InverterData,
+    bt::{BTInterface, Backoff, BluerTransport, InfluxData, RefreshIntervals, RefreshTrigger},
+    capture::{CaptureTransport, ReplayTransport},
+    energy::{EnergyMeter, EnergyQuery},
+    events::{EventFilter, EventTracker},
+    parameters::{InverterParameter, ParameterWriter},
+    pi30::Pi30Transport,
+    transport::{ConnectionMonitor, ConnectionStatus, FixtureTransport, GattTransport},
+    InverterSnapshot, InverterTotals,
 };
+use schedule::{Location, PollingSchedule, ScheduleStatus};
 use serde::Serialize;
 use serde_json::json;
-use std::{io, sync::Arc, sync::RwLock, thread, time::Duration, time::Instant};
+use std::{
+    collections::BTreeMap, io, sync::Arc, sync::RwLock, thread, time::Duration, time::Instant,
+};
 use tokio::{runtime::Handle, signal};
 
+/// Shared state of a single polled inverter.
+#[derive(Clone)]
+pub struct InverterState {
+    id: String,
+    snapshot: Arc<RwLock<Option<InverterSnapshot>>>,
+    last_update: Arc<RwLock<Option<DateTime<Utc>>>>,
+    parameter_writer: ParameterWriter,
+    refresh: RefreshTrigger,
+    connection: ConnectionMonitor,
+}
+
 #[derive(Clone)]
 pub struct AppState {
-    inverter: Arc<RwLock<Option<InverterData>>>,
-    battery: Arc<RwLock<Option<DynessBatteryStatus>>>,
-    dyness_protocol: Arc<RwLock<usb_can_battery::dyness::DynessCanProtocol>>,
+    inverters: Vec<InverterState>,
+    battery: Arc<RwLock<Box<dyn BatteryProtocol>>>,
 
     start_time: Instant,
-    inverter_last_update: Arc<RwLock<Option<DateTime<Utc>>>>,
     battery_last_update: Arc<RwLock<Option<DateTime<Utc>>>>,
 
-    bluetooth_device: String,
     canbus_device: Option<String>,
     canbus_baud_rate: Option<u32>,
-    bluetooth_polling_period: u64,
+    canbus_stats: Arc<RwLock<FrameStats>>,
+    can_sender: Option<FrameSender>,
+    schedule: PollingSchedule,
+    events: Arc<RwLock<EventTracker>>,
+    energy: Arc<RwLock<EnergyMeter>>,
+}
+
+impl AppState {
+    /// Find an inverter by its id (the Bluetooth address, case insensitive).
+    fn inverter(&self, id: &str) -> Option<&InverterState> {
+        self.inverters
+            .iter()
+            .find(|inverter| inverter.id.eq_ignore_ascii_case(id))
+    }
+}
+
+#[derive(Serialize)]
+struct InverterStatus {
+    id: String,
+    last_update: Option<DateTime<Utc>>,
+    connection: ConnectionStatus,
+    /// Values that could not be parsed, by characteristic id.
+    parse_errors: BTreeMap<String, u64>,
+}
+
+impl From<&InverterState> for InverterStatus {
+    fn from(inverter: &InverterState) -> Self {
+        InverterStatus {
+            id: inverter.id.clone(),
+            last_update: *inverter.last_update.read().unwrap(),
+            connection: inverter.connection.status(),
+            parse_errors: inverter
+                .snapshot
+                .read()
+                .unwrap()
+                .iter()
+                .flat_map(|snapshot| &snapshot.characteristics)
+                .filter(|(_, read)| read.parse_errors > 0)
+                .map(|(id, read)| (id.clone(), read.parse_errors))
+                .collect(),
+        }
+    }
 }
 
 #[derive(Serialize)]
@@ -64,15 +181,109 @@ struct ServiceStatus {
     bluetooth_device: Option<String>,
     bluetooth_last_update: Option<DateTime<Utc>>,
     bluetooth_polling_period: u64,
+    bluetooth_schedule: ScheduleStatus,
+    bluetooth_connection: ConnectionStatus,
+    inverters: Vec<InverterStatus>,
 
     canbus: bool,
     canbus_device: Option<String>,
     canbus_baud_rate: Option<u32>,
     canbus_last_update: Option<DateTime<Utc>>,
+    canbus_stats: FrameStats,
+}
+
+/// Run the `scan`, `pair` and `unpair` commands, keeping the inverter addresses of the config
+/// file in sync with the paired inverters.
+async fn run_pairing(
+    command: &str,
+    arg: Option<&String>,
+    adapter: Option<&str>,
+    config_path: &std::path::Path,
+) -> Result<(), String> {
+    if command == "scan" {
+        let duration = arg
+            .map(|v| v.parse::<u64>().map_err(|_| "Usage: bt scan [seconds]"))
+            .transpose()?
+            .unwrap_or(config::DEFAULT_BT_DISCOVERY_TIMEOUT);
+        let candidates = inverter::pairing::scan(adapter, Duration::from_secs(duration))
+            .await
+            .map_err(|e| e.to_string())?;
+        let inverters: Vec<_> = candidates.iter().filter(|c| c.inverter).collect();
+        if inverters.is_empty() {
+            println!("No inverter advertising the 0x1810 service found, nearby devices:");
+        }
+        let shown = if inverters.is_empty() {
+            candidates.iter().collect()
+        } else {
+            inverters
+        };
+        for candidate in shown {
+            println!(
+                "{}  {:>4} dBm  {}",
+                candidate.address,
+                candidate
+                    .rssi
+                    .map(|rssi| rssi.to_string())
+                    .unwrap_or("?".to_owned()),
+                candidate.name.as_deref().unwrap_or("(unknown)")
+            );
+        }
+        return Ok(());
+    }
+
+    let usage = format!("Usage: bt {} <address>", command);
+    let address = arg
+        .ok_or(usage.clone())?
+        .parse::<Address>()
+        .map_err(|_| usage)?;
+    if command == "pair" {
+        if std::env::var("INVERTER_BT_PASSKEY").is_err() {
+            return Err("INVERTER_BT_PASSKEY must be set.".to_owned());
+        }
+        let discovery_timeout = std::env::var(config::INVERTER_BT_DISCOVERY_TIMEOUT)
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(config::DEFAULT_BT_DISCOVERY_TIMEOUT);
+        inverter::pairing::pair(address, adapter, Duration::from_secs(discovery_timeout))
+            .await
+            .map_err(|e| e.to_string())?;
+    } else {
+        inverter::pairing::unpair(address, adapter)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    inverter::pairing::update_config(config_path, address, command == "pair")
 }
 
 #[actix_web::main]
 async fn main() {
+    // Offline decoding of a capture: bt replay <capture file> [device]
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("replay") {
+        let path = args
+            .get(2)
+            .expect("Usage: bt replay <capture file> [device]");
+        let device = args.get(3).map(String::as_str);
+        if let Err(e) = inverter::capture::decode_capture(path, device).await {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Pairing: bt scan [seconds] | bt pair <address> | bt unpair <address>
+    if let Some(command @ ("scan" | "pair" | "unpair")) = args.get(1).map(String::as_str) {
+        let config_path = dotenv().expect(".env file not found.");
+        let adapter = std::env::var(config::INVERTER_BT_ADAPTER)
+            .ok()
+            .filter(|v| !v.is_empty());
+        if let Err(e) = run_pairing(command, args.get(2), adapter.as_deref(), &config_path).await {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     println!("Starting bluetooth power watch...");
 
     dotenv().expect(".env file not found.");
@@ -92,15 +303,72 @@ async fn main() {
     let bt_night_period = bt_night_period
         .parse::<u64>()
         .unwrap_or(config::DEFAULT_BT_NIGHT_PERIOD);
-    let device_address =
-        std::env::var(config::INVERTER_BT_ADDRESS).expect("INVERTER_BT_ADDRESS must be set.");
-    let device_address: Vec<u8> = device_address
-        .split(':')
-        .map(|x| hex::decode(x).expect("Invalid bluetooth address")[0])
-        .collect();
-    let device_address: [u8; 6] = device_address
-        .try_into()
-        .expect("Invalid bluetooth address length");
+    let mut schedule = PollingSchedule::new(
+        Duration::from_secs(bt_period),
+        Duration::from_secs(bt_night_period),
+    );
+    let latitude = std::env::var(config::POOLING_LATITUDE)
+        .ok()
+        .map(|v| v.parse::<f64>().expect("Invalid POOLING_LATITUDE"));
+    let longitude = std::env::var(config::POOLING_LONGITUDE)
+        .ok()
+        .map(|v| v.parse::<f64>().expect("Invalid POOLING_LONGITUDE"));
+    if let (Some(latitude), Some(longitude)) = (latitude, longitude) {
+        let sunrise_offset = std::env::var(config::POOLING_SUNRISE_OFFSET)
+            .ok()
+            .and_then(|v| v.parse::<i64>().ok())
+            .unwrap_or(0);
+        let sunset_offset = std::env::var(config::POOLING_SUNSET_OFFSET)
+            .ok()
+            .and_then(|v| v.parse::<i64>().ok())
+            .unwrap_or(0);
+        schedule = schedule.with_location(
+            Location {
+                latitude,
+                longitude,
+            },
+            sunrise_offset,
+            sunset_offset,
+        );
+    }
+    if let Ok(timetable) = std::env::var(config::POOLING_TIMETABLE) {
+        schedule = schedule.with_timetable(
+            schedule::parse_timetable(&timetable).expect("Invalid POOLING_TIMETABLE"),
+        );
+    }
+    let transport_kind = std::env::var(config::INVERTER_TRANSPORT).unwrap_or_default();
+
+    // Inverters are identified by their Bluetooth address, or by their serial device with PI30
+    let device_ids: Vec<String> = if transport_kind == "pi30" {
+        std::env::var(config::INVERTER_SERIAL_DEVICE)
+            .expect("INVERTER_SERIAL_DEVICE must be set.")
+            .split(',')
+            .map(|device| device.trim().to_owned())
+            .collect()
+    } else {
+        let device_addresses =
+            std::env::var(config::INVERTER_BT_ADDRESS).expect("INVERTER_BT_ADDRESS must be set.");
+        let device_addresses: Vec<String> = device_addresses
+            .split(',')
+            .map(str::trim)
+            .filter(|address| !address.is_empty())
+            .map(|address| {
+                let address: Vec<u8> = address
+                    .trim()
+                    .split(':')
+                    .map(|x| hex::decode(x).expect("Invalid bluetooth address")[0])
+                    .collect();
+                let address: [u8; 6] = address
+                    .try_into()
+                    .expect("Invalid bluetooth address length");
+                Address::new(address).to_string()
+            })
+            .collect();
+        if device_addresses.is_empty() {
+            panic!("INVERTER_BT_ADDRESS has no inverter address, pair one with `bt pair`.");
+        }
+        device_addresses
+    };
     let influx_data = InfluxData::new(
         influxdb2_host,
         influxdb2_org,
@@ -113,27 +381,239 @@ async fn main() {
         .and_then(|v| v.parse::<u16>().ok())
         .unwrap_or(config::DEFAULT_WEB_SERVER_PORT);
 
-    let canbus_device = std::env::var(config::CANBUS_TTY_DEVICE).ok();
+    // A candump log replay takes precedence over the SocketCAN interface, which takes
+    // precedence over the USB-CAN-A serial device
+    let canbus_replay_file = std::env::var(config::CANBUS_REPLAY_FILE)
+        .ok()
+        .filter(|v| !v.is_empty());
+    let canbus_replay_speed: f64 = std::env::var(config::CANBUS_REPLAY_SPEED)
+        .ok()
+        .map(|v| {
+            v.parse::<f64>()
+                .ok()
+                .filter(|speed| speed.is_finite() && *speed >= 0.0)
+                .expect("Invalid CANBUS_REPLAY_SPEED, a speed factor >= 0 is expected")
+        })
+        .unwrap_or(1.0);
+    // Replayed frames are only written to Influx on request, never recorded again
+    let canbus_replay_save_to_db = std::env::var(config::CANBUS_REPLAY_SAVE_TO_DB)
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false);
+    let canbus_interface = std::env::var(config::CANBUS_INTERFACE)
+        .ok()
+        .filter(|v| !v.is_empty());
+    let canbus_device = canbus_replay_file
+        .clone()
+        .or_else(|| canbus_interface.clone())
+        .or_else(|| std::env::var(config::CANBUS_TTY_DEVICE).ok());
+    // Received frames are recorded into a rotated candump log when enabled
+    let canbus_record_file = std::env::var(config::CANBUS_RECORD_FILE)
+        .ok()
+        .filter(|v| !v.is_empty());
+    let canbus_record_max_size: u64 = std::env::var(config::CANBUS_RECORD_MAX_SIZE)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(config::DEFAULT_CANBUS_RECORD_MAX_SIZE);
+    let canbus_record_files: usize = std::env::var(config::CANBUS_RECORD_FILES)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(config::DEFAULT_CANBUS_RECORD_FILES);
     let canbus_baud_rate: u32 = std::env::var(config::CANBUS_TTY_BAUD_RATE)
         .ok()
         .and_then(|v| v.parse().ok())
         .unwrap_or(config::DEFAULT_CANBUS_BAUD_RATE);
+    let battery_protocol = usb_can_battery::protocol::from_name(
+        &std::env::var(config::CANBUS_PROTOCOL)
+            .unwrap_or(config::DEFAULT_CANBUS_PROTOCOL.to_owned()),
+    )
+    .expect("Invalid CANBUS_PROTOCOL");
+    // USB-CAN-A binary protocol by default, or SLCAN (Lawicel) ASCII adapters
+    let canbus_slcan =
+        std::env::var(config::CANBUS_TTY_PROTOCOL).is_ok_and(|v| v.eq_ignore_ascii_case("slcan"));
+    let canbus_bitrate: u32 = std::env::var(config::CANBUS_BITRATE)
+        .ok()
+        .map(|v| v.parse().expect("Invalid CANBUS_BITRATE"))
+        .unwrap_or(config::DEFAULT_CANBUS_BITRATE);
+    // The USB-CAN-A adapter is configured on open unless disabled
+    let canbus_settings = std::env::var(config::CANBUS_ADAPTER_SETUP)
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(true)
+        .then(|| {
+            let hex = |key: &str| {
+                std::env::var(key).ok().map(|v| {
+                    u32::from_str_radix(v.trim_start_matches("0x"), 16)
+                        .unwrap_or_else(|_| panic!("Invalid {}", key))
+                })
+            };
+            AdapterSettings {
+                bitrate: canbus_bitrate,
+                mode: std::env::var(config::CANBUS_MODE)
+                    .map(|v| v.parse::<AdapterMode>().expect("Invalid CANBUS_MODE"))
+                    .unwrap_or(AdapterMode::Normal),
+                frame_type: match std::env::var(config::CANBUS_FRAME_TYPE).as_deref() {
+                    Ok("extended") => FrameType::Extended,
+                    Ok("standard") | Err(_) => FrameType::Standard,
+                    Ok(_) => panic!("Invalid CANBUS_FRAME_TYPE"),
+                },
+                filter_id: hex(config::CANBUS_FILTER_ID).unwrap_or(0),
+                filter_mask: hex(config::CANBUS_FILTER_MASK).unwrap_or(0),
+            }
+        });
+
+    let fixture_files: Vec<String> = std::env::var(config::INVERTER_FIXTURE_FILE)
+        .unwrap_or_default()
+        .split(',')
+        .map(|file| file.trim().to_owned())
+        .filter(|file| !file.is_empty())
+        .collect();
+    let persistent = std::env::var(config::INVERTER_BT_PERSISTENT)
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(true);
+    let discovery_timeout = std::env::var(config::INVERTER_BT_DISCOVERY_TIMEOUT)
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(config::DEFAULT_BT_DISCOVERY_TIMEOUT);
+    let connect_timeout = std::env::var(config::INVERTER_BT_CONNECT_TIMEOUT)
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(config::DEFAULT_BT_CONNECT_TIMEOUT);
+
+    let bt_adapter = std::env::var(config::INVERTER_BT_ADAPTER)
+        .ok()
+        .filter(|adapter| !adapter.is_empty());
+    let bt_adapter_reset_failures = std::env::var(config::INVERTER_BT_ADAPTER_RESET_FAILURES)
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .unwrap_or(config::DEFAULT_BT_ADAPTER_RESET_FAILURES);
+
+    let capture_file = std::env::var(config::INVERTER_CAPTURE_FILE).ok();
+
+    let default_intervals = RefreshIntervals::default();
+    let refresh_interval = |key: &str, default: Duration| {
+        std::env::var(key)
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(default)
+    };
+    let refresh_intervals = RefreshIntervals {
+        product_info: refresh_interval(
+            config::INVERTER_REFRESH_PRODUCT_INFO,
+            default_intervals.product_info,
+        ),
+        ratings: refresh_interval(config::INVERTER_REFRESH_RATINGS, default_intervals.ratings),
+        parameters: refresh_interval(
+            config::INVERTER_REFRESH_PARAMETERS,
+            default_intervals.parameters,
+        ),
+    };
+
+    // An empty INVERTER_EVENTS_FILE keeps the event history in memory only
+    let events_file = std::env::var(config::INVERTER_EVENTS_FILE)
+        .unwrap_or_else(|_| config::DEFAULT_EVENTS_FILE.to_owned());
+    let events = EventTracker::load(Some(events_file).filter(|file| !file.is_empty()));
+
+    // An empty INVERTER_ENERGY_FILE keeps the energy counters in memory only
+    let energy_file = std::env::var(config::INVERTER_ENERGY_FILE)
+        .unwrap_or_else(|_| config::DEFAULT_ENERGY_FILE.to_owned());
+    let energy = EnergyMeter::load(Some(energy_file).filter(|file| !file.is_empty()));
+
+    // One interface per inverter, each one polled independently
+    let mut bt_interfaces = Vec::new();
+    for (index, device_id) in device_ids.iter().enumerate() {
+        let transport: Box<dyn GattTransport> = match transport_kind.as_str() {
+            "fixture" => {
+                // Each inverter takes its own fixture, the last one is reused for the rest
+                let fixture_file = fixture_files
+                    .get(index)
+                    .or(fixture_files.last())
+                    .expect("INVERTER_FIXTURE_FILE must be set.");
+                Box::new(FixtureTransport::from_file(fixture_file).expect("Invalid fixture file"))
+            }
+            "replay" => {
+                let replay_file = std::env::var(config::INVERTER_REPLAY_FILE)
+                    .expect("INVERTER_REPLAY_FILE must be set.");
+                Box::new(
+                    ReplayTransport::from_file(&replay_file, Some(device_id), true)
+                        .expect("Invalid capture file"),
+                )
+            }
+            "pi30" => {
+                let baud_rate = std::env::var(config::INVERTER_SERIAL_BAUD_RATE)
+                    .ok()
+                    .and_then(|v| v.parse::<u32>().ok())
+                    .unwrap_or(inverter::pi30::DEFAULT_BAUD_RATE);
+                Box::new(Pi30Transport::new(device_id, baud_rate))
+            }
+            _ => Box::new(
+                BluerTransport::new(
+                    device_id.parse().expect("Invalid bluetooth address"),
+                    persistent,
+                    Duration::from_secs(discovery_timeout),
+                    Duration::from_secs(connect_timeout),
+                )
+                .with_adapter(bt_adapter.clone())
+                .with_adapter_reset(bt_adapter_reset_failures),
+            ),
+        };
+        let transport: Box<dyn GattTransport> = match &capture_file {
+            Some(capture_file) => Box::new(
+                CaptureTransport::new(transport, device_id, capture_file)
+                    .expect("Unable to open capture file"),
+            ),
+            None => transport,
+        };
+        println!("Inverter {} transport: {}", device_id, transport.name());
+        bt_interfaces.push(
+            BTInterface::new(device_id.clone(), transport, influx_data.clone())
+                .with_refresh_intervals(refresh_intervals),
+        );
+    }
+
+    // Frames to send are queued to the CAN bus service, polled between received frames
+    let (can_sender, mut can_send_requests) = FrameSender::channel();
+    let can_sender = canbus_device.is_some().then_some(can_sender);
 
     let state = AppState {
-        inverter: Arc::new(RwLock::new(None)),
-        battery: Arc::new(RwLock::new(None)),
-        dyness_protocol: Arc::new(RwLock::new(
-            usb_can_battery::dyness::DynessCanProtocol::default(),
-        )),
+        inverters: bt_interfaces
+            .iter()
+            .zip(device_ids.iter())
+            .map(|(bt_interface, device_id)| InverterState {
+                id: device_id.clone(),
+                snapshot: Arc::new(RwLock::new(None)),
+                last_update: Arc::new(RwLock::new(None)),
+                parameter_writer: bt_interface.parameter_writer(),
+                refresh: bt_interface.refresh_trigger(),
+                connection: bt_interface.connection_monitor(),
+            })
+            .collect(),
+        battery: Arc::new(RwLock::new(battery_protocol)),
         start_time: Instant::now(),
-        inverter_last_update: Arc::new(RwLock::new(None)),
         battery_last_update: Arc::new(RwLock::new(None)),
-        bluetooth_device: Address::new(device_address).to_string(),
         canbus_device: canbus_device,
-        canbus_baud_rate: Some(canbus_baud_rate),
-        bluetooth_polling_period: bt_period,
+        canbus_baud_rate: (canbus_replay_file.is_none() && canbus_interface.is_none())
+            .then_some(canbus_baud_rate),
+        canbus_stats: Arc::new(RwLock::new(FrameStats::default())),
+        can_sender: can_sender.clone(),
+        schedule: schedule.clone(),
+        events: Arc::new(RwLock::new(events)),
+        energy: Arc::new(RwLock::new(energy)),
     };
 
+    // The parameter endpoints change inverter settings without authentication, opt-in only
+    let web_parameters_enabled = std::env::var(config::WEB_PARAMETERS_ENABLED)
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false);
+    // Same for the frames sent on the CAN bus
+    let canbus_send_enabled = std::env::var(config::CANBUS_SEND_ENABLED)
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false);
+
     // Run Web Service
     println!("Starting web server...");
     let web_server_host = "0.0.0.0".to_owned();
@@ -147,8 +627,26 @@ async fn main() {
             .service(json_response_status)
             .service(json_response_version)
             .service(json_response_inverter_info)
+            .configure(|cfg| {
+                if web_parameters_enabled {
+                    cfg.service(json_request_inverter_parameter)
+                        .service(json_request_inverter_parameter_by_id);
+                }
+            })
+            .service(json_response_inverters)
+            .service(json_response_inverters_total)
+            .service(json_response_inverter_by_id)
+            .service(json_request_inverter_refresh)
+            .service(json_request_inverter_refresh_by_id)
+            .service(json_response_events)
+            .service(json_response_energy)
             .service(json_response_can_battery_info)
             .service(json_response_can_battery_modules_info)
+            .configure(|cfg| {
+                if canbus_send_enabled {
+                    cfg.service(json_request_can_send);
+                }
+            })
     })
     .bind((web_server_host.clone(), web_server_port))
     .unwrap()
@@ -163,14 +661,32 @@ async fn main() {
 
     // Run Bluetooth Service
     println!("Starting bluetooth interface service...");
-    let mut bt_interface = BTInterface::new(Address::new(device_address), influx_data);
-    let state_for_bt = state.clone();
-    bt_interface.connect(move |data| on_emit(&state_for_bt, data));
-    rt::spawn(async move {
-        let _ = bt_interface.serve(bt_period, bt_night_period).await;
-    });
-
-    // Run USB CAN serial port Service
+    let bt_backoff_min = std::env::var(config::INVERTER_BT_BACKOFF_MIN)
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(config::DEFAULT_BT_BACKOFF_MIN);
+    let bt_backoff_max = std::env::var(config::INVERTER_BT_BACKOFF_MAX)
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(config::DEFAULT_BT_BACKOFF_MAX);
+    let bt_backoff = Backoff::new(
+        Duration::from_secs(bt_backoff_min),
+        Duration::from_secs(bt_backoff_max),
+    );
+    println!("Polling schedule: {}", schedule.describe());
+    for (mut bt_interface, inverter) in bt_interfaces.into_iter().zip(state.inverters.clone()) {
+        let events = state.events.clone();
+        let energy = state.energy.clone();
+        let energy_influx_data = influx_data.clone();
+        bt_interface
+            .connect(move |data| on_emit(&inverter, &events, &energy, &energy_influx_data, data));
+        let schedule = schedule.clone();
+        rt::spawn(async move {
+            let _ = bt_interface.serve(schedule, bt_backoff).await;
+        });
+    }
+
+    // Run CAN bus service, USB-CAN-A serial port, SocketCAN interface or candump replay
     let handle = Handle::current();
     let state_for_can = state.clone();
     let can_debug = std::env::var(config::CANBUS_DEBUG_MSGS)
@@ -178,79 +694,138 @@ async fn main() {
         .and_then(|v| v.parse::<bool>().ok())
         .unwrap_or(false);
 
-    if let Some(port_name) = state_for_can.canbus_device {
-        println!("Starting USB CAN serial interface service...");
+    if let Some(port_name) = state_for_can.canbus_device.clone() {
+        println!(
+            "Starting CAN bus service ({} battery protocol)...",
+            state_for_can.battery.read().unwrap().name()
+        );
         thread::spawn(move || {
             let expire_time = std::time::Duration::from_secs(5 * 60); // 5min
-            const SUMMARY_FRAME_ID: u32 = 787;
+            let mut last_db_write: Option<Instant> = None;
+            // Counters of the previous connections
+            let mut previous_stats = FrameStats::default();
+            let replay = canbus_replay_file.is_some();
+            let save_to_db = !replay || canbus_replay_save_to_db;
+            if replay && canbus_record_file.is_some() {
+                println!("CAN bus frames are not recorded while replaying a candump log");
+            }
+            let mut recorder = canbus_record_file
+                .as_ref()
+                .filter(|_| !replay)
+                .and_then(|path| {
+                    let interface = canbus_interface.as_deref().unwrap_or("can0");
+                    CandumpRecorder::new(
+                        path,
+                        interface,
+                        canbus_record_max_size,
+                        canbus_record_files,
+                    )
+                    .map_err(|e| eprintln!("Unable to open candump log {}: {}", path, e))
+                    .ok()
+                });
 
             loop {
                 let expire_connection = std::time::SystemTime::now();
 
-                let mut port = serialport::new(&port_name, canbus_baud_rate)
-                    .timeout(Duration::from_millis(10))
-                    .open()
-                    .expect("Failed to open port");
-
-                let mut serial_buf: Vec<u8> = vec![0; 1];
-                let mut decoder = Decoder::new();
-                println!(
-                    "Receiving data on {} at {} baud:",
-                    &port_name, &canbus_baud_rate
-                );
+                let source: io::Result<Box<dyn FrameSource>> = match &canbus_interface {
+                    _ if replay => CandumpReplaySource::open(
+                        &port_name,
+                        canbus_replay_speed,
+                        Duration::from_millis(10),
+                    )
+                    .map(|source| Box::new(source) as Box<dyn FrameSource>),
+                    Some(interface) => SocketCanSource::open(interface, Duration::from_millis(10))
+                        .map(|source| Box::new(source) as Box<dyn FrameSource>),
+                    None if canbus_slcan => SlcanFrameSource::open(
+                        &port_name,
+                        canbus_baud_rate,
+                        canbus_bitrate,
+                        Duration::from_millis(10),
+                    )
+                    .map(|source| Box::new(source) as Box<dyn FrameSource>),
+                    None => SerialFrameSource::open(
+                        &port_name,
+                        canbus_baud_rate,
+                        canbus_settings.as_ref(),
+                        Duration::from_millis(10),
+                    )
+                    .map(|source| Box::new(source) as Box<dyn FrameSource>),
+                };
+                let mut source = match source {
+                    Ok(source) => source,
+                    Err(e) => {
+                        eprintln!("Failed to open CAN bus {}: {}", &port_name, e);
+                        // Don't leave the senders waiting for a bus that isn't there
+                        while let Ok(request) = can_send_requests.try_recv() {
+                            let _ = request
+                                .reply
+                                .send(Err("CAN bus is not connected".to_owned()));
+                        }
+                        thread::sleep(Duration::from_secs(3));
+                        continue;
+                    }
+                };
+                println!("Receiving data on {}:", source.name());
 
                 loop {
-                    // Read serial port data
-                    match port.read_exact(serial_buf.as_mut_slice()) {
-                        Ok(_) => {
-                            if let Some(frame) = decoder.append(serial_buf[0]) {
-                                match frame.id {
-                                    SUMMARY_FRAME_ID => {
-                                        if can_debug {
-                                            println!("|====>{}", frame.to_string());
-                                        }
-                                        let battery_status = DynessBatteryStatus::from(frame);
-                                        if let Ok(battery_status) = battery_status {
-                                            if can_debug {
-                                                println!("{}", battery_status.to_string());
-                                            }
-
-                                            let battery_status_copy: DynessBatteryStatus =
-                                                battery_status.clone();
-
-                                            // Update the shared state with the new battery status
-                                            *state_for_can.battery.write().unwrap() =
-                                                Some(battery_status);
-                                            *state_for_can.battery_last_update.write().unwrap() =
-                                                Some(Utc::now());
-
-                                            if can_debug {
-                                                println!("Saving CAN bus data into DB...");
-                                            }
-                                            let influx_data_copy = can_bus_influx_data.clone();
-                                            handle.spawn(async move {
-                                                battery_status_copy
-                                                    .save_to_db(influx_data_copy)
-                                                    .await;
-                                            });
-                                        }
-                                    }
-                                    _ => {
-                                        if can_debug
-                                            && frame.header.frame_type == FrameType::Standard
-                                        {
-                                            println!("{}", frame.to_string());
-                                        }
-
-                                        // Try to decode any other frame type as a Dyness protocol message
-                                        let mut dyness_protocol =
-                                            state_for_can.dyness_protocol.write().unwrap();
-                                        dyness_protocol.decode(&frame);
-                                    }
+                    let result = source.read_frame();
+                    let mut stats = previous_stats;
+                    stats.add(&source.stats());
+                    *state_for_can.canbus_stats.write().unwrap() = stats;
+
+                    while let Ok(request) = can_send_requests.try_recv() {
+                        let result = source.send_frame(&request.frame).map_err(|e| e.to_string());
+                        if can_debug {
+                            println!("<====|{} {:?}", request.frame.to_string(), result);
+                        }
+                        let _ = request.reply.send(result);
+                    }
+
+                    match result {
+                        Ok(frame) => {
+                            if can_debug && frame.header.frame_type == FrameType::Standard {
+                                println!("{}", frame.to_string());
+                            }
+                            if let Some(recorder) = recorder.as_mut() {
+                                if let Err(e) = recorder.record(&frame) {
+                                    println!("Unable to write candump log: {}", e);
                                 }
                             }
+
+                            let summary = {
+                                let mut battery = state_for_can.battery.write().unwrap();
+                                if !battery.decode(&frame) {
+                                    continue;
+                                }
+                                battery.summary()
+                            };
+                            let Some(summary) = summary else {
+                                continue;
+                            };
+                            if can_debug {
+                                println!("|====>{}", summary);
+                            }
+                            *state_for_can.battery_last_update.write().unwrap() = Some(Utc::now());
+                            if !save_to_db {
+                                continue;
+                            }
+
+                            // Follow the polling schedule cadence for DB writes
+                            let period = state_for_can.schedule.period_at(Local::now());
+                            if last_db_write.is_some_and(|t| t.elapsed() < period) {
+                                continue;
+                            }
+                            last_db_write = Some(Instant::now());
+
+                            if can_debug {
+                                println!("Saving CAN bus data into DB...");
+                            }
+                            let influx_data_copy = can_bus_influx_data.clone();
+                            handle.spawn(async move {
+                                summary.save_to_db(influx_data_copy).await;
+                            });
                         }
-                        Err(ref e) if e.kind() == io::ErrorKind::TimedOut => {
+                        Err(ref e) if e.kind() == io::ErrorKind::TimedOut && !replay => {
                             if let Ok(secs) = expire_connection.elapsed() {
                                 if secs > expire_time {
                                     // Connection has expired. Restart it!
@@ -258,6 +833,12 @@ async fn main() {
                                 }
                             }
                         }
+                        // Waiting for the next replayed frame
+                        Err(ref e) if e.kind() == io::ErrorKind::TimedOut => {}
+                        Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof && replay => {
+                            println!("{}, CAN bus service stopped", e);
+                            return;
+                        }
                         Err(e) => {
                             eprintln!("{:?}", e);
                             // An error has occurred. Restart it!
@@ -266,7 +847,8 @@ async fn main() {
                     }
                 }
 
-                drop(port);
+                previous_stats.add(&source.stats());
+                drop(source);
                 thread::sleep(Duration::from_secs(3));
             }
         });
@@ -284,10 +866,30 @@ async fn main() {
     }
 }
 
-/// Callback function to handle emitted inverter data and update the application state.
-fn on_emit(state: &AppState, data: InverterData) {
-    *state.inverter.write().unwrap() = Some(data);
-    *state.inverter_last_update.write().unwrap() = Some(Utc::now());
+/// Callback function to handle emitted inverter data and update the inverter state.
+fn on_emit(
+    inverter: &InverterState,
+    events: &Arc<RwLock<EventTracker>>,
+    energy: &Arc<RwLock<EnergyMeter>>,
+    influx_data: &InfluxData,
+    snapshot: InverterSnapshot,
+) {
+    if let Some(totals) = energy.write().unwrap().update(&inverter.id, &snapshot) {
+        rt::spawn(inverter::energy::save_to_db(
+            inverter.id.clone(),
+            totals,
+            snapshot.read_at,
+            influx_data.clone(),
+        ));
+    }
+    if let Some(inverter_events) = snapshot.events() {
+        events
+            .write()
+            .unwrap()
+            .update(&inverter.id, inverter_events, snapshot.read_at);
+    }
+    *inverter.last_update.write().unwrap() = Some(snapshot.read_at);
+    *inverter.snapshot.write().unwrap() = Some(snapshot);
 }
 
 #[get("/")]
@@ -309,7 +911,14 @@ async fn json_response_version() -> impl Responder {
 #[get("/api/status")]
 async fn json_response_status(state: web::Data<AppState>) -> impl Responder {
     let uptime = state.start_time.elapsed().as_secs();
-    let bt_last = state.inverter_last_update.read().unwrap().clone();
+    let schedule = state.schedule.status();
+    // Bluetooth is only as recent as its least recently updated inverter
+    let bt_last = state
+        .inverters
+        .iter()
+        .map(|inverter| *inverter.last_update.read().unwrap())
+        .min()
+        .flatten();
     let can_last = state.battery_last_update.read().unwrap().clone();
 
     let bluetooth = bt_last
@@ -331,12 +940,22 @@ async fn json_response_status(state: web::Data<AppState>) -> impl Responder {
         version: env!("CARGO_PKG_VERSION"),
         uptime_secs: uptime,
 
-        bluetooth_device: Some(state.bluetooth_device.clone()),
+        bluetooth_device: Some(
+            state
+                .inverters
+                .iter()
+                .map(|inverter| inverter.id.clone())
+                .collect::<Vec<String>>()
+                .join(","),
+        ),
         bluetooth: bt_last
             .as_ref()
             .is_some_and(|t| (Utc::now() - *t).num_seconds() < 120),
         bluetooth_last_update: bt_last,
-        bluetooth_polling_period: state.bluetooth_polling_period,
+        bluetooth_polling_period: schedule.period_secs,
+        bluetooth_schedule: schedule,
+        bluetooth_connection: state.inverters[0].connection.status(),
+        inverters: state.inverters.iter().map(InverterStatus::from).collect(),
 
         canbus_device: state.canbus_device.clone(),
         canbus_baud_rate: state.canbus_baud_rate,
@@ -344,6 +963,7 @@ async fn json_response_status(state: web::Data<AppState>) -> impl Responder {
             .as_ref()
             .is_some_and(|t| (Utc::now() - *t).num_seconds() < 300),
         canbus_last_update: can_last,
+        canbus_stats: *state.canbus_stats.read().unwrap(),
     };
 
     HttpResponse::Ok().json(status)
@@ -351,9 +971,136 @@ async fn json_response_status(state: web::Data<AppState>) -> impl Responder {
 
 #[get("/api/info")]
 async fn json_response_inverter_info(state: web::Data<AppState>) -> impl Responder {
-    let guard = state.inverter.read().unwrap();
+    inverter_info_response(&state.inverters[0])
+}
+
+#[post("/api/parameters")]
+async fn json_request_inverter_parameter(
+    state: web::Data<AppState>,
+    parameter: web::Json<InverterParameter>,
+) -> impl Responder {
+    inverter_parameter_response(&state.inverters[0], parameter.into_inner()).await
+}
+
+#[get("/api/inverters")]
+async fn json_response_inverters(state: web::Data<AppState>) -> impl Responder {
+    let inverters: Vec<InverterStatus> = state.inverters.iter().map(InverterStatus::from).collect();
+    HttpResponse::Ok().json(inverters)
+}
 
-    match guard.as_ref().and_then(|data| data.to_json().ok()) {
+#[get("/api/inverters/total")]
+async fn json_response_inverters_total(state: web::Data<AppState>) -> impl Responder {
+    let snapshots: Vec<(&str, InverterSnapshot)> = state
+        .inverters
+        .iter()
+        .filter_map(|inverter| {
+            let snapshot = inverter.snapshot.read().unwrap().clone()?;
+            Some((inverter.id.as_str(), snapshot))
+        })
+        .collect();
+
+    if snapshots.is_empty() {
+        return HttpResponse::ServiceUnavailable().json(json!({
+            "error": "No inverter data available"
+        }));
+    }
+
+    // Inverters that missed two polls in a row are considered offline
+    let period = state.schedule.period_at(Local::now()) * 2;
+    let max_age = chrono::Duration::from_std(period)
+        .unwrap_or(chrono::Duration::max_value())
+        .max(chrono::Duration::seconds(120));
+    let totals = InverterTotals::from_snapshots(
+        snapshots.iter().map(|(id, snapshot)| (*id, snapshot)),
+        Utc::now(),
+        max_age,
+    );
+    if totals.inverters == 0 {
+        return HttpResponse::ServiceUnavailable().json(json!({
+            "error": "No recent inverter data available",
+            "stale_inverters": totals.stale_inverters,
+        }));
+    }
+    HttpResponse::Ok().json(totals)
+}
+
+#[get("/api/inverters/{id}")]
+async fn json_response_inverter_by_id(
+    state: web::Data<AppState>,
+    id: web::Path<String>,
+) -> impl Responder {
+    match state.inverter(&id) {
+        Some(inverter) => inverter_info_response(inverter),
+        None => HttpResponse::NotFound().json(json!({
+            "error": format!("Unknown inverter {}", id)
+        })),
+    }
+}
+
+#[post("/api/inverters/{id}/parameters")]
+async fn json_request_inverter_parameter_by_id(
+    state: web::Data<AppState>,
+    id: web::Path<String>,
+    parameter: web::Json<InverterParameter>,
+) -> impl Responder {
+    match state.inverter(&id) {
+        Some(inverter) => inverter_parameter_response(inverter, parameter.into_inner()).await,
+        None => HttpResponse::NotFound().json(json!({
+            "error": format!("Unknown inverter {}", id)
+        })),
+    }
+}
+
+#[get("/api/events")]
+async fn json_response_events(
+    state: web::Data<AppState>,
+    filter: web::Query<EventFilter>,
+) -> impl Responder {
+    HttpResponse::Ok().json(state.events.read().unwrap().query(&filter))
+}
+
+#[post("/api/refresh")]
+async fn json_request_inverter_refresh(state: web::Data<AppState>) -> impl Responder {
+    inverter_refresh_response(&state.inverters[0])
+}
+
+#[post("/api/inverters/{id}/refresh")]
+async fn json_request_inverter_refresh_by_id(
+    state: web::Data<AppState>,
+    id: web::Path<String>,
+) -> impl Responder {
+    match state.inverter(&id) {
+        Some(inverter) => inverter_refresh_response(inverter),
+        None => HttpResponse::NotFound().json(json!({
+            "error": format!("Unknown inverter {}", id)
+        })),
+    }
+}
+
+/// Poll every characteristic group of the inverter right away.
+fn inverter_refresh_response(inverter: &InverterState) -> HttpResponse {
+    inverter.refresh.request();
+    HttpResponse::Accepted().json(json!({
+        "status": "Refresh requested"
+    }))
+}
+
+#[get("/api/energy")]
+async fn json_response_energy(
+    state: web::Data<AppState>,
+    query: web::Query<EnergyQuery>,
+) -> impl Responder {
+    let date = query.date.unwrap_or_else(|| Local::now().date_naive());
+    HttpResponse::Ok().json(state.energy.read().unwrap().report(query.period, date))
+}
+
+fn inverter_info_response(inverter: &InverterState) -> HttpResponse {
+    let guard = inverter.snapshot.read().unwrap();
+
+    match guard
+        .as_ref()
+        .and_then(|snapshot| snapshot.to_json_with_age(Utc::now()).ok())
+    {
         Some(json) => HttpResponse::Ok()
             .content_type("application/json")
             .body(json),
@@ -363,14 +1110,26 @@ async fn json_response_inverter_info(state: web::Data<AppState>) -> impl Respond
     }
 }
 
+async fn inverter_parameter_response(
+    inverter: &InverterState,
+    parameter: InverterParameter,
+) -> HttpResponse {
+    match inverter.parameter_writer.write(parameter).await {
+        Ok(()) => HttpResponse::Ok().json(json!({
+            "status": "OK"
+        })),
+        Err(err) => HttpResponse::BadRequest().json(json!({
+            "error": err
+        })),
+    }
+}
+
 #[get("/api/info/battery")]
 async fn json_response_can_battery_info(state: web::Data<AppState>) -> impl Responder {
-    let guard = state.battery.read().unwrap();
+    let summary = state.battery.read().unwrap().summary();
 
-    match guard.as_ref().and_then(|data| data.to_json().ok()) {
-        Some(json) => HttpResponse::Ok()
-            .content_type("application/json")
-            .body(json),
+    match summary {
+        Some(summary) => HttpResponse::Ok().json(summary),
         None => HttpResponse::ServiceUnavailable().json(json!({
             "error": "No battery data available"
         })),
@@ -379,7 +1138,7 @@ async fn json_response_can_battery_info(state: web::Data<AppState>) -> impl Resp
 
 #[get("/api/info/battery/modules")]
 async fn json_response_can_battery_modules_info(state: web::Data<AppState>) -> impl Responder {
-    let guard = state.dyness_protocol.read().unwrap();
+    let guard = state.battery.read().unwrap();
 
     match guard.to_json() {
         Ok(json) => HttpResponse::Ok()
@@ -390,3 +1149,32 @@ async fn json_response_can_battery_modules_info(state: web::Data<AppState>) -> i
         })),
     }
 }
+
+#[post("/api/can/send")]
+async fn json_request_can_send(
+    state: web::Data<AppState>,
+    request: web::Json<FrameRequest>,
+) -> impl Responder {
+    let Some(can_sender) = &state.can_sender else {
+        return HttpResponse::ServiceUnavailable().json(json!({
+            "error": "CAN bus is not configured"
+        }));
+    };
+    let frame = match request.to_frame() {
+        Ok(frame) => frame,
+        Err(err) => {
+            return HttpResponse::BadRequest().json(json!({
+                "error": err
+            }))
+        }
+    };
+
+    match can_sender.send(frame).await {
+        Ok(()) => HttpResponse::Ok().json(json!({
+            "status": "OK"
+        })),
+        Err(err) => HttpResponse::BadRequest().json(json!({
+            "error": err
+        })),
+    }
+}
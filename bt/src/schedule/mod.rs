@@ -0,0 +1,379 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveTime, TimeZone, Utc};
+use serde::Serialize;
+use std::time::Duration;
+
+/// Day window used when no location is configured.
+const DEFAULT_DAY_START: (u32, u32) = (7, 15);
+const DEFAULT_DAY_END: (u32, u32) = (23, 15);
+
+/// Sun altitude at sunrise/sunset, accounting for refraction and the solar disc.
+const SUN_ALTITUDE_DEG: f64 = -0.833;
+
+/// Julian date of the J2000 epoch (2000-01-01 12:00 UTC).
+const J2000: f64 = 2_451_545.0;
+/// Julian date of the Unix epoch.
+const JULIAN_UNIX_EPOCH: f64 = 2_440_587.5;
+
+/// Geographic location used to compute sunrise and sunset.
+#[derive(Debug, Clone, Copy)]
+pub struct Location {
+    /// Degrees, north positive.
+    pub latitude: f64,
+    /// Degrees, east positive.
+    pub longitude: f64,
+}
+
+/// Sunrise and sunset of a single day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SunTimes {
+    Normal {
+        sunrise: DateTime<Utc>,
+        sunset: DateTime<Utc>,
+    },
+    /// The sun does not set (midnight sun).
+    AlwaysUp,
+    /// The sun does not rise (polar night).
+    AlwaysDown,
+}
+
+impl Location {
+    /// Compute sunrise and sunset for the given date using the sunrise equation.
+    pub fn sun_times(&self, date: NaiveDate) -> SunTimes {
+        let epoch = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let n = (date - epoch).num_days() as f64;
+
+        // Mean solar time
+        let j_star = n - self.longitude / 360.0;
+        // Solar mean anomaly
+        let m = (357.5291 + 0.985_600_28 * j_star).rem_euclid(360.0);
+        let m_rad = m.to_radians();
+        // Equation of the center
+        let c = 1.9148 * m_rad.sin() + 0.02 * (2.0 * m_rad).sin() + 0.0003 * (3.0 * m_rad).sin();
+        // Ecliptic longitude
+        let lambda = (m + c + 180.0 + 102.9372).rem_euclid(360.0).to_radians();
+        // Solar transit
+        let j_transit = J2000 + j_star + 0.0053 * m_rad.sin() - 0.0069 * (2.0 * lambda).sin();
+        // Declination of the sun
+        let sin_declination = lambda.sin() * 23.4397_f64.to_radians().sin();
+        let cos_declination = sin_declination.asin().cos();
+
+        let latitude = self.latitude.to_radians();
+        let cos_hour_angle = (SUN_ALTITUDE_DEG.to_radians().sin()
+            - latitude.sin() * sin_declination)
+            / (latitude.cos() * cos_declination);
+
+        if cos_hour_angle < -1.0 {
+            return SunTimes::AlwaysUp;
+        }
+        if cos_hour_angle > 1.0 {
+            return SunTimes::AlwaysDown;
+        }
+
+        let hour_angle = cos_hour_angle.acos().to_degrees();
+        SunTimes::Normal {
+            sunrise: julian_to_utc(j_transit - hour_angle / 360.0),
+            sunset: julian_to_utc(j_transit + hour_angle / 360.0),
+        }
+    }
+}
+
+fn julian_to_utc(julian: f64) -> DateTime<Utc> {
+    let millis = ((julian - JULIAN_UNIX_EPOCH) * 86_400_000.0).round() as i64;
+    Utc.timestamp_millis_opt(millis).unwrap()
+}
+
+/// Explicit polling period for a time range of the day, e.g. `06:00-09:00=60`.
+/// Ranges may wrap around midnight (`22:00-06:00=600`).
+#[derive(Debug, Clone, Copy)]
+pub struct TimetableEntry {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    pub period: Duration,
+}
+
+impl TimetableEntry {
+    fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            time >= self.start && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+/// Parse a comma separated timetable such as `06:00-09:00=60,22:00-06:00=600`.
+pub fn parse_timetable(value: &str) -> Result<Vec<TimetableEntry>, String> {
+    let parse_time = |time: &str| {
+        NaiveTime::parse_from_str(time.trim(), "%H:%M")
+            .map_err(|e| format!("Invalid time {} in timetable: {}", time, e))
+    };
+
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (range, period) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("Missing period in timetable entry {}", entry))?;
+            let (start, end) = range
+                .split_once('-')
+                .ok_or_else(|| format!("Invalid range in timetable entry {}", entry))?;
+            let period = period
+                .trim()
+                .parse::<u64>()
+                .map_err(|e| format!("Invalid period in timetable entry {}: {}", entry, e))?;
+            // A zero period would poll the inverter back to back
+            if period == 0 {
+                return Err(format!("Zero period in timetable entry {}", entry));
+            }
+            let (start, end) = (parse_time(start)?, parse_time(end)?);
+            if start == end {
+                return Err(format!("Empty range in timetable entry {}", entry));
+            }
+
+            Ok(TimetableEntry {
+                start,
+                end,
+                period: Duration::from_secs(period),
+            })
+        })
+        .collect()
+}
+
+/// Current state of the schedule, exposed through `/api/status`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduleStatus {
+    pub daylight: bool,
+    pub period_secs: u64,
+    pub sunrise: Option<DateTime<Utc>>,
+    pub sunset: Option<DateTime<Utc>>,
+}
+
+/// Chooses the polling period depending on the time of day.
+#[derive(Debug, Clone)]
+pub struct PollingSchedule {
+    day_period: Duration,
+    night_period: Duration,
+    location: Option<Location>,
+    sunrise_offset: chrono::Duration,
+    sunset_offset: chrono::Duration,
+    timetable: Vec<TimetableEntry>,
+}
+
+impl PollingSchedule {
+    pub fn new(day_period: Duration, night_period: Duration) -> Self {
+        PollingSchedule {
+            day_period,
+            night_period,
+            location: None,
+            sunrise_offset: chrono::Duration::zero(),
+            sunset_offset: chrono::Duration::zero(),
+            timetable: Vec::new(),
+        }
+    }
+
+    /// Use the sunrise and sunset at the given location, shifted by the offsets in minutes,
+    /// to tell day from night.
+    pub fn with_location(
+        mut self,
+        location: Location,
+        sunrise_offset_minutes: i64,
+        sunset_offset_minutes: i64,
+    ) -> Self {
+        self.location = Some(location);
+        self.sunrise_offset = chrono::Duration::minutes(sunrise_offset_minutes);
+        self.sunset_offset = chrono::Duration::minutes(sunset_offset_minutes);
+        self
+    }
+
+    /// Explicit periods that take precedence over the day/night periods.
+    pub fn with_timetable(mut self, timetable: Vec<TimetableEntry>) -> Self {
+        self.timetable = timetable;
+        self
+    }
+
+    /// Polling period to apply at the given time.
+    pub fn period_at(&self, now: DateTime<Local>) -> Duration {
+        self.status_at(now).1
+    }
+
+    pub fn status(&self) -> ScheduleStatus {
+        let (status, _) = self.status_at(Local::now());
+        status
+    }
+
+    fn status_at(&self, now: DateTime<Local>) -> (ScheduleStatus, Duration) {
+        let (daylight, sunrise, sunset) = self.daylight_at(now);
+        let period = self
+            .timetable
+            .iter()
+            .find(|entry| entry.contains(now.time()))
+            .map(|entry| entry.period)
+            .unwrap_or(if daylight {
+                self.day_period
+            } else {
+                self.night_period
+            });
+
+        (
+            ScheduleStatus {
+                daylight,
+                period_secs: period.as_secs(),
+                sunrise,
+                sunset,
+            },
+            period,
+        )
+    }
+
+    /// Whether it is day at the given time, with the (offset) sunrise and sunset if known.
+    fn daylight_at(
+        &self,
+        now: DateTime<Local>,
+    ) -> (bool, Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
+        match self.location {
+            Some(location) => match location.sun_times(now.date_naive()) {
+                SunTimes::Normal { sunrise, sunset } => {
+                    let sunrise = sunrise + self.sunrise_offset;
+                    let sunset = sunset + self.sunset_offset;
+                    let now = now.with_timezone(&Utc);
+                    (now >= sunrise && now < sunset, Some(sunrise), Some(sunset))
+                }
+                SunTimes::AlwaysUp => (true, None, None),
+                SunTimes::AlwaysDown => (false, None, None),
+            },
+            None => {
+                let low =
+                    NaiveTime::from_hms_opt(DEFAULT_DAY_START.0, DEFAULT_DAY_START.1, 0).unwrap();
+                let high =
+                    NaiveTime::from_hms_opt(DEFAULT_DAY_END.0, DEFAULT_DAY_END.1, 0).unwrap();
+                let time_of_day = now.time();
+                ((time_of_day > low) && (time_of_day < high), None, None)
+            }
+        }
+    }
+
+    /// Short description of today's day window, used in logs.
+    pub fn describe(&self) -> String {
+        let today = Local::now().date_naive();
+        match self.location.map(|location| location.sun_times(today)) {
+            Some(SunTimes::Normal { sunrise, sunset }) => format!(
+                "sunrise {} sunset {}",
+                (sunrise + self.sunrise_offset)
+                    .with_timezone(&Local)
+                    .format("%H:%M"),
+                (sunset + self.sunset_offset)
+                    .with_timezone(&Local)
+                    .format("%H:%M")
+            ),
+            Some(SunTimes::AlwaysUp) => "midnight sun".to_owned(),
+            Some(SunTimes::AlwaysDown) => "polar night".to_owned(),
+            None => "fixed 07:15-23:15 window".to_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    fn assert_near(actual: DateTime<Utc>, expected: DateTime<Utc>) {
+        let difference = (actual - expected).num_seconds().abs();
+        assert!(
+            difference < 5 * 60,
+            "{} is not close to {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn equinox_sun_times_in_madrid() {
+        let madrid = Location {
+            latitude: 40.4168,
+            longitude: -3.7038,
+        };
+        let SunTimes::Normal { sunrise, sunset } =
+            madrid.sun_times(NaiveDate::from_ymd_opt(2026, 3, 20).unwrap())
+        else {
+            panic!("The sun rises and sets in Madrid");
+        };
+        // 07:16 and 19:25 CET
+        assert_near(
+            sunrise,
+            Utc.with_ymd_and_hms(2026, 3, 20, 6, 16, 0).unwrap(),
+        );
+        assert_near(
+            sunset,
+            Utc.with_ymd_and_hms(2026, 3, 20, 18, 25, 0).unwrap(),
+        );
+    }
+
+    #[test]
+    fn polar_sun_times() {
+        let tromso = Location {
+            latitude: 69.65,
+            longitude: 18.96,
+        };
+        let date = |month: u32, day: u32| NaiveDate::from_ymd_opt(2026, month, day).unwrap();
+        assert_eq!(tromso.sun_times(date(12, 21)), SunTimes::AlwaysDown);
+        assert_eq!(tromso.sun_times(date(6, 21)), SunTimes::AlwaysUp);
+
+        let south_pole = Location {
+            latitude: -89.0,
+            longitude: 0.0,
+        };
+        assert_eq!(south_pole.sun_times(date(12, 21)), SunTimes::AlwaysUp);
+        assert_eq!(south_pole.sun_times(date(6, 21)), SunTimes::AlwaysDown);
+    }
+
+    #[test]
+    fn timetable_is_parsed() {
+        let timetable = parse_timetable(" 06:00-09:00=60, 22:00-06:00=600 ,").unwrap();
+        assert_eq!(timetable.len(), 2);
+        assert_eq!(timetable[0].start, time(6, 0));
+        assert_eq!(timetable[0].end, time(9, 0));
+        assert_eq!(timetable[0].period, Duration::from_secs(60));
+        assert_eq!(timetable[1].period, Duration::from_secs(600));
+        assert!(parse_timetable("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn invalid_timetables_are_rejected() {
+        for value in [
+            "06:00-09:00",
+            "06:00=60",
+            "06:00-25:00=60",
+            "6h-9h=60",
+            "06:00-09:00=-1",
+            "06:00-09:00=0",
+            "06:00-06:00=60",
+            "06:00-09:00=60,22:00",
+        ] {
+            assert!(parse_timetable(value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn timetable_ranges_wrap_around_midnight() {
+        let day = parse_timetable("06:00-09:00=60").unwrap()[0];
+        assert!(!day.contains(time(5, 59)));
+        assert!(day.contains(time(6, 0)));
+        assert!(day.contains(time(8, 59)));
+        assert!(!day.contains(time(9, 0)));
+
+        let night = parse_timetable("22:00-06:00=600").unwrap()[0];
+        assert!(night.contains(time(22, 0)));
+        assert!(night.contains(time(23, 59)));
+        assert!(night.contains(time(0, 0)));
+        assert!(night.contains(time(5, 59)));
+        assert!(!night.contains(time(6, 0)));
+        assert!(!night.contains(time(12, 0)));
+        assert!(!night.contains(time(21, 59)));
+    }
+}
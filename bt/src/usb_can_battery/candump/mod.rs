@@ -0,0 +1,311 @@
+use super::{Frame, FrameSource, FrameStats, FrameType};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Format a frame as a candump log line: `(timestamp) iface ID#DATA`, `ID#R` for remote
+/// frames. Extended ids are written with 8 digits, standard ones with 3.
+pub fn format_line(timestamp: Duration, interface: &str, frame: &Frame) -> String {
+    let id = match frame.header.frame_type {
+        FrameType::Standard => format!("{:03X}", frame.id),
+        FrameType::Extended => format!("{:08X}", frame.id),
+    };
+    let data = match (frame.is_remote(), frame.header.frame_data_length) {
+        (true, 0) => "R".to_owned(),
+        (true, length) => format!("R{}", length),
+        (false, _) => hex::encode_upper(&frame.data),
+    };
+    format!(
+        "({}.{:06}) {} {}#{}",
+        timestamp.as_secs(),
+        timestamp.subsec_micros(),
+        interface,
+        id,
+        data
+    )
+}
+
+/// Parse a candump log line into its timestamp and frame, `None` if it is not a valid
+/// classic CAN frame.
+pub fn parse_line(line: &str) -> Option<(Duration, Frame)> {
+    let mut fields = line.split_whitespace();
+    let timestamp = fields.next()?.strip_prefix('(')?.strip_suffix(')')?;
+    let _interface = fields.next()?;
+    let (id, data) = fields.next()?.split_once('#')?;
+    if fields.next().is_some() {
+        return None;
+    }
+
+    let (secs, fraction) = timestamp.split_once('.').unwrap_or((timestamp, "0"));
+    if fraction.is_empty() || fraction.len() > 9 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let nanos = fraction.parse::<u32>().ok()? * 10u32.pow(9 - fraction.len() as u32);
+    let timestamp = Duration::new(secs.parse().ok()?, nanos);
+
+    let frame_type = match id.len() {
+        1..=3 => FrameType::Standard,
+        8 => FrameType::Extended,
+        _ => return None,
+    };
+    let id = u32::from_str_radix(id, 16).ok()?;
+
+    let frame = match data.strip_prefix('R') {
+        Some(length) => {
+            let length = match length {
+                "" => 0,
+                _ => length.parse().ok()?,
+            };
+            Frame::new(id, frame_type, None, length)
+        }
+        // canplayer accepts dots between the data bytes
+        None => Frame::new(
+            id,
+            frame_type,
+            Some(hex::decode(data.replace('.', "")).ok()?),
+            0,
+        ),
+    };
+    Some((timestamp, frame.ok()?))
+}
+
+/// Writer of received frames into a candump log, rotated once it reaches `max_size` bytes:
+/// `can.log` is renamed `can.log.1`, the older files shifted up to `can.log.<files>`.
+pub struct CandumpRecorder {
+    path: String,
+    interface: String,
+    max_size: u64,
+    files: usize,
+    file: File,
+    size: u64,
+}
+
+impl CandumpRecorder {
+    pub fn new(path: &str, interface: &str, max_size: u64, files: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+
+        Ok(CandumpRecorder {
+            path: path.to_owned(),
+            interface: interface.to_owned(),
+            max_size,
+            files,
+            file,
+            size,
+        })
+    }
+
+    /// Append a frame received now.
+    pub fn record(&mut self, frame: &Frame) -> io::Result<()> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let line = format_line(timestamp, &self.interface, frame) + "\n";
+
+        if self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.files > 0 {
+            for index in (1..self.files).rev() {
+                let from = format!("{}.{}", self.path, index);
+                if fs::metadata(&from).is_ok() {
+                    fs::rename(&from, format!("{}.{}", self.path, index + 1))?;
+                }
+            }
+            fs::rename(&self.path, format!("{}.1", self.path))?;
+        }
+
+        self.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+/// Frames of a candump log played back with their original timing, divided by `speed`
+/// (`0` plays them as fast as they are read). The source ends with `UnexpectedEof`.
+pub struct CandumpReplaySource {
+    path: String,
+    lines: io::Lines<BufReader<File>>,
+    speed: f64,
+    timeout: Duration,
+    /// Timestamp of the first frame and when it was replayed.
+    start: Option<(Duration, Instant)>,
+    pending: Option<(Duration, Frame)>,
+    stats: FrameStats,
+}
+
+impl CandumpReplaySource {
+    pub fn open(path: &str, speed: f64, timeout: Duration) -> io::Result<Self> {
+        let file = File::open(path)?;
+
+        Ok(CandumpReplaySource {
+            path: path.to_owned(),
+            lines: BufReader::new(file).lines(),
+            speed,
+            timeout,
+            start: None,
+            pending: None,
+            stats: FrameStats::default(),
+        })
+    }
+
+    fn next_frame(&mut self) -> io::Result<(Duration, Frame)> {
+        loop {
+            let Some(line) = self.lines.next() else {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("End of candump log {}", self.path),
+                ));
+            };
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match parse_line(&line) {
+                Some(frame) => return Ok(frame),
+                None => self.stats.corrupt_packets += 1,
+            }
+        }
+    }
+}
+
+impl FrameSource for CandumpReplaySource {
+    fn read_frame(&mut self) -> io::Result<Frame> {
+        let (timestamp, frame) = match self.pending.take() {
+            Some(pending) => pending,
+            None => self.next_frame()?,
+        };
+        let (first_timestamp, started) = *self.start.get_or_insert((timestamp, Instant::now()));
+
+        // Wait for the frame time, at most `timeout` per read like the bus sources
+        if self.speed > 0.0 {
+            // Very slow speeds wait longer than a `Duration` holds
+            let offset = Duration::try_from_secs_f64(
+                timestamp.saturating_sub(first_timestamp).as_secs_f64() / self.speed,
+            )
+            .unwrap_or(Duration::MAX);
+            let wait = offset.saturating_sub(started.elapsed());
+            if wait > self.timeout {
+                thread::sleep(self.timeout);
+                self.pending = Some((timestamp, frame));
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            thread::sleep(wait);
+        }
+
+        self.stats.frames += 1;
+        Ok(frame)
+    }
+
+    fn send_frame(&mut self, _frame: &Frame) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Frames can't be sent while replaying a candump log",
+        ))
+    }
+
+    fn stats(&self) -> FrameStats {
+        self.stats
+    }
+
+    fn name(&self) -> String {
+        format!("candump log {}", self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_are_parsed_back() {
+        let timestamp = Duration::new(1_700_000_000, 123_456_000);
+        let frames = [
+            Frame::new(0x313, FrameType::Standard, Some(vec![0x13, 0xD2, 0x00]), 0).unwrap(),
+            Frame::new(0x18FF50E5, FrameType::Extended, Some(vec![]), 0).unwrap(),
+            Frame::new(0x7FF, FrameType::Standard, None, 0).unwrap(),
+            Frame::new(0x00000001, FrameType::Extended, None, 8).unwrap(),
+        ];
+        let lines = [
+            "(1700000000.123456) can0 313#13D200",
+            "(1700000000.123456) can0 18FF50E5#",
+            "(1700000000.123456) can0 7FF#R",
+            "(1700000000.123456) can0 00000001#R8",
+        ];
+
+        for (frame, expected) in frames.iter().zip(lines) {
+            let line = format_line(timestamp, "can0", frame);
+            assert_eq!(line, expected);
+
+            let (parsed_timestamp, parsed) = parse_line(&line).unwrap();
+            assert_eq!(parsed_timestamp, timestamp);
+            assert_eq!(parsed.id, frame.id);
+            assert_eq!(parsed.header.frame_type, frame.header.frame_type);
+            assert_eq!(parsed.is_remote(), frame.is_remote());
+            assert_eq!(
+                parsed.header.frame_data_length,
+                frame.header.frame_data_length
+            );
+            assert_eq!(parsed.data, frame.data);
+        }
+    }
+
+    #[test]
+    fn slow_replays_wait_for_the_frames() {
+        let path = std::env::temp_dir().join("bt_candump_slow.log");
+        let path = path.to_str().unwrap();
+        fs::write(path, "(1.0) can0 355#5500\n(2.0) can0 356#7814\n").unwrap();
+
+        // Far beyond what a `Duration` holds
+        let mut source = CandumpReplaySource::open(path, 1e-12, Duration::from_millis(1)).unwrap();
+        assert_eq!(source.read_frame().unwrap().id, 0x355);
+        let error = source.read_frame().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+
+        let mut source = CandumpReplaySource::open(path, 0.0, Duration::from_millis(1)).unwrap();
+        assert_eq!(source.read_frame().unwrap().id, 0x355);
+        assert_eq!(source.read_frame().unwrap().id, 0x356);
+        let error = source.read_frame().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn canplayer_lines_are_parsed() {
+        let (timestamp, frame) = parse_line("(12.5) vcan0 123#01.02.03").unwrap();
+        assert_eq!(timestamp, Duration::from_millis(12_500));
+        assert_eq!(frame.id, 0x123);
+        assert_eq!(frame.data, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn invalid_lines_are_rejected() {
+        let lines = [
+            "",
+            "can0 313#13D2",
+            "(1.0) can0 313",
+            "(1.0) can0 313#13D",
+            "(1.0) can0 313#0102030405060708090A",
+            "(1.0) can0 800#01",
+            "(1.0) can0 1234#01",
+            "(1.0) can0 313#R9",
+            "(1.0) can0 313#ZZ",
+            "(1.x) can0 313#01",
+            "(1.0) can0 313#01 extra",
+        ];
+        for line in lines {
+            assert!(parse_line(line).is_none(), "{}", line);
+        }
+    }
+}
@@ -1,4 +1,5 @@
-use super::Frame;
+use super::protocol::{BatteryProtocol, BatterySummary};
+use super::{DynessBatteryStatus, Frame};
 use byteorder::{BigEndian, ByteOrder};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
@@ -493,15 +494,20 @@ pub struct DynessBatteryModule {
     acc_charge_kwh: Option<ACCChargeKiloWattHours>,
 }
 
+/// Pack summary frame, the other frames describe single modules.
+const SUMMARY_FRAME_ID: u32 = 0x313;
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct DynessCanProtocol {
     modules: HashMap<ModuleId, DynessBatteryModule>,
     last_update: Option<DateTime<Utc>>,
+    /// Pack summary of the 0x313 frame.
+    summary: Option<DynessBatteryStatus>,
 }
 
 impl DynessCanProtocol {
-    /// Decode a CAN frame and update the internal state of the protocol.
-    pub fn decode(&mut self, frame: &Frame) {
+    /// Decode a module CAN frame and update the internal state of the protocol.
+    fn decode_module_frame(&mut self, frame: &Frame) {
         let Ok(frame_data) = try_to_decode_frame(frame) else {
             // Skip unknown frame types
             return;
@@ -586,9 +592,33 @@ impl DynessCanProtocol {
     pub fn modules(&self) -> impl Iterator<Item = (&ModuleId, &DynessBatteryModule)> {
         self.modules.iter()
     }
+}
+
+impl BatteryProtocol for DynessCanProtocol {
+    fn name(&self) -> &'static str {
+        "dyness"
+    }
+
+    fn decode(&mut self, frame: &Frame) -> bool {
+        if frame.id != SUMMARY_FRAME_ID {
+            self.decode_module_frame(frame);
+            return false;
+        }
+
+        match DynessBatteryStatus::from(frame.clone()) {
+            Ok(status) => {
+                self.summary = Some(status);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn summary(&self) -> Option<BatterySummary> {
+        self.summary.as_ref().map(DynessBatteryStatus::summary)
+    }
 
-    /// Serialize the current state of the protocol to JSON.
-    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+    fn to_json(&self) -> Result<String, serde_json::Error> {
         // Include the average SOC and module count in the JSON output
         let mut json_map = serde_json::Map::new();
         json_map.insert(
@@ -1,11 +1,23 @@
+pub mod candump;
 pub mod dyness;
+pub mod protocol;
+pub mod pylontech;
+pub mod slcan;
+pub mod socketcan;
 
-use crate::inverter::bt::InfluxData;
 use byteorder::{BigEndian, ByteOrder, LittleEndian};
-use futures::stream;
-use influxdb2::models::DataPoint;
+use protocol::BatterySummary;
 use serde::{Deserialize, Serialize};
+use serialport::SerialPort;
+use std::io;
 use std::slice::Iter;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+/// Maximum number of frames waiting to be sent.
+const SEND_QUEUE_SIZE: usize = 8;
+/// Time to wait for the CAN bus service to send a queued frame.
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[derive(Debug)]
 pub struct Frame {
@@ -36,40 +48,276 @@ pub struct FrameHeader {
 const PACKET_HEADER: u8 = 0xAA;
 const PACKET_END: u8 = 0x55;
 
+/// Second byte of the packets of the fixed 20 byte mode.
+const FIXED_PACKET_MARKER: u8 = 0x55;
+const FIXED_PACKET_LENGTH: usize = 20;
+/// Type byte of the CAN data packets in the fixed mode, other types are adapter settings.
+const FIXED_PACKET_DATA: u8 = 0x01;
+
+/// Type byte of the variable length mode: `0b11` marker, extended and remote flags and DLC.
+const VARIABLE_TYPE_MARKER: u8 = 0b_1100_0000;
+const VARIABLE_TYPE_EXTENDED: u8 = 1 << 5;
+const VARIABLE_TYPE_REMOTE: u8 = 1 << 4;
+
+const MAX_DATA_LENGTH: usize = 8;
+
+/// Type byte of the settings command selecting the variable length mode for received packets.
+const SETTINGS_VARIABLE_PACKETS: u8 = 0x12;
+
+/// Speed codes of the settings command by CAN bitrate.
+const ADAPTER_BITRATES: [(u32, u8); 12] = [
+    (1_000_000, 0x01),
+    (800_000, 0x02),
+    (500_000, 0x03),
+    (400_000, 0x04),
+    (250_000, 0x05),
+    (200_000, 0x06),
+    (125_000, 0x07),
+    (100_000, 0x08),
+    (50_000, 0x09),
+    (20_000, 0x0A),
+    (10_000, 0x0B),
+    (5_000, 0x0C),
+];
+
+/// Operating mode of the USB-CAN-A adapter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AdapterMode {
+    Normal,
+    Loopback,
+    /// Listen only, frames are never acknowledged.
+    Silent,
+    LoopbackSilent,
+}
+
+impl std::str::FromStr for AdapterMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "normal" => Ok(AdapterMode::Normal),
+            "loopback" => Ok(AdapterMode::Loopback),
+            "silent" => Ok(AdapterMode::Silent),
+            "loopback-silent" => Ok(AdapterMode::LoopbackSilent),
+            _ => Err(format!("Invalid CAN adapter mode {}", s)),
+        }
+    }
+}
+
+/// Settings sent to the USB-CAN-A adapter when it is opened, replacing the vendor tool.
+#[derive(Debug, Clone, Copy)]
+pub struct AdapterSettings {
+    pub bitrate: u32,
+    pub mode: AdapterMode,
+    pub frame_type: FrameType,
+    /// Acceptance filter and mask, every frame is received with a zero mask.
+    pub filter_id: u32,
+    pub filter_mask: u32,
+}
+
+impl Default for AdapterSettings {
+    fn default() -> Self {
+        AdapterSettings {
+            bitrate: 500_000,
+            mode: AdapterMode::Normal,
+            frame_type: FrameType::Standard,
+            filter_id: 0,
+            filter_mask: 0,
+        }
+    }
+}
+
+impl AdapterSettings {
+    /// Settings command: `AA 55 12`, speed, frame type, 4 bytes filter, 4 bytes mask, mode,
+    /// `01`, 4 reserved bytes and the checksum, the adapter answers nothing.
+    pub fn command(&self) -> Result<[u8; FIXED_PACKET_LENGTH], String> {
+        let speed = ADAPTER_BITRATES
+            .iter()
+            .find(|(bitrate, _)| *bitrate == self.bitrate)
+            .map(|(_, speed)| *speed)
+            .ok_or_else(|| format!("Unsupported CAN bitrate {}", self.bitrate))?;
+
+        let mut command = [0; FIXED_PACKET_LENGTH];
+        command[0] = PACKET_HEADER;
+        command[1] = FIXED_PACKET_MARKER;
+        command[2] = SETTINGS_VARIABLE_PACKETS;
+        command[3] = speed;
+        command[4] = match self.frame_type {
+            FrameType::Standard => 0x01,
+            FrameType::Extended => 0x02,
+        };
+        LittleEndian::write_u32(&mut command[5..9], self.filter_id);
+        LittleEndian::write_u32(&mut command[9..13], self.filter_mask);
+        command[13] = match self.mode {
+            AdapterMode::Normal => 0x00,
+            AdapterMode::Loopback => 0x01,
+            AdapterMode::Silent => 0x02,
+            AdapterMode::LoopbackSilent => 0x03,
+        };
+        command[14] = 0x01;
+        command[FIXED_PACKET_LENGTH - 1] = checksum(&command[2..FIXED_PACKET_LENGTH - 1]);
+        Ok(command)
+    }
+}
+
+/// Checksum of the fixed length packets, the low byte of the sum of their content.
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+/// Frame and error counters of a CAN frame source.
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct FrameStats {
+    pub frames: u64,
+    /// Bytes skipped while looking for the start of a packet.
+    pub dropped_bytes: u64,
+    /// Packets with an invalid type, DLC, end marker or checksum.
+    pub corrupt_packets: u64,
+}
+
+impl FrameStats {
+    pub fn add(&mut self, other: &FrameStats) {
+        self.frames += other.frames;
+        self.dropped_bytes += other.dropped_bytes;
+        self.corrupt_packets += other.corrupt_packets;
+    }
+}
+
+/// Outcome of decoding the start of the packet buffer.
+enum Packet {
+    Incomplete,
+    Frame(Frame, usize),
+    Corrupt,
+}
+
+/// Decoder of the Waveshare USB-CAN-A serial protocol, in both the variable length and the
+/// fixed 20 byte modes. Garbage and corrupt packets are skipped until the next valid packet.
 pub struct Decoder {
     packet_buffer: Vec<u8>,
+    stats: FrameStats,
 }
 
 impl Decoder {
     pub fn new() -> Self {
         Decoder {
             packet_buffer: Vec::new(),
+            stats: FrameStats::default(),
         }
     }
 
+    pub fn stats(&self) -> FrameStats {
+        self.stats
+    }
+
     pub fn append(&mut self, byte: u8) -> Option<Frame> {
-        if self.packet_buffer.first() != Some(&PACKET_HEADER) && byte == PACKET_HEADER {
-            // Start packet
-            self.packet_buffer.push(byte);
-            return None;
+        self.packet_buffer.push(byte);
+
+        loop {
+            // Resynchronize on the next packet header
+            let start = self
+                .packet_buffer
+                .iter()
+                .position(|b| *b == PACKET_HEADER)
+                .unwrap_or(self.packet_buffer.len());
+            if start > 0 {
+                self.stats.dropped_bytes += start as u64;
+                self.packet_buffer.drain(..start);
+            }
+
+            match Decoder::decode_packet(&self.packet_buffer) {
+                Packet::Incomplete => return None,
+                Packet::Frame(frame, length) => {
+                    self.stats.frames += 1;
+                    self.packet_buffer.drain(..length);
+                    return Some(frame);
+                }
+                Packet::Corrupt => {
+                    // The header may have been payload, look for another one after it
+                    self.stats.corrupt_packets += 1;
+                    self.packet_buffer.drain(..1);
+                }
+            }
         }
+    }
 
-        if self.packet_buffer.first() == Some(&PACKET_HEADER) && byte != PACKET_END {
-            // Packet payload
-            self.packet_buffer.push(byte);
-            return None;
+    /// Decode the packet at the start of `packet`, which begins with the packet header.
+    fn decode_packet(packet: &[u8]) -> Packet {
+        match packet.get(1) {
+            None => Packet::Incomplete,
+            Some(&FIXED_PACKET_MARKER) => Decoder::decode_fixed_packet(packet),
+            Some(type_byte) if type_byte & VARIABLE_TYPE_MARKER == VARIABLE_TYPE_MARKER => {
+                Decoder::decode_variable_packet(packet)
+            }
+            Some(_) => Packet::Corrupt,
         }
+    }
 
-        if self.packet_buffer.first() == Some(&PACKET_HEADER) && byte == PACKET_END {
-            // End packet
-            self.packet_buffer.push(byte);
-            // println!("{:02X?}", &self.packet_buffer);
-            let frame = self.decode_frame();
-            self.packet_buffer.clear();
-            return frame;
+    /// `AA`, type (`0b11`, extended, remote, DLC), 2 or 4 bytes LE id, data, `55`. Remote
+    /// frames carry no data, their DLC is the requested length.
+    fn decode_variable_packet(packet: &[u8]) -> Packet {
+        let header = FrameHeader::from_bytes(&packet[1]);
+        if header.frame_data_length > MAX_DATA_LENGTH {
+            return Packet::Corrupt;
+        }
+        let id_length = match header.frame_type {
+            FrameType::Standard => 2,
+            FrameType::Extended => 4,
+        };
+        let data_length = match header.frame_format {
+            FrameFormat::DataFrame => header.frame_data_length,
+            FrameFormat::RemoteFrame => 0,
+        };
+        let length = 2 + id_length + data_length + 1;
+        if packet.len() < length {
+            return Packet::Incomplete;
+        }
+        if packet[length - 1] != PACKET_END {
+            return Packet::Corrupt;
         }
 
-        None
+        let mut raw = packet[2..].iter();
+        let Ok(id) = Decoder::decode_id(&header.frame_type, &mut raw) else {
+            return Packet::Corrupt;
+        };
+        let data = raw.take(data_length).copied().collect();
+        Packet::Frame(Frame { header, id, data }, length)
+    }
+
+    /// `AA 55`, type, frame type, frame format, 4 bytes LE id, DLC, 8 data bytes, reserved and
+    /// the checksum of the bytes from the type to the reserved one.
+    fn decode_fixed_packet(packet: &[u8]) -> Packet {
+        if packet.len() < FIXED_PACKET_LENGTH {
+            return Packet::Incomplete;
+        }
+        if checksum(&packet[2..FIXED_PACKET_LENGTH - 1]) != packet[FIXED_PACKET_LENGTH - 1] {
+            return Packet::Corrupt;
+        }
+
+        let frame_type = match packet[3] {
+            0x01 => FrameType::Standard,
+            0x02 => FrameType::Extended,
+            _ => return Packet::Corrupt,
+        };
+        let frame_format = match packet[4] {
+            0x01 => FrameFormat::DataFrame,
+            0x02 => FrameFormat::RemoteFrame,
+            _ => return Packet::Corrupt,
+        };
+        let frame_data_length = packet[9] as usize;
+        if packet[2] != FIXED_PACKET_DATA || frame_data_length > MAX_DATA_LENGTH {
+            return Packet::Corrupt;
+        }
+
+        let frame = Frame {
+            header: FrameHeader {
+                frame_type,
+                frame_format,
+                frame_data_length,
+            },
+            id: LittleEndian::read_u32(&packet[5..9]),
+            data: packet[10..10 + frame_data_length].to_vec(),
+        };
+        Packet::Frame(frame, FIXED_PACKET_LENGTH)
     }
 
     fn decode_id(frame_type: &FrameType, iter: &mut Iter<'_, u8>) -> Result<u32, String> {
@@ -95,46 +343,209 @@ impl Decoder {
             }
         }
     }
+}
 
-    fn decode_frame(&mut self) -> Option<Frame> {
-        let mut raw = self.packet_buffer.iter();
+/// Encode a frame as a USB-CAN-A variable length packet, the reverse of [`Decoder`].
+pub fn encode_packet(frame: &Frame) -> Vec<u8> {
+    let mut type_byte = VARIABLE_TYPE_MARKER | frame.header.frame_data_length as u8;
+    if frame.header.frame_type == FrameType::Extended {
+        type_byte |= VARIABLE_TYPE_EXTENDED;
+    }
+    if frame.is_remote() {
+        type_byte |= VARIABLE_TYPE_REMOTE;
+    }
 
-        while raw.next() != Some(&PACKET_HEADER) {}
+    let mut packet = vec![PACKET_HEADER, type_byte];
+    match frame.header.frame_type {
+        FrameType::Standard => packet.extend_from_slice(&(frame.id as u16).to_le_bytes()),
+        FrameType::Extended => packet.extend_from_slice(&frame.id.to_le_bytes()),
+    }
+    packet.extend_from_slice(&frame.data);
+    packet.push(PACKET_END);
+    packet
+}
 
-        let mut frame = Frame::new();
-        frame.header = FrameHeader::from_bytes(&raw.next().unwrap());
+/// Body of `/api/can/send`.
+#[derive(Debug, Deserialize)]
+pub struct FrameRequest {
+    pub id: u32,
+    #[serde(default)]
+    pub extended: bool,
+    /// Hex encoded payload, a remote frame is sent without it.
+    pub data: Option<String>,
+    /// Requested length of a remote frame.
+    #[serde(default)]
+    pub length: usize,
+}
 
-        match Decoder::decode_id(&frame.header.frame_type, &mut raw) {
-            Ok(frame_id) => {
-                frame.id = frame_id;
-            }
-            Err(_) => {
-                return None;
-            }
-        }
+impl FrameRequest {
+    pub fn to_frame(&self) -> Result<Frame, String> {
+        let data = match &self.data {
+            Some(data) => Some(
+                hex::decode(data.replace(' ', "")).map_err(|e| format!("Invalid data: {}", e))?,
+            ),
+            None => None,
+        };
+        let frame_type = match self.extended {
+            true => FrameType::Extended,
+            false => FrameType::Standard,
+        };
+        Frame::new(self.id, frame_type, data, self.length)
+    }
+}
 
-        let mut i = 0;
-        while let Some(b) = raw.next() {
-            i = i + 1;
+/// Frame waiting to be sent by the CAN bus service.
+pub struct SendRequest {
+    pub frame: Frame,
+    pub reply: oneshot::Sender<Result<(), String>>,
+}
 
-            if *b == PACKET_END && i > frame.header.frame_data_length {
-                return Some(frame);
-            }
+/// Cloneable handle used to send frames through the running CAN bus service.
+#[derive(Clone)]
+pub struct FrameSender {
+    sender: mpsc::Sender<SendRequest>,
+}
+
+impl FrameSender {
+    /// Create the handle and the receiver polled by the CAN bus service.
+    pub fn channel() -> (Self, mpsc::Receiver<SendRequest>) {
+        let (sender, receiver) = mpsc::channel(SEND_QUEUE_SIZE);
+        (FrameSender { sender }, receiver)
+    }
+
+    /// Queue a frame and wait until it has been handed to the adapter.
+    pub async fn send(&self, frame: Frame) -> Result<(), String> {
+        let (reply, response) = oneshot::channel();
+        self.sender
+            .try_send(SendRequest { frame, reply })
+            .map_err(|e| match e {
+                mpsc::error::TrySendError::Full(_) => "CAN send queue is full".to_owned(),
+                mpsc::error::TrySendError::Closed(_) => "CAN bus service is not running".to_owned(),
+            })?;
+
+        tokio::time::timeout(SEND_TIMEOUT, response)
+            .await
+            .map_err(|_| "Timed out waiting for the CAN bus service".to_owned())?
+            .map_err(|_| "CAN bus service dropped the frame".to_owned())?
+    }
+}
+
+/// Source of CAN frames, a USB-CAN-A adapter on a serial port or a SocketCAN interface.
+pub trait FrameSource: Send {
+    /// Wait for the next frame, failing with `TimedOut` when none arrives in time.
+    fn read_frame(&mut self) -> io::Result<Frame>;
+
+    /// Send a frame on the bus.
+    fn send_frame(&mut self, frame: &Frame) -> io::Result<()>;
+
+    /// Frames received and packets dropped since the source was opened.
+    fn stats(&self) -> FrameStats;
+
+    /// Human readable description used in logs.
+    fn name(&self) -> String;
+}
 
-            frame.data.push(*b);
+/// Waveshare USB-CAN-A adapter on a serial port.
+pub struct SerialFrameSource {
+    port_name: String,
+    port: Box<dyn SerialPort>,
+    decoder: Decoder,
+}
+
+impl SerialFrameSource {
+    /// Open the adapter, configuring it first when `settings` are given.
+    pub fn open(
+        port_name: &str,
+        baud_rate: u32,
+        settings: Option<&AdapterSettings>,
+        timeout: std::time::Duration,
+    ) -> io::Result<Self> {
+        let mut port = serialport::new(port_name, baud_rate)
+            .timeout(timeout)
+            .open()?;
+        if let Some(settings) = settings {
+            let command = settings
+                .command()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            port.write_all(&command)?;
+            port.flush()?;
         }
+        Ok(SerialFrameSource {
+            port_name: port_name.to_owned(),
+            port,
+            decoder: Decoder::new(),
+        })
+    }
+}
 
-        None
+impl FrameSource for SerialFrameSource {
+    fn read_frame(&mut self) -> io::Result<Frame> {
+        let mut byte = [0; 1];
+        loop {
+            self.port.read_exact(&mut byte)?;
+            if let Some(frame) = self.decoder.append(byte[0]) {
+                return Ok(frame);
+            }
+        }
+    }
+
+    fn send_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        self.port.write_all(&encode_packet(frame))?;
+        self.port.flush()
+    }
+
+    fn stats(&self) -> FrameStats {
+        self.decoder.stats()
+    }
+
+    fn name(&self) -> String {
+        format!(
+            "{} at {} baud",
+            self.port_name,
+            self.port.baud_rate().unwrap_or_default()
+        )
     }
 }
 
 impl Frame {
-    fn new() -> Self {
-        Frame {
-            header: FrameHeader::default(),
-            id: 0,
-            data: Vec::new(),
+    /// Build a data frame, or a remote frame requesting `length` bytes when `data` is `None`.
+    pub fn new(
+        id: u32,
+        frame_type: FrameType,
+        data: Option<Vec<u8>>,
+        length: usize,
+    ) -> Result<Self, String> {
+        let max_id = match frame_type {
+            FrameType::Standard => 0x7FF,
+            FrameType::Extended => 0x1FFF_FFFF,
+        };
+        if id > max_id {
+            return Err(format!("Invalid {:?} frame id {:#X}", frame_type, id));
+        }
+        let frame_data_length = data.as_ref().map_or(length, Vec::len);
+        if frame_data_length > MAX_DATA_LENGTH {
+            return Err(format!(
+                "Frame data of {} bytes, at most {} are allowed",
+                frame_data_length, MAX_DATA_LENGTH
+            ));
         }
+
+        Ok(Frame {
+            header: FrameHeader {
+                frame_type,
+                frame_format: match data {
+                    Some(_) => FrameFormat::DataFrame,
+                    None => FrameFormat::RemoteFrame,
+                },
+                frame_data_length,
+            },
+            id,
+            data: data.unwrap_or_default(),
+        })
+    }
+
+    pub fn is_remote(&self) -> bool {
+        matches!(self.header.frame_format, FrameFormat::RemoteFrame)
     }
 }
 
@@ -163,19 +574,19 @@ impl ToString for Frame {
 impl FrameHeader {
     fn from_bytes(byte: &u8) -> Self {
         let mut frame_type = FrameType::Standard;
-        if (byte & (1 << 5)) != 0 {
+        if (byte & VARIABLE_TYPE_EXTENDED) != 0 {
             frame_type = FrameType::Extended;
         }
 
         let mut frame_format = FrameFormat::DataFrame;
-        if (byte & (1 << 4)) != 0 {
+        if (byte & VARIABLE_TYPE_REMOTE) != 0 {
             frame_format = FrameFormat::RemoteFrame;
         }
 
         FrameHeader {
             frame_type,
             frame_format,
-            frame_data_length: ((byte & 0b_11100000) >> 5).into(),
+            frame_data_length: (byte & 0b_00001111).into(),
         }
     }
 }
@@ -219,11 +630,6 @@ pub struct DynessBatteryStatus {
 }
 
 impl DynessBatteryStatus {
-    /// Serialize summary battery status into JSON string
-    pub fn to_json(&self) -> Result<String, serde_json::Error> {
-        serde_json::to_string(self)
-    }
-
     /// Tries to create a Dyness battery status from a standard frame
     pub fn from(value: Frame) -> Result<Self, String> {
         // Example package data [13, B1, 00, 61, 00, A0, 50, 64]
@@ -248,31 +654,20 @@ impl DynessBatteryStatus {
         })
     }
 
-    /// Save the battery status to InfluxDB.
-    pub async fn save_to_db(&self, influx_data: InfluxData) {
-        let points = self.get_data_points();
-        let client = influx_data.create_client();
-        let result = client
-            .write(influx_data.get_bucket(), stream::iter(points))
-            .await;
-
-        if let Err(e) = result {
-            println!("USB CAN Bus Influxdb client error: {}", e.to_string());
+    /// Pack summary common to every battery protocol.
+    pub fn summary(&self) -> BatterySummary {
+        BatterySummary {
+            protocol: "dyness",
+            soc: self.soc as f32,
+            soh: Some(self.soh as f32),
+            voltage: self.voltage,
+            amps: self.amps,
+            temp: self.temp,
+            limits: None,
+            alarm_flags: None,
+            alarms: Vec::new(),
         }
     }
-
-    fn get_data_points(&self) -> Vec<DataPoint> {
-        let point_battery = DataPoint::builder("battery_can_bus")
-            .tag("host", "battery")
-            .field("soc", self.soc as f64)
-            .field("voltage", self.voltage as f64)
-            .field("temp", self.temp as f64)
-            .field("amps", self.amps as f64)
-            .build()
-            .unwrap();
-
-        vec![point_battery]
-    }
 }
 
 impl ToString for DynessBatteryStatus {
@@ -283,3 +678,142 @@ impl ToString for DynessBatteryStatus {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(decoder: &mut Decoder, input: &[u8]) -> Vec<Frame> {
+        input
+            .iter()
+            .filter_map(|byte| decoder.append(*byte))
+            .collect()
+    }
+
+    fn fixed_packet(id: u32, data: &[u8]) -> Vec<u8> {
+        let mut packet = vec![
+            PACKET_HEADER,
+            FIXED_PACKET_MARKER,
+            FIXED_PACKET_DATA,
+            0x01,
+            0x01,
+        ];
+        packet.extend_from_slice(&id.to_le_bytes());
+        packet.push(data.len() as u8);
+        packet.extend_from_slice(data);
+        packet.resize(FIXED_PACKET_LENGTH - 1, 0);
+        packet.push(checksum(&packet[2..]));
+        packet
+    }
+
+    #[test]
+    fn settings_command_layout() {
+        let settings = AdapterSettings {
+            bitrate: 250_000,
+            mode: AdapterMode::Silent,
+            frame_type: FrameType::Extended,
+            filter_id: 0x18FF50E5,
+            filter_mask: 0x1FFFFFFF,
+        };
+        assert_eq!(
+            settings.command().unwrap(),
+            [
+                0xAA, 0x55, 0x12, 0x05, 0x02, 0xE5, 0x50, 0xFF, 0x18, 0xFF, 0xFF, 0xFF, 0x1F, 0x02,
+                0x01, 0x00, 0x00, 0x00, 0x00, 0x84
+            ]
+        );
+
+        let command = AdapterSettings::default().command().unwrap();
+        assert_eq!(command[3], 0x03);
+        assert_eq!(command[13], 0x00);
+        assert_eq!(command[19], 0x17);
+
+        let settings = AdapterSettings {
+            bitrate: 33_333,
+            ..Default::default()
+        };
+        assert_eq!(
+            settings.command().unwrap_err(),
+            "Unsupported CAN bitrate 33333"
+        );
+    }
+
+    #[test]
+    fn variable_packets_are_decoded() {
+        let mut decoder = Decoder::new();
+        // Payload bytes equal to the packet header and end
+        let frame =
+            Frame::new(0x359, FrameType::Standard, Some(vec![0xAA, 0x55, 0x00]), 0).unwrap();
+        let extended = Frame::new(0x18FF50E5, FrameType::Extended, Some(vec![0x55]), 0).unwrap();
+        let input = [encode_packet(&frame), encode_packet(&extended)].concat();
+        let frames = decode(&mut decoder, &input);
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].id, 0x359);
+        assert_eq!(frames[0].data, vec![0xAA, 0x55, 0x00]);
+        assert_eq!(frames[1].id, 0x18FF50E5);
+        assert_eq!(frames[1].header.frame_type, FrameType::Extended);
+        assert_eq!(frames[1].data, vec![0x55]);
+    }
+
+    #[test]
+    fn remote_frames_are_decoded_back() {
+        let mut decoder = Decoder::new();
+        let frames = [
+            Frame::new(0x7FF, FrameType::Standard, None, 8).unwrap(),
+            Frame::new(0x18FF50E5, FrameType::Extended, None, 2).unwrap(),
+            Frame::new(0x123, FrameType::Standard, Some(vec![0x55; 8]), 0).unwrap(),
+        ];
+        for frame in frames {
+            let packet = encode_packet(&frame);
+            let decoded = decode(&mut decoder, &packet);
+            assert_eq!(decoded.len(), 1);
+            assert_eq!(decoded[0].id, frame.id);
+            assert_eq!(decoded[0].data, frame.data);
+            assert_eq!(decoded[0].is_remote(), frame.is_remote());
+            assert_eq!(
+                decoded[0].header.frame_data_length,
+                frame.header.frame_data_length
+            );
+        }
+        assert_eq!(decoder.stats().corrupt_packets, 0);
+        assert_eq!(decoder.stats().dropped_bytes, 0);
+    }
+
+    #[test]
+    fn fixed_packets_are_checked() {
+        let mut decoder = Decoder::new();
+        let mut corrupt = fixed_packet(0x351, &[1, 2, 3]);
+        corrupt[12] ^= 0xFF;
+        let input = [corrupt, fixed_packet(0x355, &[0x55, 0x00, 0x64, 0x00])].concat();
+        let frames = decode(&mut decoder, &input);
+
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].id, 0x355);
+        assert_eq!(frames[0].data, vec![0x55, 0x00, 0x64, 0x00]);
+        assert!(decoder.stats().corrupt_packets >= 1);
+    }
+
+    #[test]
+    fn garbage_is_skipped() {
+        let mut decoder = Decoder::new();
+        let frame = Frame::new(0x356, FrameType::Standard, Some(vec![1, 2]), 0).unwrap();
+        // Leading garbage, a header followed by an invalid type and a truncated packet
+        let input = [
+            &[0x01, 0x02, 0x03][..],
+            &[PACKET_HEADER, 0x00],
+            &[PACKET_HEADER, 0xC2, 0x56, 0x03, 0x01, 0x02, 0x00],
+            &encode_packet(&frame),
+        ]
+        .concat();
+        let frames = decode(&mut decoder, &input);
+
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].id, 0x356);
+        assert_eq!(frames[0].data, vec![1, 2]);
+        let stats = decoder.stats();
+        assert_eq!(stats.frames, 1);
+        assert!(stats.corrupt_packets >= 2);
+        assert!(stats.dropped_bytes >= 3);
+    }
+}
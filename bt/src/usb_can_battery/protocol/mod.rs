@@ -0,0 +1,119 @@
+use super::dyness::DynessCanProtocol;
+use super::pylontech::PylontechCanProtocol;
+use super::Frame;
+use crate::inverter::bt::InfluxData;
+use futures::stream;
+use influxdb2::models::DataPoint;
+use serde::Serialize;
+
+/// CAN protocol of a battery management system brand.
+pub trait BatteryProtocol: Send + Sync {
+    /// Brand name reported in the API and Influx, e.g. `dyness`.
+    fn name(&self) -> &'static str;
+
+    /// Decode a CAN frame and update the pack and module state, unknown frames are ignored.
+    /// Returns `true` when the frame updated the pack summary.
+    fn decode(&mut self, frame: &Frame) -> bool;
+
+    /// Pack summary, `None` until it has been received.
+    fn summary(&self) -> Option<BatterySummary>;
+
+    /// Pack and module state as JSON.
+    fn to_json(&self) -> Result<String, serde_json::Error>;
+}
+
+/// Build the protocol selected in the configuration.
+pub fn from_name(name: &str) -> Result<Box<dyn BatteryProtocol>, String> {
+    match name.to_lowercase().as_str() {
+        "dyness" => Ok(Box::new(DynessCanProtocol::default())),
+        "pylontech" => Ok(Box::new(PylontechCanProtocol::default())),
+        _ => Err(format!("Unknown battery protocol {}", name)),
+    }
+}
+
+/// State of the whole battery pack, common to every protocol.
+#[derive(Debug, Clone, Serialize)]
+pub struct BatterySummary {
+    pub protocol: &'static str,
+    pub soc: f32,
+    pub soh: Option<f32>,
+    pub voltage: f32,
+    /// Positive while charging.
+    pub amps: f32,
+    pub temp: f32,
+    /// Limits requested by the BMS, when the protocol reports them.
+    pub limits: Option<ChargeLimits>,
+    /// Raw protection and alarm bits, their layout depends on the protocol.
+    pub alarm_flags: Option<u32>,
+    /// Names of the active protections and alarms.
+    pub alarms: Vec<&'static str>,
+}
+
+/// Charge and discharge limits requested by the BMS to the inverter.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ChargeLimits {
+    pub charge_voltage: f32,
+    pub charge_current: f32,
+    pub discharge_current: f32,
+    pub discharge_voltage: Option<f32>,
+}
+
+impl BatterySummary {
+    /// Save the pack summary to InfluxDB.
+    pub async fn save_to_db(&self, influx_data: InfluxData) {
+        let mut point = DataPoint::builder("battery_can_bus")
+            .tag("host", "battery")
+            .tag("protocol", self.protocol)
+            .field("soc", self.soc as f64)
+            .field("voltage", self.voltage as f64)
+            .field("temp", self.temp as f64)
+            .field("amps", self.amps as f64);
+        if let Some(limits) = self.limits {
+            point = point
+                .field("charge_voltage_limit", limits.charge_voltage as f64)
+                .field("charge_current_limit", limits.charge_current as f64)
+                .field("discharge_current_limit", limits.discharge_current as f64);
+            if let Some(discharge_voltage) = limits.discharge_voltage {
+                point = point.field("discharge_voltage_limit", discharge_voltage as f64);
+            }
+        }
+        if let Some(alarm_flags) = self.alarm_flags {
+            point = point
+                .field("alarm_flags", alarm_flags as i64)
+                .field("alarm_count", self.alarms.len() as i64);
+        }
+        let point = point.build();
+        let Ok(point) = point else {
+            return;
+        };
+
+        let client = influx_data.create_client();
+        let result = client
+            .write(influx_data.get_bucket(), stream::iter([point]))
+            .await;
+        if let Err(e) = result {
+            println!("USB CAN Bus Influxdb client error: {}", e);
+        }
+    }
+}
+
+impl std::fmt::Display for BatterySummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} soc: {}% soh: {}%, {}A {}V {}ºC",
+            self.protocol,
+            self.soc,
+            self.soh
+                .map(|soh| soh.to_string())
+                .unwrap_or("?".to_owned()),
+            self.amps,
+            self.voltage,
+            self.temp
+        )?;
+        if !self.alarms.is_empty() {
+            write!(f, " alarms: {}", self.alarms.join(", "))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,251 @@
+use super::protocol::{BatteryProtocol, BatterySummary, ChargeLimits};
+use super::Frame;
+use byteorder::{ByteOrder, LittleEndian};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+const LIMITS_FRAME_ID: u32 = 0x351;
+const STATE_OF_CHARGE_FRAME_ID: u32 = 0x355;
+const PACK_FRAME_ID: u32 = 0x356;
+const ALARMS_FRAME_ID: u32 = 0x359;
+const REQUESTS_FRAME_ID: u32 = 0x35C;
+const MANUFACTURER_FRAME_ID: u32 = 0x35E;
+
+/// Names of the protection bits (bytes 0 and 1 of 0x359), the packs stop charging or
+/// discharging while one is set.
+const PROTECTION_BITS: [(usize, &str); 7] = [
+    (1, "protection_over_voltage"),
+    (2, "protection_under_voltage"),
+    (3, "protection_over_temperature"),
+    (4, "protection_under_temperature"),
+    (7, "protection_discharge_over_current"),
+    (8, "protection_charge_over_current"),
+    (11, "protection_system_error"),
+];
+
+/// Names of the alarm bits (bytes 2 and 3 of 0x359), warnings before a protection trips.
+const ALARM_BITS: [(usize, &str); 7] = [
+    (1, "alarm_high_voltage"),
+    (2, "alarm_low_voltage"),
+    (3, "alarm_high_temperature"),
+    (4, "alarm_low_temperature"),
+    (7, "alarm_discharge_high_current"),
+    (8, "alarm_charge_high_current"),
+    (11, "alarm_internal_communication_fail"),
+];
+
+/// Charge and discharge requests of the pack (0x35C).
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct PylontechRequests {
+    pub charge_enable: bool,
+    pub discharge_enable: bool,
+    pub force_charge_1: bool,
+    pub force_charge_2: bool,
+    pub full_charge: bool,
+}
+
+/// Pylontech compatible inverter CAN protocol, broadcast by most lithium packs (Dyness
+/// included when linked to an inverter): limits, state of charge, pack readings, alarms,
+/// requests and manufacturer name, one frame each.
+#[derive(Debug, Default, Serialize)]
+pub struct PylontechCanProtocol {
+    limits: Option<ChargeLimits>,
+    soc: Option<u16>,
+    soh: Option<u16>,
+    voltage: Option<f32>,
+    amps: Option<f32>,
+    temp: Option<f32>,
+    /// Protection bits in the low 16 bits, alarm bits in the high 16 bits.
+    alarm_flags: Option<u32>,
+    alarms: Vec<&'static str>,
+    module_count: Option<u8>,
+    requests: Option<PylontechRequests>,
+    manufacturer: Option<String>,
+    last_update: Option<DateTime<Utc>>,
+}
+
+impl PylontechCanProtocol {
+    /// Active protection and alarm names of the 0x359 flags.
+    fn alarm_names(alarm_flags: u32) -> Vec<&'static str> {
+        let protection = alarm_flags & 0xFFFF;
+        let alarm = alarm_flags >> 16;
+        PROTECTION_BITS
+            .iter()
+            .filter(|(bit, _)| protection & (1 << bit) != 0)
+            .chain(ALARM_BITS.iter().filter(|(bit, _)| alarm & (1 << bit) != 0))
+            .map(|(_, name)| *name)
+            .collect()
+    }
+}
+
+impl BatteryProtocol for PylontechCanProtocol {
+    fn name(&self) -> &'static str {
+        "pylontech"
+    }
+
+    fn decode(&mut self, frame: &Frame) -> bool {
+        let data = &frame.data;
+        let min_length = match frame.id {
+            LIMITS_FRAME_ID => 6,
+            STATE_OF_CHARGE_FRAME_ID => 4,
+            PACK_FRAME_ID => 6,
+            ALARMS_FRAME_ID => 5,
+            REQUESTS_FRAME_ID => 1,
+            MANUFACTURER_FRAME_ID => 0,
+            _ => return false,
+        };
+        if data.len() < min_length {
+            return false;
+        }
+        self.last_update = Some(Utc::now());
+
+        match frame.id {
+            LIMITS_FRAME_ID => {
+                self.limits = Some(ChargeLimits {
+                    charge_voltage: LittleEndian::read_u16(&data[0..2]) as f32 * 0.1,
+                    charge_current: LittleEndian::read_i16(&data[2..4]) as f32 * 0.1,
+                    discharge_current: LittleEndian::read_i16(&data[4..6]) as f32 * 0.1,
+                    // Not sent by every pack
+                    discharge_voltage: data
+                        .get(6..8)
+                        .map(|bytes| LittleEndian::read_u16(bytes) as f32 * 0.1)
+                        .filter(|voltage| *voltage > 0.0),
+                });
+            }
+            STATE_OF_CHARGE_FRAME_ID => {
+                self.soc = Some(LittleEndian::read_u16(&data[0..2]));
+                self.soh = Some(LittleEndian::read_u16(&data[2..4]));
+                return self.summary().is_some();
+            }
+            PACK_FRAME_ID => {
+                self.voltage = Some(LittleEndian::read_i16(&data[0..2]) as f32 * 0.01);
+                self.amps = Some(LittleEndian::read_i16(&data[2..4]) as f32 * 0.1);
+                self.temp = Some(LittleEndian::read_i16(&data[4..6]) as f32 * 0.1);
+                return self.summary().is_some();
+            }
+            ALARMS_FRAME_ID => {
+                let alarm_flags = LittleEndian::read_u32(&data[0..4]);
+                self.alarm_flags = Some(alarm_flags);
+                self.alarms = PylontechCanProtocol::alarm_names(alarm_flags);
+                self.module_count = Some(data[4]);
+            }
+            REQUESTS_FRAME_ID => {
+                let flags = data[0];
+                self.requests = Some(PylontechRequests {
+                    charge_enable: flags & (1 << 7) != 0,
+                    discharge_enable: flags & (1 << 6) != 0,
+                    force_charge_1: flags & (1 << 5) != 0,
+                    force_charge_2: flags & (1 << 4) != 0,
+                    full_charge: flags & (1 << 3) != 0,
+                });
+            }
+            _ => {
+                let name = String::from_utf8_lossy(data);
+                self.manufacturer = Some(name.trim_end_matches(['\0', ' ']).to_owned());
+            }
+        }
+        false
+    }
+
+    fn summary(&self) -> Option<BatterySummary> {
+        Some(BatterySummary {
+            protocol: self.name(),
+            soc: self.soc? as f32,
+            soh: self.soh.map(|soh| soh as f32),
+            voltage: self.voltage?,
+            amps: self.amps?,
+            temp: self.temp?,
+            limits: self.limits,
+            alarm_flags: self.alarm_flags,
+            alarms: self.alarms.clone(),
+        })
+    }
+
+    fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::usb_can_battery::FrameType;
+
+    fn frame(id: u32, data: &[u8]) -> Frame {
+        Frame::new(id, FrameType::Standard, Some(data.to_vec()), 0).unwrap()
+    }
+
+    #[test]
+    fn alarm_bits_are_named() {
+        assert_eq!(
+            PylontechCanProtocol::alarm_names(0x00080000),
+            vec!["alarm_high_temperature"]
+        );
+        assert_eq!(
+            PylontechCanProtocol::alarm_names(0x0802_0802),
+            vec![
+                "protection_over_voltage",
+                "protection_system_error",
+                "alarm_high_voltage",
+                "alarm_internal_communication_fail"
+            ]
+        );
+        // Reserved bits have no name
+        assert!(PylontechCanProtocol::alarm_names(0x0001_0001).is_empty());
+    }
+
+    #[test]
+    fn frames_are_decoded() {
+        let mut battery = PylontechCanProtocol::default();
+
+        // 56.8V, 50A, 100A, 48V
+        assert!(!battery.decode(&frame(
+            0x351,
+            &[0x38, 0x02, 0xF4, 0x01, 0xE8, 0x03, 0xE0, 0x01]
+        )));
+        // High temperature alarm and over voltage protection, 2 modules
+        assert!(!battery.decode(&frame(0x359, &[0x02, 0x00, 0x08, 0x00, 0x02, 0x50, 0x4E])));
+        // Charge and discharge enabled
+        assert!(!battery.decode(&frame(0x35C, &[0xC0, 0x00])));
+        assert!(!battery.decode(&frame(0x35E, b"PYLON   ")));
+        // The summary needs both the state of charge and the pack readings
+        assert!(!battery.decode(&frame(0x355, &[0x55, 0x00, 0x64, 0x00])));
+        assert!(battery.summary().is_none());
+        // 52.4V, -12.5A, 23.1ºC
+        assert!(battery.decode(&frame(0x356, &[0x78, 0x14, 0x83, 0xFF, 0xE7, 0x00])));
+        assert!(!battery.decode(&frame(0x123, &[0x00])));
+
+        let summary = battery.summary().unwrap();
+        assert_eq!(summary.protocol, "pylontech");
+        assert_eq!(summary.soc, 85.0);
+        assert_eq!(summary.soh, Some(100.0));
+        assert!((summary.voltage - 52.4).abs() < 0.001);
+        assert!((summary.amps + 12.5).abs() < 0.001);
+        assert!((summary.temp - 23.1).abs() < 0.001);
+        let limits = summary.limits.unwrap();
+        assert!((limits.charge_voltage - 56.8).abs() < 0.001);
+        assert!((limits.charge_current - 50.0).abs() < 0.001);
+        assert!((limits.discharge_current - 100.0).abs() < 0.001);
+        assert!((limits.discharge_voltage.unwrap() - 48.0).abs() < 0.001);
+        assert_eq!(summary.alarm_flags, Some(0x00080002));
+        assert_eq!(
+            summary.alarms,
+            vec!["protection_over_voltage", "alarm_high_temperature"]
+        );
+
+        assert_eq!(battery.module_count, Some(2));
+        let requests = battery.requests.unwrap();
+        assert!(requests.charge_enable && requests.discharge_enable);
+        assert!(!requests.force_charge_1 && !requests.force_charge_2 && !requests.full_charge);
+        assert_eq!(battery.manufacturer.as_deref(), Some("PYLON"));
+    }
+
+    #[test]
+    fn short_frames_are_ignored() {
+        let mut battery = PylontechCanProtocol::default();
+        assert!(!battery.decode(&frame(0x355, &[0x55, 0x00, 0x64])));
+        assert!(!battery.decode(&frame(0x351, &[0x38, 0x02])));
+        assert!(battery.soc.is_none() && battery.limits.is_none());
+        assert!(battery.last_update.is_none());
+    }
+}
@@ -0,0 +1,281 @@
+use super::{Frame, FrameFormat, FrameHeader, FrameSource, FrameStats, FrameType};
+use serialport::SerialPort;
+use std::io;
+use std::time::Duration;
+
+const LINE_END: u8 = b'\r';
+/// Sent by the adapter instead of `\r` when a command fails.
+const COMMAND_ERROR: u8 = 0x07;
+
+/// Longest valid line: extended frame with 8 data bytes and a timestamp.
+const MAX_LINE_LENGTH: usize = 1 + 8 + 1 + 16 + 4;
+
+const MAX_DATA_LENGTH: usize = 8;
+
+/// Bitrates of the `S0`..`S8` commands.
+const BITRATES: [u32; 9] = [
+    10_000, 20_000, 50_000, 100_000, 125_000, 250_000, 500_000, 800_000, 1_000_000,
+];
+
+/// Decoder of the SLCAN (Lawicel) ASCII protocol spoken by CANable and USBtin style adapters:
+/// `tiiildd..` and `Tiiiiiiiildd..` frames (`r`/`R` for remote frames), ended by `\r`.
+pub struct SlcanDecoder {
+    line: Vec<u8>,
+    stats: FrameStats,
+}
+
+impl SlcanDecoder {
+    pub fn new() -> Self {
+        SlcanDecoder {
+            line: Vec::new(),
+            stats: FrameStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> FrameStats {
+        self.stats
+    }
+
+    pub fn append(&mut self, byte: u8) -> Option<Frame> {
+        if byte != LINE_END && byte != COMMAND_ERROR {
+            if self.line.len() < MAX_LINE_LENGTH {
+                self.line.push(byte);
+            } else {
+                self.stats.dropped_bytes += 1;
+            }
+            return None;
+        }
+
+        let line = std::mem::take(&mut self.line);
+        match line.first() {
+            // Command acknowledgements (empty, `z`, `Z`) and version replies carry no frame
+            None | Some(b'z' | b'Z' | b'V' | b'v' | b'N' | b'F') => None,
+            Some(b't' | b'T' | b'r' | b'R') => match SlcanDecoder::decode_line(&line) {
+                Some(frame) => {
+                    self.stats.frames += 1;
+                    Some(frame)
+                }
+                None => {
+                    self.stats.corrupt_packets += 1;
+                    None
+                }
+            },
+            Some(_) => {
+                self.stats.dropped_bytes += line.len() as u64;
+                None
+            }
+        }
+    }
+
+    fn decode_line(line: &[u8]) -> Option<Frame> {
+        // Only ASCII is valid, which also keeps the slicing below on char boundaries
+        if !line.is_ascii() {
+            return None;
+        }
+        let line = std::str::from_utf8(line).ok()?;
+        let (frame_type, id_length) = match &line[..1] {
+            "t" | "r" => (FrameType::Standard, 3),
+            _ => (FrameType::Extended, 8),
+        };
+        let frame_format = match &line[..1] {
+            "t" | "T" => FrameFormat::DataFrame,
+            _ => FrameFormat::RemoteFrame,
+        };
+
+        let id = u32::from_str_radix(line.get(1..1 + id_length)?, 16).ok()?;
+        let frame_data_length =
+            usize::from_str_radix(line.get(1 + id_length..2 + id_length)?, 16).ok()?;
+        let valid_id = match frame_type {
+            FrameType::Standard => id <= 0x7FF,
+            FrameType::Extended => id <= 0x1FFF_FFFF,
+        };
+        if !valid_id || frame_data_length > MAX_DATA_LENGTH {
+            return None;
+        }
+
+        // Remote frames carry no data, an optional 4 digit timestamp may follow
+        let payload = &line[2 + id_length..];
+        let data_digits = match frame_format {
+            FrameFormat::DataFrame => frame_data_length * 2,
+            FrameFormat::RemoteFrame => 0,
+        };
+        if payload.len() != data_digits && payload.len() != data_digits + 4 {
+            return None;
+        }
+        let data = hex::decode(&payload[..data_digits]).ok()?;
+
+        Some(Frame {
+            header: FrameHeader {
+                frame_type,
+                frame_format,
+                frame_data_length,
+            },
+            id,
+            data,
+        })
+    }
+}
+
+/// Encode a frame as an SLCAN transmit command, the reverse of [`SlcanDecoder`].
+pub fn encode_line(frame: &Frame) -> String {
+    let command = match (frame.header.frame_type, frame.is_remote()) {
+        (FrameType::Standard, false) => 't',
+        (FrameType::Standard, true) => 'r',
+        (FrameType::Extended, false) => 'T',
+        (FrameType::Extended, true) => 'R',
+    };
+    let id = match frame.header.frame_type {
+        FrameType::Standard => format!("{:03X}", frame.id),
+        FrameType::Extended => format!("{:08X}", frame.id),
+    };
+    format!(
+        "{}{}{:X}{}\r",
+        command,
+        id,
+        frame.header.frame_data_length,
+        hex::encode_upper(&frame.data)
+    )
+}
+
+/// SLCAN adapter on a serial port, opened on the CAN bus at the given bitrate.
+pub struct SlcanFrameSource {
+    port_name: String,
+    port: Box<dyn SerialPort>,
+    decoder: SlcanDecoder,
+}
+
+impl SlcanFrameSource {
+    pub fn open(
+        port_name: &str,
+        baud_rate: u32,
+        bitrate: u32,
+        timeout: Duration,
+    ) -> io::Result<Self> {
+        let bitrate_index = BITRATES.iter().position(|b| *b == bitrate).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unsupported SLCAN bitrate {}", bitrate),
+            )
+        })?;
+        let mut port = serialport::new(port_name, baud_rate)
+            .timeout(timeout)
+            .open()?;
+
+        // Close the channel first, it may have been left open, then set the bitrate and open it
+        port.write_all(b"\r\r\rC\r")?;
+        port.write_all(format!("S{}\r", bitrate_index).as_bytes())?;
+        port.write_all(b"O\r")?;
+        port.flush()?;
+
+        Ok(SlcanFrameSource {
+            port_name: port_name.to_owned(),
+            port,
+            decoder: SlcanDecoder::new(),
+        })
+    }
+}
+
+impl FrameSource for SlcanFrameSource {
+    fn read_frame(&mut self) -> io::Result<Frame> {
+        let mut byte = [0; 1];
+        loop {
+            self.port.read_exact(&mut byte)?;
+            if let Some(frame) = self.decoder.append(byte[0]) {
+                return Ok(frame);
+            }
+        }
+    }
+
+    fn send_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        self.port.write_all(encode_line(frame).as_bytes())?;
+        self.port.flush()
+    }
+
+    fn stats(&self) -> FrameStats {
+        self.decoder.stats()
+    }
+
+    fn name(&self) -> String {
+        format!("SLCAN adapter {}", self.port_name)
+    }
+}
+
+impl Drop for SlcanFrameSource {
+    fn drop(&mut self) {
+        let _ = self.port.write_all(b"C\r");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(decoder: &mut SlcanDecoder, input: &[u8]) -> Vec<Frame> {
+        input
+            .iter()
+            .filter_map(|byte| decoder.append(*byte))
+            .collect()
+    }
+
+    #[test]
+    fn frames_are_decoded() {
+        let mut decoder = SlcanDecoder::new();
+        let frames = decode(
+            &mut decoder,
+            b"t313813D20037009F5564\rT18FF50E52AABB1234\rr1230\rR18FF50E54\r",
+        );
+
+        assert_eq!(frames.len(), 4);
+        assert_eq!(frames[0].id, 0x313);
+        assert_eq!(frames[0].data, hex::decode("13D20037009F5564").unwrap());
+        assert_eq!(frames[1].id, 0x18FF50E5);
+        assert_eq!(frames[1].header.frame_type, FrameType::Extended);
+        // Followed by a timestamp
+        assert_eq!(frames[1].data, vec![0xAA, 0xBB]);
+        assert!(frames[2].is_remote() && frames[2].data.is_empty());
+        assert!(frames[3].is_remote() && frames[3].header.frame_data_length == 4);
+        assert_eq!(decoder.stats().frames, 4);
+    }
+
+    #[test]
+    fn invalid_lines_are_skipped() {
+        let mut decoder = SlcanDecoder::new();
+        let input = [
+            &b"z\r\x07garbage\rt31381234\rt8001AA\rt1232AAB"[..],
+            "\u{e9}CCC\r".as_bytes(),
+            &[b'T'; 64],
+            b"\rt3132ABCD\r",
+        ]
+        .concat();
+        let frames = decode(&mut decoder, &input);
+
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].data, vec![0xAB, 0xCD]);
+        let stats = decoder.stats();
+        assert_eq!(stats.frames, 1);
+        // Short data, id out of range, non-ASCII and overlong lines
+        assert_eq!(stats.corrupt_packets, 4);
+        assert_eq!(stats.dropped_bytes, 7 + 64 - MAX_LINE_LENGTH as u64);
+    }
+
+    #[test]
+    fn encoded_lines_are_decoded_back() {
+        let frames = [
+            Frame::new(0x313, FrameType::Standard, Some(vec![1, 2, 3]), 0).unwrap(),
+            Frame::new(0x18FF50E5, FrameType::Extended, Some(vec![]), 0).unwrap(),
+            Frame::new(0x7FF, FrameType::Standard, None, 8).unwrap(),
+        ];
+        let mut decoder = SlcanDecoder::new();
+        for frame in frames {
+            let decoded = decode(&mut decoder, encode_line(&frame).as_bytes());
+            assert_eq!(decoded.len(), 1);
+            assert_eq!(decoded[0].id, frame.id);
+            assert_eq!(decoded[0].data, frame.data);
+            assert_eq!(decoded[0].is_remote(), frame.is_remote());
+            assert_eq!(
+                decoded[0].header.frame_data_length,
+                frame.header.frame_data_length
+            );
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use super::{Frame, FrameFormat, FrameHeader, FrameSource, FrameStats, FrameType};
+use std::ffi::CString;
+use std::io;
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::time::Duration;
+
+/// Raw CAN socket bound to a SocketCAN interface (e.g. `can0`, `vcan0`), for native CAN hats
+/// (MCP2515) and gs_usb adapters.
+pub struct SocketCanSource {
+    interface: String,
+    socket: OwnedFd,
+    frames: u64,
+}
+
+impl SocketCanSource {
+    /// Bind to `interface`, reads fail with `TimedOut` when no frame arrives within `timeout`.
+    pub fn open(interface: &str, timeout: Duration) -> io::Result<Self> {
+        let name = CString::new(interface)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Invalid interface name"))?;
+        let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+        if index == 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let fd = unsafe {
+            libc::socket(
+                libc::PF_CAN,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                libc::CAN_RAW,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let mut address: libc::sockaddr_can = unsafe { mem::zeroed() };
+        address.can_family = libc::AF_CAN as libc::sa_family_t;
+        address.can_ifindex = index as libc::c_int;
+        let result = unsafe {
+            libc::bind(
+                socket.as_raw_fd(),
+                &address as *const libc::sockaddr_can as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_can>() as libc::socklen_t,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let timeout = libc::timeval {
+            tv_sec: timeout.as_secs() as libc::time_t,
+            tv_usec: timeout.subsec_micros() as libc::suseconds_t,
+        };
+        let result = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_RCVTIMEO,
+                &timeout as *const libc::timeval as *const libc::c_void,
+                mem::size_of::<libc::timeval>() as libc::socklen_t,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(SocketCanSource {
+            interface: interface.to_owned(),
+            socket,
+            frames: 0,
+        })
+    }
+}
+
+impl FrameSource for SocketCanSource {
+    fn read_frame(&mut self) -> io::Result<Frame> {
+        let mut raw: libc::can_frame = unsafe { mem::zeroed() };
+        let size = mem::size_of::<libc::can_frame>();
+        let read = unsafe {
+            libc::read(
+                self.socket.as_raw_fd(),
+                &mut raw as *mut libc::can_frame as *mut libc::c_void,
+                size,
+            )
+        };
+        if read < 0 {
+            let error = io::Error::last_os_error();
+            return Err(match error.kind() {
+                io::ErrorKind::WouldBlock => io::ErrorKind::TimedOut.into(),
+                _ => error,
+            });
+        }
+        if read as usize != size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Short CAN frame of {} bytes", read),
+            ));
+        }
+
+        self.frames += 1;
+        let extended = raw.can_id & libc::CAN_EFF_FLAG != 0;
+        let remote = raw.can_id & libc::CAN_RTR_FLAG != 0;
+        let length = (raw.can_dlc as usize).min(raw.data.len());
+        Ok(Frame {
+            header: FrameHeader {
+                frame_type: if extended {
+                    FrameType::Extended
+                } else {
+                    FrameType::Standard
+                },
+                frame_format: if remote {
+                    FrameFormat::RemoteFrame
+                } else {
+                    FrameFormat::DataFrame
+                },
+                frame_data_length: length,
+            },
+            id: if extended {
+                raw.can_id & libc::CAN_EFF_MASK
+            } else {
+                raw.can_id & libc::CAN_SFF_MASK
+            },
+            // Remote frames request `length` bytes but carry none, like the serial adapters
+            data: if remote {
+                Vec::new()
+            } else {
+                raw.data[..length].to_vec()
+            },
+        })
+    }
+
+    fn send_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        let mut raw: libc::can_frame = unsafe { mem::zeroed() };
+        raw.can_id = frame.id;
+        if frame.header.frame_type == FrameType::Extended {
+            raw.can_id |= libc::CAN_EFF_FLAG;
+        }
+        if frame.is_remote() {
+            raw.can_id |= libc::CAN_RTR_FLAG;
+        }
+        raw.can_dlc = frame.header.frame_data_length as u8;
+        raw.data[..frame.data.len()].copy_from_slice(&frame.data);
+
+        let size = mem::size_of::<libc::can_frame>();
+        let written = unsafe {
+            libc::write(
+                self.socket.as_raw_fd(),
+                &raw as *const libc::can_frame as *const libc::c_void,
+                size,
+            )
+        };
+        if written < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if written as usize != size {
+            return Err(io::Error::new(
+                io::ErrorKind::WriteZero,
+                format!("Short CAN frame write of {} bytes", written),
+            ));
+        }
+        Ok(())
+    }
+
+    fn stats(&self) -> FrameStats {
+        // The kernel drops corrupt frames before they reach the socket
+        FrameStats {
+            frames: self.frames,
+            ..Default::default()
+        }
+    }
+
+    fn name(&self) -> String {
+        format!("SocketCAN interface {}", self.interface)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERFACE: &str = "vcan0";
+
+    /// Needs a `vcan0` interface (see the readme), run with `cargo test -- --ignored`.
+    #[test]
+    #[ignore]
+    fn frames_go_through_vcan() {
+        let timeout = Duration::from_millis(500);
+        let mut receiver = SocketCanSource::open(INTERFACE, timeout).expect("vcan0 is not up");
+        let mut sender = SocketCanSource::open(INTERFACE, timeout).unwrap();
+
+        let data = vec![0x13, 0xD2, 0x00, 0x37, 0x00, 0x9F, 0x55, 0x64];
+        let frame = Frame::new(0x313, FrameType::Standard, Some(data.clone()), 0).unwrap();
+        sender.send_frame(&frame).unwrap();
+        let received = receiver.read_frame().unwrap();
+        assert_eq!(received.id, 0x313);
+        assert_eq!(received.header.frame_type, FrameType::Standard);
+        assert!(!received.is_remote());
+        assert_eq!(received.data, data);
+
+        let frame = Frame::new(0x18FF50E5, FrameType::Extended, None, 4).unwrap();
+        sender.send_frame(&frame).unwrap();
+        let received = receiver.read_frame().unwrap();
+        assert_eq!(received.id, 0x18FF50E5);
+        assert_eq!(received.header.frame_type, FrameType::Extended);
+        assert!(received.is_remote());
+        assert_eq!(received.header.frame_data_length, 4);
+        assert!(received.data.is_empty());
+
+        let error = receiver.read_frame().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(receiver.stats().frames, 2);
+    }
+}
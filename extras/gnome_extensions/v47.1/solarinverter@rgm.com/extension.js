@@ -216,7 +216,7 @@ const Indicator = GObject.registerClass(
 
         _get_battery_discharge_efficiency_rate() {
             const type = this.inverter_data.p_battery_type;
-            if (type == 3 || type == 5) {
+            if (type == 'PYLON' || type == 'OTHER_LITHIUM') {
                 // Lithium (LiFePO4, LiPo, Li-ion, etc.)
                 return 0.95;
             }
@@ -0,0 +1,110 @@
+#!/usr/bin/env python3
+"""Axpert PI30 inverter simulator on a pseudo terminal.
+
+Usage: pi30_simulator.py [link path]
+
+Prints the pty device (and creates a symlink to it when a path is given) so the
+service can be run with INVERTER_TRANSPORT=pi30 and INVERTER_SERIAL_DEVICE set to it.
+"""
+import os
+import sys
+import tty
+
+QPIGS = ("232.0 50.0 229.9 50.0 0459 0415 009 394 52.70 004 095 0043 0009 286.4 "
+         "52.68 00000 00110110 00 00 00258 010")
+QPIWS = "00000000000000000000000000000000000000"
+QMOD = "L"
+QVFW = "VERFW:00092.70"
+
+# Field index of the settings in the QPIRI response
+QPIRI = ["230.0", "21.7", "230.0", "50.0", "21.7", "5000", "5000", "48.0", "46.0", "42.0",
+         "56.4", "54.0", "2", "30", "060", "0", "2", "1", "9", "01", "0", "0", "54.0", "0",
+         "1", "000"]
+SETTINGS = {
+    "POP": (16, "{:d}"),
+    "PCP": (17, "{:d}"),
+    "PGR": (15, "{:d}"),
+    "PBT": (12, "{:d}"),
+    "MUCHGC": (13, "{:02d}"),
+    "MCHGC": (14, "{:03d}"),
+    "PCVV": (10, "{:.1f}"),
+    "PBFT": (11, "{:.1f}"),
+    "PSDV": (9, "{:.1f}"),
+    "PBCV": (8, "{:.1f}"),
+    "PBDV": (22, "{:.1f}"),
+}
+
+flags = {"enabled": set("axyz"), "disabled": set("bkuv")}
+
+
+def crc(data):
+    value = 0
+    for byte in data:
+        value ^= byte << 8
+        for _ in range(8):
+            value = ((value << 1) ^ 0x1021) if value & 0x8000 else value << 1
+            value &= 0xFFFF
+    result = bytearray(value.to_bytes(2, "big"))
+    for i, byte in enumerate(result):
+        if byte in (0x28, 0x0D, 0x0A):
+            result[i] = byte + 1
+    return bytes(result)
+
+
+def respond(command):
+    if command == "QPIGS":
+        return QPIGS
+    if command == "QPIRI":
+        return " ".join(QPIRI)
+    if command == "QMOD":
+        return QMOD
+    if command == "QPIWS":
+        return QPIWS
+    if command == "QVFW":
+        return QVFW
+    if command == "QFLAG":
+        return "E" + "".join(sorted(flags["enabled"])) + "D" + "".join(sorted(flags["disabled"]))
+    if len(command) == 3 and command[:2] in ("PE", "PD"):
+        letter = command[2]
+        enable = command[1] == "E"
+        flags["enabled" if enable else "disabled"].add(letter)
+        flags["disabled" if enable else "enabled"].discard(letter)
+        return "ACK"
+    for prefix, (index, fmt) in SETTINGS.items():
+        if command.startswith(prefix):
+            try:
+                value = float(command[len(prefix):])
+            except ValueError:
+                return "NAK"
+            QPIRI[index] = fmt.format(int(value) if "d" in fmt else value)
+            return "ACK"
+    return "NAK"
+
+
+def main():
+    master, slave = os.openpty()
+    tty.setraw(slave)
+    path = os.ttyname(slave)
+    if len(sys.argv) > 1:
+        if os.path.lexists(sys.argv[1]):
+            os.remove(sys.argv[1])
+        os.symlink(path, sys.argv[1])
+        path = sys.argv[1]
+    print(f"PI30 simulator listening on {path}", flush=True)
+
+    buffer = b""
+    while True:
+        buffer += os.read(master, 256)
+        while b"\r" in buffer:
+            request, buffer = buffer.split(b"\r", 1)
+            if len(request) < 3 or crc(request[:-2]) != request[-2:]:
+                print(f"Invalid request {request!r}", flush=True)
+                continue
+            command = request[:-2].decode("ascii", "replace")
+            payload = b"(" + respond(command).encode("ascii")
+            print(f"{command} -> {payload.decode()}", flush=True)
+            os.write(master, payload + crc(payload) + b"\r")
+
+
+if __name__ == "__main__":
+    main()